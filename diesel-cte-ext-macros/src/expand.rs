@@ -0,0 +1,106 @@
+//! Code generation for `cte!`, targeting the `diesel_cte_ext` builders.
+//!
+//! Paths start at the crate path parsed from the invocation, which defaults
+//! to `::diesel_cte_ext`.
+
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::{Path, ext::IdentExt};
+
+use crate::parse::{CteInput, Fragments, Materialization};
+
+/// Expand a parsed invocation into a `with_recursive` or `with_cte` call.
+pub(crate) fn expand(input: &CteInput) -> TokenStream {
+    let krate = &input.krate;
+    let name = input.name.unraw().to_string();
+    let columns = input.columns.iter().map(|col| col.name.unraw().to_string());
+    let sql_type = sql_type(input);
+    let seed = fragments(krate, &input.seed, sql_type.as_ref());
+    let body = body(krate, &input.body);
+    let materialization = materialization(krate, input.materialization);
+    let Some(step) = &input.step else {
+        return quote! {
+            #krate::builders::with_cte(
+                #name,
+                &[#(#columns),*],
+                #krate::CteParts::new(
+                    #krate::cte_query!(#seed),
+                    #body,
+                )
+                #materialization,
+            )
+        };
+    };
+    let step_fragments = fragments(krate, &step.fragments, sql_type.as_ref());
+    let union = if step.distinct {
+        quote!(.with_union(#krate::UnionKind::Distinct))
+    } else {
+        TokenStream::new()
+    };
+    quote! {
+        #krate::builders::with_recursive(
+            #name,
+            &[#(#columns),*],
+            #krate::RecursiveParts::new(
+                #krate::seed_query!(#seed),
+                #krate::step_query!(#step_fragments),
+                #body,
+            )
+            #union
+            #materialization,
+        )
+    }
+}
+
+/// Builder call setting a non-default planner hint.
+fn materialization(krate: &Path, hint: Materialization) -> TokenStream {
+    match hint {
+        Materialization::Default => TokenStream::new(),
+        Materialization::Materialized => {
+            quote!(.with_materialization(#krate::Materialization::Materialized))
+        }
+        Materialization::NotMaterialized => {
+            quote!(.with_materialization(#krate::Materialization::NotMaterialized))
+        }
+    }
+}
+
+/// Body query, kept as is when it is a single expression so its row type
+/// stays visible to Diesel.
+fn body(krate: &Path, fragments: &Fragments) -> TokenStream {
+    match fragments.exprs.as_slice() {
+        [single] => quote!(#single),
+        many => quote!(#krate::cte_query!(#(#many),*)),
+    }
+}
+
+/// SQL type of a row when every column declares one.
+fn sql_type(input: &CteInput) -> Option<TokenStream> {
+    if !input.is_typed() {
+        return None;
+    }
+    let types: Vec<_> = input
+        .columns
+        .iter()
+        .filter_map(|col| col.sql_type.as_ref())
+        .collect();
+    Some(match types.as_slice() {
+        [single] => quote!(#single),
+        many => quote!((#(#many),*)),
+    })
+}
+
+/// Fragment expressions, wrapped in a row type check when `sql_type` is set.
+///
+/// Typed invocations hold a single expression, as checked by the parser.
+fn fragments(krate: &Path, fragments: &Fragments, sql_type: Option<&TokenStream>) -> TokenStream {
+    let exprs = &fragments.exprs;
+    sql_type.map_or_else(
+        || quote!(#(#exprs),*),
+        |row| {
+            quote_spanned! {fragments.span=>
+                #(#krate::macros::expect_sql_type::<#row, _>(#exprs)),*
+            }
+        },
+    )
+}
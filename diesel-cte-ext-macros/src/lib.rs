@@ -0,0 +1,152 @@
+//! Procedural macros for `diesel-cte-ext`.
+//!
+//! Use these through the `macros` feature of `diesel-cte-ext`, which
+//! re-exports them; the expansions refer to that crate as `::diesel_cte_ext`
+//! unless an invocation names another path.
+
+use proc_macro::TokenStream;
+
+mod expand;
+mod parse;
+
+/// Build a CTE query from a compact, SQL-like description.
+///
+/// ```text
+/// cte!([crate = PATH;]
+///     recursive NAME(COLUMN, ...) [materialized | not materialized]
+///     = { SEED } union [all] { STEP };
+///     select { BODY })
+/// cte!([crate = PATH;]
+///     NAME(COLUMN, ...) [materialized | not materialized]
+///     = { QUERY };
+///     select { BODY })
+/// ```
+///
+/// Each braced section holds one or more comma-separated Diesel fragments,
+/// passed to `seed_query!`, `step_query!`, and `cte_query!` respectively, so
+/// several fragments render back to back. A body made of one expression is
+/// used as is, so the query can load its rows; a body of several fragments
+/// can only be rendered or executed. The invocation expands to a call to
+/// `builders::with_recursive` or `builders::with_cte`. `union all` keeps
+/// repeated rows and `union` discards them. The column list may be omitted.
+///
+/// Annotating every column with its SQL type, as in `nums(n: Integer)`,
+/// requires the seed and step to be single Diesel queries returning exactly
+/// those columns; a mismatch, including a different number of columns, is a
+/// compile error.
+///
+/// A column list without types is only a list of names: the macro does not
+/// compare its length with the seed or step, even when they are typed Diesel
+/// queries, so a mismatch is reported by the database when the query runs.
+/// Malformed input, repeated column names, and a step on a non-recursive CTE
+/// are reported at the offending tokens.
+///
+/// The expansion refers to `::diesel_cte_ext`. When the crate is renamed in
+/// `Cargo.toml` or only reachable through a re-export, start the invocation
+/// with `crate = PATH;` naming it, for example `crate = ::db::cte_ext;`.
+#[proc_macro]
+pub fn cte(input: TokenStream) -> TokenStream {
+    let parsed = syn::parse_macro_input!(input as parse::CteInput);
+    expand::expand(&parsed).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand::expand, parse::CteInput};
+    use quote::quote;
+
+    fn expand_str(input: proc_macro2::TokenStream) -> String {
+        let parsed: CteInput = syn::parse2(input).expect("valid input");
+        expand(&parsed).to_string().split_whitespace().collect()
+    }
+
+    fn parse_error(input: proc_macro2::TokenStream) -> String {
+        match syn::parse2::<CteInput>(input) {
+            Ok(_) => String::from("no error"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn recursive_input_expands_to_builder_call() {
+        let expanded = expand_str(quote! {
+            recursive nums(n) not materialized = { seed } union { step, key }; select { body }
+        });
+        let expected: String = quote! {
+            ::diesel_cte_ext::builders::with_recursive(
+                "nums",
+                &["n"],
+                ::diesel_cte_ext::RecursiveParts::new(
+                    ::diesel_cte_ext::seed_query!(seed),
+                    ::diesel_cte_ext::step_query!(step, key),
+                    body,
+                )
+                .with_union(::diesel_cte_ext::UnionKind::Distinct)
+                .with_materialization(::diesel_cte_ext::Materialization::NotMaterialized),
+            )
+        }
+        .to_string()
+        .split_whitespace()
+        .collect();
+        assert_eq!(expanded, expected);
+    }
+
+    #[test]
+    fn typed_columns_wrap_seed_and_step_in_type_checks() {
+        let expanded = expand_str(quote! {
+            recursive t(id: Integer, depth: Integer) = { seed } union all { step }; select { body }
+        });
+        assert!(expanded.contains("expect_sql_type::<(Integer,Integer),_>(seed)"));
+        assert!(expanded.contains("expect_sql_type::<(Integer,Integer),_>(step)"));
+        assert!(!expanded.contains("with_union"));
+    }
+
+    #[test]
+    fn plain_input_expands_to_with_cte() {
+        let expanded = expand_str(quote!(totals = { cte }; select { body }));
+        assert!(expanded.starts_with("::diesel_cte_ext::builders::with_cte(\"totals\",&[],"));
+    }
+
+    #[test]
+    fn crate_path_overrides_the_default() {
+        let expanded = expand_str(quote! {
+            crate = ::db::cte_ext; recursive t(n: Integer) = { seed } union { step }; select { body }
+        });
+        assert!(expanded.starts_with("::db::cte_ext::builders::with_recursive(\"t\",&[\"n\"],"));
+        assert!(expanded.contains("::db::cte_ext::macros::expect_sql_type::<Integer,_>(seed)"));
+        assert!(expanded.contains(".with_union(::db::cte_ext::UnionKind::Distinct)"));
+        assert!(!expanded.contains("diesel_cte_ext"));
+    }
+
+    #[test]
+    fn cte_named_crate_is_not_a_crate_path() {
+        let expanded = expand_str(quote!(crate = { cte }; select { body }));
+        assert!(expanded.starts_with("::diesel_cte_ext::builders::with_cte(\"crate\",&[],"));
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        assert!(
+            parse_error(quote!(recursive t(n) = { seed }; select { body }))
+                .contains("needs `union { step }`")
+        );
+        assert!(
+            parse_error(quote!(t(n) = { seed } union all { step }; select { body }))
+                .contains("only a `recursive` CTE")
+        );
+        assert!(
+            parse_error(quote!(t(n, n) = { seed }; select { body }))
+                .contains("duplicate column name `n`")
+        );
+        assert!(
+            parse_error(quote!(t(n: Integer, m) = { seed }; select { body }))
+                .contains("every column or none")
+        );
+        assert!(
+            parse_error(quote!(t(n: Integer) = { a, b }; select { body }))
+                .contains("single seed expression")
+        );
+        assert!(parse_error(quote!(t(n) = {}; select { body })).contains("at least one"));
+        assert!(parse_error(quote!(t(n) = { seed } select { body })).contains("expected `;`"));
+    }
+}
@@ -0,0 +1,245 @@
+//! Parser for the `cte!` input.
+
+use std::collections::BTreeSet;
+
+use proc_macro2::Span;
+use syn::{
+    Error, Expr, Ident, Path, Result, Token, Type, braced,
+    ext::IdentExt,
+    parenthesized,
+    parse::{Parse, ParseStream, discouraged::Speculative},
+    parse_quote,
+    punctuated::Punctuated,
+};
+
+mod kw {
+    syn::custom_keyword!(recursive);
+    syn::custom_keyword!(union);
+    syn::custom_keyword!(all);
+    syn::custom_keyword!(materialized);
+    syn::custom_keyword!(not);
+    syn::custom_keyword!(select);
+}
+
+/// Parsed `cte!` invocation.
+pub(crate) struct CteInput {
+    pub(crate) krate: Path,
+    pub(crate) name: Ident,
+    pub(crate) columns: Vec<Column>,
+    pub(crate) materialization: Materialization,
+    pub(crate) seed: Fragments,
+    pub(crate) step: Option<Step>,
+    pub(crate) body: Fragments,
+}
+
+/// Column name with its optional SQL type.
+pub(crate) struct Column {
+    pub(crate) name: Ident,
+    pub(crate) sql_type: Option<Type>,
+}
+
+/// Planner hint written after the column list.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Materialization {
+    Default,
+    Materialized,
+    NotMaterialized,
+}
+
+/// Step of a recursive CTE and the union operator preceding it.
+pub(crate) struct Step {
+    pub(crate) distinct: bool,
+    pub(crate) fragments: Fragments,
+}
+
+/// Comma-separated fragment expressions written inside braces.
+pub(crate) struct Fragments {
+    pub(crate) span: Span,
+    pub(crate) exprs: Vec<Expr>,
+}
+
+impl Parse for CteInput {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        let krate = parse_crate_path(input);
+        let recursive = input.parse::<Option<kw::recursive>>()?.is_some();
+        let name = Ident::parse_any(input)?;
+        let columns = parse_columns(input)?;
+        let materialization = parse_materialization(input)?;
+        input.parse::<Token![=]>()?;
+        let seed = input.parse::<Fragments>()?;
+        let step = parse_step(input)?;
+        input.parse::<Token![;]>()?;
+        input.parse::<kw::select>()?;
+        let body = input.parse::<Fragments>()?;
+        input.parse::<Option<Token![;]>>()?;
+        let parsed = Self {
+            krate,
+            name,
+            columns,
+            materialization,
+            seed,
+            step,
+            body,
+        };
+        parsed.validate(recursive)?;
+        Ok(parsed)
+    }
+}
+
+impl CteInput {
+    /// Whether every column declares an SQL type.
+    pub(crate) fn is_typed(&self) -> bool {
+        self.columns
+            .first()
+            .is_some_and(|col| col.sql_type.is_some())
+    }
+
+    fn validate(&self, recursive: bool) -> Result<()> {
+        match (&self.step, recursive) {
+            (None, true) => {
+                return Err(Error::new(
+                    self.seed.span,
+                    "a recursive CTE needs `union { step }` or `union all { step }` after its seed",
+                ));
+            }
+            (Some(step), false) => {
+                return Err(Error::new(
+                    step.fragments.span,
+                    "only a `recursive` CTE takes a union step",
+                ));
+            }
+            _ => {}
+        }
+        validate_columns(&self.columns)?;
+        if self.is_typed() {
+            self.seed.ensure_single("seed")?;
+            if let Some(step) = &self.step {
+                step.fragments.ensure_single("step")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Parse for Fragments {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        let content;
+        let brace = braced!(content in input);
+        let exprs: Vec<Expr> = Punctuated::<Expr, Token![,]>::parse_terminated(&content)?
+            .into_iter()
+            .collect();
+        if exprs.is_empty() {
+            return Err(Error::new(
+                brace.span.join(),
+                "expected at least one query fragment",
+            ));
+        }
+        Ok(Self {
+            span: brace.span.join(),
+            exprs,
+        })
+    }
+}
+
+impl Fragments {
+    fn ensure_single(&self, role: &str) -> Result<()> {
+        if self.exprs.len() == 1 {
+            return Ok(());
+        }
+        Err(Error::new(
+            self.span,
+            format!(
+                "column types can only be checked against a single {role} expression; \
+                 remove the types or combine the fragments"
+            ),
+        ))
+    }
+}
+
+impl Parse for Column {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        let name = Ident::parse_any(input)?;
+        let sql_type = if input.parse::<Option<Token![:]>>()?.is_some() {
+            Some(input.parse()?)
+        } else {
+            None
+        };
+        Ok(Self { name, sql_type })
+    }
+}
+
+/// Path of `diesel_cte_ext` given as `crate = path;`, or `::diesel_cte_ext`.
+///
+/// The input is parsed on a fork, so a CTE named `crate`, as in
+/// `crate = { ... }`, is still read as the CTE.
+fn parse_crate_path(input: ParseStream<'_>) -> Path {
+    let fork = input.fork();
+    let parsed = (|| {
+        fork.parse::<Token![crate]>()?;
+        fork.parse::<Token![=]>()?;
+        let path = Path::parse_mod_style(&fork)?;
+        fork.parse::<Token![;]>()?;
+        Ok::<_, Error>(path)
+    })();
+    parsed.map_or_else(
+        |_| parse_quote!(::diesel_cte_ext),
+        |path| {
+            input.advance_to(&fork);
+            path
+        },
+    )
+}
+
+fn parse_columns(input: ParseStream<'_>) -> Result<Vec<Column>> {
+    if !input.peek(syn::token::Paren) {
+        return Ok(Vec::new());
+    }
+    let content;
+    parenthesized!(content in input);
+    Ok(Punctuated::<Column, Token![,]>::parse_terminated(&content)?
+        .into_iter()
+        .collect())
+}
+
+fn parse_materialization(input: ParseStream<'_>) -> Result<Materialization> {
+    if input.parse::<Option<kw::materialized>>()?.is_some() {
+        return Ok(Materialization::Materialized);
+    }
+    if input.parse::<Option<kw::not>>()?.is_some() {
+        input.parse::<kw::materialized>()?;
+        return Ok(Materialization::NotMaterialized);
+    }
+    Ok(Materialization::Default)
+}
+
+fn parse_step(input: ParseStream<'_>) -> Result<Option<Step>> {
+    if input.parse::<Option<kw::union>>()?.is_none() {
+        return Ok(None);
+    }
+    let distinct = input.parse::<Option<kw::all>>()?.is_none();
+    let fragments = input.parse()?;
+    Ok(Some(Step {
+        distinct,
+        fragments,
+    }))
+}
+
+fn validate_columns(columns: &[Column]) -> Result<()> {
+    let typed = columns.first().is_some_and(|col| col.sql_type.is_some());
+    let mut seen = BTreeSet::new();
+    for column in columns {
+        if column.sql_type.is_some() != typed {
+            return Err(Error::new(
+                column.name.span(),
+                "either every column or none must declare an SQL type",
+            ));
+        }
+        if !seen.insert(column.name.unraw().to_string()) {
+            return Err(Error::new(
+                column.name.span(),
+                format!("duplicate column name `{}`", column.name.unraw()),
+            ));
+        }
+    }
+    Ok(())
+}
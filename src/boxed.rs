@@ -0,0 +1,216 @@
+//! Type-erased CTE queries for dynamic composition.
+//!
+//! Every seed, step, and body contributes to the type of a
+//! [`WithRecursive`](crate::cte::WithRecursive) or
+//! [`WithCte`](crate::cte::WithCte), so queries assembled in different
+//! branches have different types. [`BoxedCteQuery`] erases everything but the
+//! backend and the body's SQL type, in the spirit of Diesel's
+//! `BoxedSelectStatement`, and [`BoxedFragment`] does the same for individual
+//! seed and step fragments.
+
+use std::marker::PhantomData;
+
+use diesel::{
+    backend::Backend,
+    query_builder::{AstPass, Query, QueryFragment, QueryId},
+    result::QueryResult,
+};
+
+/// Seed, step, or CTE fragment boxed behind a trait object.
+///
+/// Built by `into_boxed` on [`RecursiveParts`](crate::builders::RecursiveParts)
+/// and [`CteParts`](crate::builders::CteParts). Like [`BoxedCteQuery`], a
+/// boxed fragment has no static query id.
+pub struct BoxedFragment<'a, DB: Backend>(Box<dyn QueryFragment<DB> + Send + 'a>);
+
+impl<'a, DB> BoxedFragment<'a, DB>
+where
+    DB: Backend,
+{
+    pub(crate) fn new<F>(fragment: F) -> Self
+    where
+        F: QueryFragment<DB> + Send + 'a,
+    {
+        Self(Box::new(fragment))
+    }
+}
+
+impl<DB> std::fmt::Debug for BoxedFragment<'_, DB>
+where
+    DB: Backend,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BoxedFragment").finish_non_exhaustive()
+    }
+}
+
+impl<DB> QueryFragment<DB> for BoxedFragment<'_, DB>
+where
+    DB: Backend,
+{
+    fn walk_ast<'b>(&'b self, out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        self.0.walk_ast(out)
+    }
+}
+
+impl<DB> QueryId for BoxedFragment<'_, DB>
+where
+    DB: Backend,
+{
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+/// CTE query boxed behind a trait object.
+///
+/// Built by `into_boxed` on [`WithRecursive`](crate::cte::WithRecursive) or
+/// [`WithCte`](crate::cte::WithCte). `ST` is the SQL type of the body, so the
+/// boxed query loads the same rows as the original.
+pub struct BoxedCteQuery<'a, DB, ST> {
+    query: Box<dyn QueryFragment<DB> + Send + 'a>,
+    _marker: PhantomData<ST>,
+}
+
+impl<'a, DB, ST> BoxedCteQuery<'a, DB, ST>
+where
+    DB: Backend,
+{
+    pub(crate) fn new<Q>(query: Q) -> Self
+    where
+        Q: QueryFragment<DB> + Send + 'a,
+    {
+        Self {
+            query: Box::new(query),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<DB, ST> std::fmt::Debug for BoxedCteQuery<'_, DB, ST> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BoxedCteQuery").finish_non_exhaustive()
+    }
+}
+
+impl<DB, ST> QueryFragment<DB> for BoxedCteQuery<'_, DB, ST>
+where
+    DB: Backend,
+{
+    fn walk_ast<'b>(&'b self, out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        self.query.walk_ast(out)
+    }
+}
+
+impl<DB, ST> QueryId for BoxedCteQuery<'_, DB, ST> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<DB, ST> Query for BoxedCteQuery<'_, DB, ST>
+where
+    DB: Backend,
+{
+    type SqlType = ST;
+}
+
+impl<DB, ST> diesel::QueryDsl for BoxedCteQuery<'_, DB, ST> where DB: Backend {}
+
+impl<DB, ST, Conn> diesel::query_dsl::RunQueryDsl<Conn> for BoxedCteQuery<'_, DB, ST>
+where
+    DB: Backend,
+    Conn: diesel::connection::Connection<Backend = DB>,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{builders, builders::RecursiveParts, test_support::normalise_debug_sql};
+    use diesel::{debug_query, dsl::sql, sql_types::Integer, sqlite::Sqlite};
+
+    fn numbers(limit: Option<i32>) -> BoxedCteQuery<'static, Sqlite, Integer> {
+        let seed = sql::<Integer>("SELECT 1");
+        let body = sql::<Integer>("SELECT n FROM nums");
+        match limit {
+            Some(max) => builders::with_recursive::<Sqlite, _, _, _, _, _>(
+                "nums",
+                &["n"],
+                RecursiveParts::new(
+                    seed,
+                    sql::<Integer>("SELECT n + 1 FROM nums WHERE n < ").bind::<Integer, _>(max),
+                    body,
+                ),
+            )
+            .into_boxed(),
+            None => builders::with_recursive::<Sqlite, _, _, _, _, _>(
+                "nums",
+                &["n"],
+                RecursiveParts::new(seed, sql::<Integer>("SELECT n + 1 FROM nums"), body),
+            )
+            .into_boxed(),
+        }
+    }
+
+    type BoxedParts = RecursiveParts<
+        BoxedFragment<'static, Sqlite>,
+        BoxedFragment<'static, Sqlite>,
+        diesel::expression::SqlLiteral<Integer>,
+    >;
+
+    fn parts(limit: Option<i32>) -> BoxedParts {
+        let seed = sql::<Integer>("SELECT 1");
+        let body = sql::<Integer>("SELECT n FROM nums");
+        match limit {
+            Some(max) => RecursiveParts::new(
+                seed,
+                sql::<Integer>("SELECT n + 1 FROM nums WHERE n < ").bind::<Integer, _>(max),
+                body,
+            )
+            .into_boxed(),
+            None => RecursiveParts::new(seed, sql::<Integer>("SELECT n + 1 FROM nums"), body)
+                .into_boxed(),
+        }
+    }
+
+    #[test]
+    fn boxed_queries_share_a_type_across_branches() {
+        let limited = normalise_debug_sql(&debug_query::<Sqlite, _>(&numbers(Some(3))).to_string());
+        let unlimited = normalise_debug_sql(&debug_query::<Sqlite, _>(&numbers(None)).to_string());
+        assert_eq!(
+            limited,
+            "WITH RECURSIVE \"nums\" (\"n\") AS (SELECT 1 UNION ALL SELECT n + 1 FROM nums WHERE n < ?) SELECT n FROM nums"
+        );
+        assert_eq!(
+            unlimited,
+            "WITH RECURSIVE \"nums\" (\"n\") AS (SELECT 1 UNION ALL SELECT n + 1 FROM nums) SELECT n FROM nums"
+        );
+    }
+
+    #[test]
+    fn boxed_parts_share_a_type_across_branches() {
+        let query =
+            builders::with_recursive::<Sqlite, _, _, _, _, _>("nums", &["n"], parts(Some(3)));
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert_eq!(
+            sql,
+            "WITH RECURSIVE \"nums\" (\"n\") AS (SELECT 1 UNION ALL SELECT n + 1 FROM nums WHERE n < ?) SELECT n FROM nums"
+        );
+        let unlimited =
+            builders::with_recursive::<Sqlite, _, _, _, _, _>("nums", &["n"], parts(None));
+        assert!(
+            normalise_debug_sql(&debug_query::<Sqlite, _>(&unlimited).to_string())
+                .ends_with("FROM nums) SELECT n FROM nums")
+        );
+    }
+
+    #[test]
+    fn boxed_queries_nest_as_cte_queries() {
+        let query = builders::with_cte::<Sqlite, _, _, _, _>(
+            "picked",
+            &["n"],
+            builders::CteParts::new(numbers(Some(3)), sql::<Integer>("SELECT n FROM picked")),
+        );
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert!(sql.starts_with("WITH \"picked\" (\"n\") AS (WITH RECURSIVE \"nums\""));
+    }
+}
@@ -0,0 +1,816 @@
+//! Breadcrumb paths over adjacency-list trees.
+//!
+//! [`Breadcrumbs`] pairs a [`Hierarchy`] with the column labelling each row
+//! and builds [`WithRecursive`] queries returning, for every node, the labels
+//! from the root down to the node joined by a separator, such as
+//! `Root > Electronics > Phones`, and the keys along the same path. Labels
+//! are cast to `TEXT` and joined with the `||` operator, which `SQLite` and
+//! `PostgreSQL` render the same way; other backends report a query builder
+//! error. [`BreadcrumbBackend`] chooses how the keys are stored: `PostgreSQL`
+//! collects them in an array of the key type, such as `{1,2,5}`, while
+//! `SQLite` casts them to `TEXT` and joins them with `/`, such as `1/2/5`.
+
+use std::{borrow::Cow, marker::PhantomData};
+
+use diesel::{
+    Expression, ExpressionMethods, Table,
+    backend::Backend,
+    dsl,
+    expression::{AsExpression, TypedExpressionType},
+    query_builder::{AstPass, Query, QueryFragment, QueryId},
+    query_source::Column,
+    result::{Error, QueryResult},
+    sql_types::{SingleValue, SqlType, Text},
+};
+
+use crate::{
+    builders::{self, RecursiveParts},
+    cte::{Qualified, RecursiveBackend, WithRecursive},
+    depth::DepthLimit,
+    hierarchy::{Direction, Hierarchy},
+};
+
+/// Name of the CTE built by [`Breadcrumbs`] queries.
+const CTE_NAME: &str = "breadcrumbs";
+
+/// Columns of the CTE built by [`Breadcrumbs`] queries.
+const CTE_COLUMNS: &[&str] = &["node", "id", "parent", "path", "ancestors"];
+
+/// Columns of the CTE built by [`Breadcrumbs`] queries with a maximum depth.
+const DEPTH_CTE_COLUMNS: &[&str] = &["node", "id", "parent", "path", "ancestors", "depth"];
+
+/// Node whose breadcrumb a CTE row builds.
+const NODE: Qualified = Qualified(CTE_NAME, "node");
+
+/// Row reached by the walk so far.
+const ID: Qualified = Qualified(CTE_NAME, "id");
+
+/// Parent of the row reached by the walk so far.
+const PARENT: Qualified = Qualified(CTE_NAME, "parent");
+
+/// Labels joined so far.
+const PATH: Qualified = Qualified(CTE_NAME, "path");
+
+/// Keys collected so far.
+const ANCESTORS: Qualified = Qualified(CTE_NAME, "ancestors");
+
+/// Separator placed between labels unless
+/// [`Breadcrumbs::with_separator`] replaces it.
+const DEFAULT_SEPARATOR: &str = " > ";
+
+/// Separator placed between keys on backends storing them as text.
+#[cfg(feature = "sqlite")]
+const KEY_SEPARATOR: &str = "/";
+
+/// Query returned by [`Breadcrumbs::all`] and [`Breadcrumbs::of`].
+///
+/// `Cond` selects the rows the walk starts from.
+pub type BreadcrumbQuery<DB, Tbl, Id, Parent, Label, Cond> = WithRecursive<
+    DB,
+    (),
+    BreadcrumbSeed<Tbl, Id, Parent, Label, Cond>,
+    BreadcrumbStep<Tbl, Id, Parent, Label>,
+    BreadcrumbBody<DB, <Id as Expression>::SqlType>,
+>;
+
+/// Backend able to collect the keys along a breadcrumb.
+///
+/// `Keys<ST>` is the SQL type of the `ancestors` column returned by
+/// [`Breadcrumbs`] queries whose keys have type `ST`. `PostgreSQL` uses
+/// `Array<ST>` and `SQLite` uses `Text`, holding the keys joined by `/`.
+/// `MySQL` declares `Text` but keeps the default methods, so its breadcrumbs
+/// fail to render. A backend enum spanning several of them can do the same.
+pub trait BreadcrumbBackend: RecursiveBackend {
+    /// SQL type of the keys along a breadcrumb.
+    type Keys<ST: 'static>: SqlType + SingleValue;
+
+    /// Render the keys of a breadcrumb holding `key` alone.
+    ///
+    /// # Errors
+    ///
+    /// The default implementation returns a query builder error because the
+    /// backend has no representation for the keys.
+    fn walk_first_key<'b, Key>(key: &'b Key, out: &mut AstPass<'_, 'b, Self>) -> QueryResult<()>
+    where
+        Key: QueryFragment<Self>,
+    {
+        let _ = (key, out);
+        Err(Error::QueryBuilderError(
+            "breadcrumb keys are not supported by this backend".into(),
+        ))
+    }
+
+    /// Render `keys` followed by `key`.
+    ///
+    /// # Errors
+    ///
+    /// The default implementation returns a query builder error because the
+    /// backend has no representation for the keys.
+    fn walk_append_key<'b, Keys, Key>(
+        keys: &'b Keys,
+        key: &'b Key,
+        out: &mut AstPass<'_, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Keys: QueryFragment<Self>,
+        Key: QueryFragment<Self>,
+    {
+        let _ = (keys, key, out);
+        Err(Error::QueryBuilderError(
+            "breadcrumb keys are not supported by this backend".into(),
+        ))
+    }
+
+    /// Render `key` followed by `keys`.
+    ///
+    /// # Errors
+    ///
+    /// The default implementation returns a query builder error because the
+    /// backend has no representation for the keys.
+    fn walk_prepend_key<'b, Key, Keys>(
+        key: &'b Key,
+        keys: &'b Keys,
+        out: &mut AstPass<'_, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Key: QueryFragment<Self>,
+        Keys: QueryFragment<Self>,
+    {
+        let _ = (key, keys, out);
+        Err(Error::QueryBuilderError(
+            "breadcrumb keys are not supported by this backend".into(),
+        ))
+    }
+
+    /// Render a condition holding when `key` is not among `keys`.
+    ///
+    /// # Errors
+    ///
+    /// The default implementation returns a query builder error because the
+    /// backend has no representation for the keys.
+    fn walk_key_unvisited<'b, Keys, Key>(
+        keys: &'b Keys,
+        key: &'b Key,
+        out: &mut AstPass<'_, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Keys: QueryFragment<Self>,
+        Key: QueryFragment<Self>,
+    {
+        let _ = (keys, key, out);
+        Err(Error::QueryBuilderError(
+            "breadcrumb keys are not supported by this backend".into(),
+        ))
+    }
+}
+
+/// Keys cast to `TEXT` and joined by `/`.
+#[cfg(feature = "sqlite")]
+impl BreadcrumbBackend for diesel::sqlite::Sqlite {
+    type Keys<ST: 'static> = Text;
+
+    fn walk_first_key<'b, Key>(key: &'b Key, out: &mut AstPass<'_, 'b, Self>) -> QueryResult<()>
+    where
+        Key: QueryFragment<Self>,
+    {
+        walk_as_text(key, out)
+    }
+
+    fn walk_append_key<'b, Keys, Key>(
+        keys: &'b Keys,
+        key: &'b Key,
+        out: &mut AstPass<'_, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Keys: QueryFragment<Self>,
+        Key: QueryFragment<Self>,
+    {
+        keys.walk_ast(out.reborrow())?;
+        out.push_sql(" || '");
+        out.push_sql(KEY_SEPARATOR);
+        out.push_sql("' || ");
+        walk_as_text(key, out)
+    }
+
+    fn walk_prepend_key<'b, Key, Keys>(
+        key: &'b Key,
+        keys: &'b Keys,
+        out: &mut AstPass<'_, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Key: QueryFragment<Self>,
+        Keys: QueryFragment<Self>,
+    {
+        walk_as_text(key, out)?;
+        out.push_sql(" || '");
+        out.push_sql(KEY_SEPARATOR);
+        out.push_sql("' || ");
+        keys.walk_ast(out.reborrow())
+    }
+
+    fn walk_key_unvisited<'b, Keys, Key>(
+        keys: &'b Keys,
+        key: &'b Key,
+        out: &mut AstPass<'_, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Keys: QueryFragment<Self>,
+        Key: QueryFragment<Self>,
+    {
+        Self::walk_path_check(
+            false,
+            |pass| {
+                pass.push_sql("'");
+                pass.push_sql(KEY_SEPARATOR);
+                pass.push_sql("' || ");
+                keys.walk_ast(pass.reborrow())?;
+                pass.push_sql(" || '");
+                pass.push_sql(KEY_SEPARATOR);
+                pass.push_sql("'");
+                Ok(())
+            },
+            |pass| walk_as_text(key, pass),
+            out,
+        )
+    }
+}
+
+/// Keys collected in an array, extended with `||`.
+#[cfg(feature = "postgres")]
+impl BreadcrumbBackend for diesel::pg::Pg {
+    type Keys<ST: 'static> = diesel::sql_types::Array<ST>;
+
+    fn walk_first_key<'b, Key>(key: &'b Key, out: &mut AstPass<'_, 'b, Self>) -> QueryResult<()>
+    where
+        Key: QueryFragment<Self>,
+    {
+        out.push_sql("ARRAY[");
+        key.walk_ast(out.reborrow())?;
+        out.push_sql("]");
+        Ok(())
+    }
+
+    fn walk_append_key<'b, Keys, Key>(
+        keys: &'b Keys,
+        key: &'b Key,
+        out: &mut AstPass<'_, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Keys: QueryFragment<Self>,
+        Key: QueryFragment<Self>,
+    {
+        keys.walk_ast(out.reborrow())?;
+        out.push_sql(" || ");
+        key.walk_ast(out.reborrow())
+    }
+
+    fn walk_prepend_key<'b, Key, Keys>(
+        key: &'b Key,
+        keys: &'b Keys,
+        out: &mut AstPass<'_, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Key: QueryFragment<Self>,
+        Keys: QueryFragment<Self>,
+    {
+        key.walk_ast(out.reborrow())?;
+        out.push_sql(" || ");
+        keys.walk_ast(out.reborrow())
+    }
+
+    fn walk_key_unvisited<'b, Keys, Key>(
+        keys: &'b Keys,
+        key: &'b Key,
+        out: &mut AstPass<'_, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Keys: QueryFragment<Self>,
+        Key: QueryFragment<Self>,
+    {
+        out.push_sql("NOT (");
+        key.walk_ast(out.reborrow())?;
+        out.push_sql(" = ANY(");
+        keys.walk_ast(out.reborrow())?;
+        out.push_sql("))");
+        Ok(())
+    }
+}
+
+/// No portable representation; breadcrumbs fail to render.
+#[cfg(feature = "mysql")]
+impl BreadcrumbBackend for diesel::mysql::Mysql {
+    type Keys<ST: 'static> = Text;
+}
+
+/// Breadcrumb paths of a [`Hierarchy`], labelled by one of its columns.
+///
+/// Created by [`Hierarchy::breadcrumbs`]. Root rows are those whose parent
+/// is `NULL`. A `NULL` label makes the whole path `NULL`. A maximum depth set
+/// with [`Hierarchy::with_max_depth`] limits how many levels the walk
+/// crosses.
+#[derive(Debug, Clone)]
+pub struct Breadcrumbs<Tbl, Id, Parent, Label> {
+    hierarchy: Hierarchy<Tbl, Id, Parent>,
+    label: Label,
+    separator: Cow<'static, str>,
+}
+
+impl<Tbl, Id, Parent, Label> Breadcrumbs<Tbl, Id, Parent, Label>
+where
+    Tbl: Table + Copy,
+    Id: Column<Table = Tbl> + Copy,
+    Parent: Column<Table = Tbl> + Copy,
+    Label: Column<Table = Tbl> + Copy,
+    Id::SqlType: SqlType + TypedExpressionType,
+{
+    pub(crate) const fn new(hierarchy: Hierarchy<Tbl, Id, Parent>, label: Label) -> Self {
+        Self {
+            hierarchy,
+            label,
+            separator: Cow::Borrowed(DEFAULT_SEPARATOR),
+        }
+    }
+
+    /// Join labels with `separator` instead of `" > "`.
+    ///
+    /// The separator may be a static string or built at runtime.
+    #[must_use]
+    pub fn with_separator(mut self, separator: impl Into<Cow<'static, str>>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    /// Breadcrumb of every node reachable from a root, ordered by key.
+    ///
+    /// Each row holds the node's key, its path of labels, and the keys from
+    /// the root down to the node, stored as [`BreadcrumbBackend::Keys`].
+    /// With a maximum depth, only nodes at most that many levels below a root
+    /// are returned.
+    pub fn all<DB>(&self) -> BreadcrumbQuery<DB, Tbl, Id, Parent, Label, dsl::IsNull<Parent>>
+    where
+        DB: BreadcrumbBackend,
+        Parent::SqlType: SingleValue,
+        Tbl: QueryFragment<DB>,
+        Id: QueryFragment<DB>,
+        Parent: QueryFragment<DB>,
+        Label: QueryFragment<DB>,
+        dsl::IsNull<Parent>: QueryFragment<DB>,
+    {
+        self.walk(Direction::Descendants, self.hierarchy.parent.is_null())
+    }
+
+    /// Breadcrumb of `node` alone, bound as a query parameter.
+    ///
+    /// The tree is walked upwards from `node`, so only its ancestors are
+    /// read. No row is returned when `node` does not exist or its ancestors
+    /// do not lead to a root. The walk stops at a key it has already passed,
+    /// so a loop in the parent links returns no row rather than recursing
+    /// forever. With a maximum depth, no row is returned either when the root
+    /// is more than that many levels above `node`.
+    pub fn of<DB, Node>(
+        &self,
+        node: Node,
+    ) -> BreadcrumbQuery<DB, Tbl, Id, Parent, Label, dsl::Eq<Id, Node>>
+    where
+        DB: BreadcrumbBackend,
+        Node: AsExpression<Id::SqlType>,
+        Id::SqlType: SingleValue,
+        Tbl: QueryFragment<DB>,
+        Id: QueryFragment<DB>,
+        Parent: QueryFragment<DB>,
+        Label: QueryFragment<DB>,
+        dsl::Eq<Id, Node>: QueryFragment<DB>,
+    {
+        self.walk(Direction::Ancestors, self.hierarchy.id.eq(node))
+    }
+
+    fn walk<DB, Cond>(
+        &self,
+        direction: Direction,
+        condition: Cond,
+    ) -> BreadcrumbQuery<DB, Tbl, Id, Parent, Label, Cond>
+    where
+        DB: BreadcrumbBackend,
+        Tbl: QueryFragment<DB>,
+        Id: QueryFragment<DB>,
+        Parent: QueryFragment<DB>,
+        Label: QueryFragment<DB>,
+        Cond: QueryFragment<DB>,
+    {
+        let Hierarchy {
+            table,
+            id,
+            parent,
+            max_depth,
+        } = self.hierarchy;
+        let columns = if max_depth.is_some() {
+            DEPTH_CTE_COLUMNS
+        } else {
+            CTE_COLUMNS
+        };
+        builders::with_recursive(
+            CTE_NAME,
+            columns,
+            RecursiveParts::new(
+                BreadcrumbSeed {
+                    hierarchy: self.hierarchy,
+                    label: self.label,
+                    condition,
+                },
+                BreadcrumbStep {
+                    table,
+                    id,
+                    parent,
+                    label: AsText(self.label),
+                    separator: self.separator.clone(),
+                    max_depth: max_depth.map(|max| DepthLimit::new("depth", max)),
+                    direction,
+                },
+                BreadcrumbBody {
+                    direction,
+                    _marker: PhantomData,
+                },
+            ),
+        )
+    }
+}
+
+/// Expression cast to text, rendered as `CAST(<expr> AS TEXT)`.
+#[derive(Debug, Clone, Copy)]
+struct AsText<Expr>(Expr);
+
+impl<DB, Expr> QueryFragment<DB> for AsText<Expr>
+where
+    DB: Backend,
+    Expr: QueryFragment<DB>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        walk_as_text(&self.0, &mut out)
+    }
+}
+
+/// Render `CAST(<expr> AS TEXT)`.
+fn walk_as_text<'b, DB, Expr>(expr: &'b Expr, out: &mut AstPass<'_, 'b, DB>) -> QueryResult<()>
+where
+    DB: Backend,
+    Expr: QueryFragment<DB>,
+{
+    out.push_sql("CAST(");
+    expr.walk_ast(out.reborrow())?;
+    out.push_sql(" AS TEXT)");
+    Ok(())
+}
+
+/// Seed selecting the rows the walk starts from, each labelled by itself.
+#[derive(Debug, Clone, Copy)]
+pub struct BreadcrumbSeed<Tbl, Id, Parent, Label, Cond> {
+    hierarchy: Hierarchy<Tbl, Id, Parent>,
+    label: Label,
+    condition: Cond,
+}
+
+impl<Tbl, Id, Parent, Label, Cond> QueryId for BreadcrumbSeed<Tbl, Id, Parent, Label, Cond> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<DB, Tbl, Id, Parent, Label, Cond> QueryFragment<DB>
+    for BreadcrumbSeed<Tbl, Id, Parent, Label, Cond>
+where
+    DB: BreadcrumbBackend,
+    Tbl: QueryFragment<DB>,
+    Id: QueryFragment<DB>,
+    Parent: QueryFragment<DB>,
+    Label: QueryFragment<DB>,
+    Cond: QueryFragment<DB>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        let Hierarchy {
+            table,
+            id,
+            parent,
+            max_depth,
+        } = &self.hierarchy;
+        out.push_sql("SELECT ");
+        id.walk_ast(out.reborrow())?;
+        out.push_sql(", ");
+        id.walk_ast(out.reborrow())?;
+        out.push_sql(", ");
+        parent.walk_ast(out.reborrow())?;
+        out.push_sql(", ");
+        walk_as_text(&self.label, &mut out)?;
+        out.push_sql(", ");
+        DB::walk_first_key(id, &mut out)?;
+        if max_depth.is_some() {
+            out.push_sql(", 0");
+        }
+        out.push_sql(" FROM ");
+        table.walk_ast(out.reborrow())?;
+        out.push_sql(" WHERE ");
+        self.condition.walk_ast(out.reborrow())
+    }
+}
+
+/// Step moving one level down from the roots or up towards them, appending
+/// or prepending the label and key of the row it reaches.
+///
+/// Walks towards the roots stop at a row whose key is already on the path, so
+/// a loop in the parent links ends the walk instead of repeating forever.
+/// Walks down from the roots cannot enter a loop, since no row on one leads
+/// back to a root. With a maximum depth, the step also counts the levels it
+/// crosses and stops at the maximum.
+#[derive(Debug, Clone)]
+pub struct BreadcrumbStep<Tbl, Id, Parent, Label> {
+    table: Tbl,
+    id: Id,
+    parent: Parent,
+    label: AsText<Label>,
+    separator: Cow<'static, str>,
+    max_depth: Option<DepthLimit>,
+    direction: Direction,
+}
+
+impl<Tbl, Id, Parent, Label> QueryId for BreadcrumbStep<Tbl, Id, Parent, Label> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<DB, Tbl, Id, Parent, Label> QueryFragment<DB> for BreadcrumbStep<Tbl, Id, Parent, Label>
+where
+    DB: BreadcrumbBackend,
+    Tbl: QueryFragment<DB>,
+    Id: QueryFragment<DB>,
+    Parent: QueryFragment<DB>,
+    Label: QueryFragment<DB>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        out.push_sql("SELECT ");
+        match self.direction {
+            Direction::Descendants => self.id.walk_ast(out.reborrow())?,
+            Direction::Ancestors => NODE.walk_ast(out.reborrow())?,
+        }
+        out.push_sql(", ");
+        self.id.walk_ast(out.reborrow())?;
+        out.push_sql(", ");
+        self.parent.walk_ast(out.reborrow())?;
+        out.push_sql(", ");
+        match self.direction {
+            Direction::Descendants => {
+                DB::walk_concat(&PATH, &self.separator, &self.label, &mut out)?;
+                out.push_sql(", ");
+                DB::walk_append_key(&ANCESTORS, &self.id, &mut out)?;
+            }
+            Direction::Ancestors => {
+                DB::walk_concat(&self.label, &self.separator, &PATH, &mut out)?;
+                out.push_sql(", ");
+                DB::walk_prepend_key(&self.id, &ANCESTORS, &mut out)?;
+            }
+        }
+        if let Some(limit) = &self.max_depth {
+            out.push_sql(", ");
+            limit.walk_counter(Some(CTE_NAME), &mut out)?;
+        }
+        out.push_sql(" FROM ");
+        self.table.walk_ast(out.reborrow())?;
+        out.push_sql(" INNER JOIN ");
+        out.push_identifier(CTE_NAME)?;
+        out.push_sql(" ON ");
+        match self.direction {
+            Direction::Descendants => {
+                self.parent.walk_ast(out.reborrow())?;
+                out.push_sql(" = ");
+                ID.walk_ast(out.reborrow())?;
+                if let Some(limit) = &self.max_depth {
+                    out.push_sql(" WHERE ");
+                    limit.walk_guard(CTE_NAME, &mut out)?;
+                }
+            }
+            Direction::Ancestors => {
+                self.id.walk_ast(out.reborrow())?;
+                out.push_sql(" = ");
+                PARENT.walk_ast(out.reborrow())?;
+                out.push_sql(" WHERE ");
+                DB::walk_key_unvisited(&ANCESTORS, &self.id, &mut out)?;
+                if let Some(limit) = &self.max_depth {
+                    out.push_sql(" AND ");
+                    limit.walk_guard(CTE_NAME, &mut out)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Body returning each node's key, path of labels, and path of keys.
+///
+/// Walks towards the roots only return the row that reached one.
+#[derive(Debug, Clone, Copy)]
+pub struct BreadcrumbBody<DB, ST> {
+    direction: Direction,
+    _marker: PhantomData<(DB, ST)>,
+}
+
+impl<DB, ST> QueryFragment<DB> for BreadcrumbBody<DB, ST>
+where
+    DB: Backend,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        out.push_sql("SELECT ");
+        NODE.walk_ast(out.reborrow())?;
+        out.push_sql(", ");
+        PATH.walk_ast(out.reborrow())?;
+        out.push_sql(", ");
+        ANCESTORS.walk_ast(out.reborrow())?;
+        out.push_sql(" FROM ");
+        out.push_identifier(CTE_NAME)?;
+        if self.direction == Direction::Ancestors {
+            out.push_sql(" WHERE ");
+            PARENT.walk_ast(out.reborrow())?;
+            out.push_sql(" IS NULL");
+        }
+        out.push_sql(" ORDER BY ");
+        NODE.walk_ast(out.reborrow())
+    }
+}
+
+impl<DB, ST> Query for BreadcrumbBody<DB, ST>
+where
+    DB: BreadcrumbBackend,
+    ST: SqlType + TypedExpressionType + 'static,
+{
+    type SqlType = (ST, Text, DB::Keys<ST>);
+}
+
+impl<DB, ST> QueryId for BreadcrumbBody<DB, ST> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+/// Render `<head> || '<separator>' || <tail>`, doubling quotes in the
+/// separator.
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+pub(crate) fn walk_pipe_concat<'b, DB, Head, Tail>(
+    head: &'b Head,
+    separator: &str,
+    tail: &'b Tail,
+    out: &mut AstPass<'_, 'b, DB>,
+) -> QueryResult<()>
+where
+    DB: Backend,
+    Head: QueryFragment<DB>,
+    Tail: QueryFragment<DB>,
+{
+    head.walk_ast(out.reborrow())?;
+    out.push_sql(" || '");
+    out.push_sql(&separator.replace('\'', "''"));
+    out.push_sql("' || ");
+    tail.walk_ast(out.reborrow())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Hierarchy, test_support::normalise_debug_sql};
+    use diesel::{debug_query, sqlite::Sqlite};
+
+    diesel::table! {
+        categories (id) {
+            id -> Integer,
+            parent_id -> Nullable<Integer>,
+            name -> Text,
+        }
+    }
+
+    #[test]
+    fn all_breadcrumbs_walk_down_from_the_roots() {
+        let crumbs =
+            Hierarchy::new(categories::id, categories::parent_id).breadcrumbs(categories::name);
+        let query = crumbs.all::<Sqlite>();
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert_eq!(
+            sql,
+            "WITH RECURSIVE \"breadcrumbs\" (\"node\", \"id\", \"parent\", \"path\", \"ancestors\") AS (\
+             SELECT \"categories\".\"id\", \"categories\".\"id\", \"categories\".\"parent_id\", \
+             CAST(\"categories\".\"name\" AS TEXT), CAST(\"categories\".\"id\" AS TEXT) \
+             FROM \"categories\" WHERE (\"categories\".\"parent_id\" IS NULL) \
+             UNION ALL \
+             SELECT \"categories\".\"id\", \"categories\".\"id\", \"categories\".\"parent_id\", \
+             \"breadcrumbs\".\"path\" || ' > ' || CAST(\"categories\".\"name\" AS TEXT), \
+             \"breadcrumbs\".\"ancestors\" || '/' || CAST(\"categories\".\"id\" AS TEXT) \
+             FROM \"categories\" INNER JOIN \"breadcrumbs\" ON \"categories\".\"parent_id\" = \"breadcrumbs\".\"id\") \
+             SELECT \"breadcrumbs\".\"node\", \"breadcrumbs\".\"path\", \"breadcrumbs\".\"ancestors\" \
+             FROM \"breadcrumbs\" ORDER BY \"breadcrumbs\".\"node\""
+        );
+    }
+
+    #[test]
+    fn single_breadcrumb_walks_up_and_prepends_labels() {
+        let crumbs = Hierarchy::new(categories::id, categories::parent_id)
+            .breadcrumbs(categories::name)
+            .with_separator(" ' ");
+        let query = crumbs.of::<Sqlite, _>(5);
+        let rendered = debug_query::<Sqlite, _>(&query).to_string();
+        let sql = normalise_debug_sql(&rendered);
+        assert!(
+            sql.contains(
+                "SELECT \"breadcrumbs\".\"node\", \"categories\".\"id\", \"categories\".\"parent_id\", \
+                 CAST(\"categories\".\"name\" AS TEXT) || ' '' ' || \"breadcrumbs\".\"path\", \
+                 CAST(\"categories\".\"id\" AS TEXT) || '/' || \"breadcrumbs\".\"ancestors\" \
+                 FROM \"categories\" INNER JOIN \"breadcrumbs\" ON \"categories\".\"id\" = \"breadcrumbs\".\"parent\" \
+                 WHERE instr('/' || \"breadcrumbs\".\"ancestors\" || '/', '/' || CAST(\"categories\".\"id\" AS TEXT) || '/') = 0)"
+            ),
+            "unexpected SQL: {sql}"
+        );
+        assert!(
+            sql.ends_with(
+                "WHERE \"breadcrumbs\".\"parent\" IS NULL ORDER BY \"breadcrumbs\".\"node\""
+            ),
+            "unexpected SQL: {sql}"
+        );
+        assert!(rendered.ends_with("-- binds: [5]"));
+    }
+
+    #[test]
+    fn max_depth_counts_and_guards_levels() {
+        let crumbs = Hierarchy::new(categories::id, categories::parent_id)
+            .with_max_depth(2)
+            .breadcrumbs(categories::name)
+            .with_separator(String::from(" / "));
+        let all =
+            normalise_debug_sql(&debug_query::<Sqlite, _>(&crumbs.all::<Sqlite>()).to_string());
+        let single =
+            normalise_debug_sql(&debug_query::<Sqlite, _>(&crumbs.of::<Sqlite, _>(5)).to_string());
+        assert!(
+            all.starts_with(
+                "WITH RECURSIVE \"breadcrumbs\" (\"node\", \"id\", \"parent\", \"path\", \"ancestors\", \"depth\") AS (\
+                 SELECT \"categories\".\"id\", \"categories\".\"id\", \"categories\".\"parent_id\", \
+                 CAST(\"categories\".\"name\" AS TEXT), CAST(\"categories\".\"id\" AS TEXT), 0 FROM"
+            ),
+            "unexpected SQL: {all}"
+        );
+        assert!(
+            all.contains(
+                "\"breadcrumbs\".\"path\" || ' / ' || CAST(\"categories\".\"name\" AS TEXT), \
+                 \"breadcrumbs\".\"ancestors\" || '/' || CAST(\"categories\".\"id\" AS TEXT), \
+                 \"breadcrumbs\".\"depth\" + 1 \
+                 FROM \"categories\" INNER JOIN \"breadcrumbs\" ON \"categories\".\"parent_id\" = \"breadcrumbs\".\"id\" \
+                 WHERE \"breadcrumbs\".\"depth\" < 2)"
+            ),
+            "unexpected SQL: {all}"
+        );
+        assert!(
+            single.contains(
+                "'/' || CAST(\"categories\".\"id\" AS TEXT) || '/') = 0 AND \"breadcrumbs\".\"depth\" < 2)"
+            ),
+            "unexpected SQL: {single}"
+        );
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn postgres_keys_are_arrays() {
+        use diesel::pg::Pg;
+
+        let crumbs =
+            Hierarchy::new(categories::id, categories::parent_id).breadcrumbs(categories::name);
+        let all = normalise_debug_sql(&debug_query::<Pg, _>(&crumbs.all::<Pg>()).to_string());
+        let single = normalise_debug_sql(&debug_query::<Pg, _>(&crumbs.of::<Pg, _>(5)).to_string());
+        assert!(
+            all.contains(
+                "CAST(\"categories\".\"name\" AS TEXT), ARRAY[\"categories\".\"id\"] FROM \"categories\""
+            ),
+            "unexpected SQL: {all}"
+        );
+        assert!(
+            all.contains("\"breadcrumbs\".\"ancestors\" || \"categories\".\"id\" FROM"),
+            "unexpected SQL: {all}"
+        );
+        assert!(
+            single.contains("\"categories\".\"id\" || \"breadcrumbs\".\"ancestors\" FROM"),
+            "unexpected SQL: {single}"
+        );
+        assert!(
+            single.contains(
+                "WHERE NOT (\"categories\".\"id\" = ANY(\"breadcrumbs\".\"ancestors\")))"
+            ),
+            "unexpected SQL: {single}"
+        );
+    }
+
+    #[cfg(feature = "mysql")]
+    #[test]
+    fn breadcrumbs_are_rejected_on_mysql() {
+        use crate::test_support::assert_query_builder_error;
+        use diesel::{
+            mysql::{Mysql, MysqlQueryBuilder},
+            query_builder::QueryFragment,
+        };
+
+        let crumbs =
+            Hierarchy::new(categories::id, categories::parent_id).breadcrumbs(categories::name);
+        let err = crumbs
+            .all::<Mysql>()
+            .to_sql(&mut MysqlQueryBuilder::new(), &Mysql)
+            .expect_err("MySQL has no representation for breadcrumb keys");
+        assert_query_builder_error(&err, "breadcrumb keys are not supported");
+    }
+}
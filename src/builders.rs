@@ -5,11 +5,20 @@
 //! These helpers are used indirectly via
 //! [`crate::connection_ext::RecursiveCTEExt::with_recursive`].
 
-use diesel::{backend::Backend, query_builder::QueryFragment};
+use std::borrow::Cow;
+
+use diesel::{
+    backend::Backend,
+    query_builder::{Query, QueryFragment},
+};
 
 use crate::{
-    columns::Columns,
-    cte::{RecursiveBackend, WithCte, WithRecursive},
+    boxed::BoxedFragment,
+    columns::{ColumnTypes, Columns, CteColumns},
+    cte::{Materialization, RecursiveBackend, UnionKind, WithCte, WithRecursive},
+    cycle::CycleCheck,
+    kind::CteQuery,
+    search::SearchOrder,
 };
 
 /// Query fragments used by a recursive CTE.
@@ -21,12 +30,134 @@ pub struct RecursiveParts<Seed, Step, Body> {
     pub step: Step,
     /// Query consuming the CTE.
     pub body: Body,
+    /// Set operator joining the seed and step; defaults to `UNION ALL`.
+    pub union: UnionKind,
+    /// Optional traversal ordering column exposed by the CTE.
+    pub search: Option<SearchOrder>,
+    /// Optional cycle marker and path columns exposed by the CTE.
+    pub cycle: Option<CycleCheck>,
+    /// Planner hint for the CTE; defaults to no hint.
+    pub materialization: Materialization,
 }
 
 impl<Seed, Step, Body> RecursiveParts<Seed, Step, Body> {
     /// Bundle the seed, step and body queries together.
     pub const fn new(seed: Seed, step: Step, body: Body) -> Self {
-        Self { seed, step, body }
+        Self {
+            seed,
+            step,
+            body,
+            union: UnionKind::All,
+            search: None,
+            cycle: None,
+            materialization: Materialization::Default,
+        }
+    }
+
+    /// Box the seed and step so parts assembled in different branches share
+    /// a type.
+    ///
+    /// The body keeps its type because it determines the rows the query
+    /// loads; use Diesel's `into_boxed` on it if it varies as well.
+    pub fn into_boxed<'a, DB>(
+        self,
+    ) -> RecursiveParts<BoxedFragment<'a, DB>, BoxedFragment<'a, DB>, Body>
+    where
+        DB: Backend,
+        Seed: QueryFragment<DB> + Send + 'a,
+        Step: QueryFragment<DB> + Send + 'a,
+    {
+        RecursiveParts {
+            seed: BoxedFragment::new(self.seed),
+            step: BoxedFragment::new(self.step),
+            body: self.body,
+            union: self.union,
+            search: self.search,
+            cycle: self.cycle,
+            materialization: self.materialization,
+        }
+    }
+
+    /// Render `materialization` between the CTE's column list and its query.
+    #[must_use]
+    pub const fn with_materialization(mut self, materialization: Materialization) -> Self {
+        self.materialization = materialization;
+        self
+    }
+
+    /// Expose a depth-first or breadth-first ordering column on the CTE.
+    ///
+    /// On backends without a native `SEARCH` clause the seed and step must
+    /// end their select lists with [`SearchOrder::seed_key`] and
+    /// [`SearchOrder::step_key`] respectively.
+    #[must_use]
+    pub fn with_search(mut self, search: SearchOrder) -> Self {
+        self.search = Some(search);
+        self
+    }
+
+    /// Mark rows that revisit a value on their path and stop expanding them.
+    ///
+    /// On backends without a native `CYCLE` clause the seed and step must end
+    /// their select lists with [`CycleCheck::seed_key`] and
+    /// [`CycleCheck::step_key`], and the step must filter on
+    /// [`CycleCheck::step_guard`].
+    #[must_use]
+    pub fn with_cycle(mut self, cycle: CycleCheck) -> Self {
+        self.cycle = Some(cycle);
+        self
+    }
+
+    /// Join the seed and step with `union` instead of `UNION ALL`.
+    ///
+    /// Use [`UnionKind::Distinct`] when traversing data that may contain
+    /// cycles so the recursion stops once no new rows are produced.
+    #[must_use]
+    pub const fn with_union(mut self, union: UnionKind) -> Self {
+        self.union = union;
+        self
+    }
+}
+
+/// Seed and step fragments of a recursive CTE appended to an existing query.
+///
+/// Unlike [`RecursiveParts`] there is no body: the appended definition is
+/// consumed by the body of the query it joins.
+#[derive(Debug, Clone)]
+pub struct RecursiveTerms<Seed, Step> {
+    /// Seed query producing the first row(s) of the CTE.
+    pub seed: Seed,
+    /// Step query referencing the previous iteration's result.
+    pub step: Step,
+    /// Set operator joining the seed and step; defaults to `UNION ALL`.
+    pub union: UnionKind,
+    /// Planner hint for the CTE; defaults to no hint.
+    pub materialization: Materialization,
+}
+
+impl<Seed, Step> RecursiveTerms<Seed, Step> {
+    /// Bundle the seed and step queries together.
+    pub const fn new(seed: Seed, step: Step) -> Self {
+        Self {
+            seed,
+            step,
+            union: UnionKind::All,
+            materialization: Materialization::Default,
+        }
+    }
+
+    /// Render `materialization` between the CTE's column list and its query.
+    #[must_use]
+    pub const fn with_materialization(mut self, materialization: Materialization) -> Self {
+        self.materialization = materialization;
+        self
+    }
+
+    /// Join the seed and step with `union` instead of `UNION ALL`.
+    #[must_use]
+    pub const fn with_union(mut self, union: UnionKind) -> Self {
+        self.union = union;
+        self
     }
 }
 
@@ -37,18 +168,45 @@ pub struct CteParts<Cte, Body> {
     pub cte: Cte,
     /// Query consuming the CTE.
     pub body: Body,
+    /// Planner hint for the CTE; defaults to no hint.
+    pub materialization: Materialization,
 }
 
 impl<Cte, Body> CteParts<Cte, Body> {
     /// Bundle the CTE and body queries together.
     pub const fn new(cte: Cte, body: Body) -> Self {
-        Self { cte, body }
+        Self {
+            cte,
+            body,
+            materialization: Materialization::Default,
+        }
+    }
+
+    /// Box the CTE query so parts assembled in different branches share a
+    /// type.
+    pub fn into_boxed<'a, DB, Kind>(self) -> CteParts<BoxedFragment<'a, DB>, Body>
+    where
+        DB: Backend,
+        Cte: CteQuery<DB, Kind> + Send + 'a,
+    {
+        CteParts {
+            cte: BoxedFragment::new(self.cte),
+            body: self.body,
+            materialization: self.materialization,
+        }
+    }
+
+    /// Render `materialization` between the CTE's column list and its query.
+    #[must_use]
+    pub const fn with_materialization(mut self, materialization: Materialization) -> Self {
+        self.materialization = materialization;
+        self
     }
 }
 
 /// Build a recursive CTE query.
 pub fn with_recursive<DB, Cols, Seed, Step, Body, ColSpec>(
-    cte_name: &'static str,
+    cte_name: impl Into<Cow<'static, str>>,
     columns: ColSpec,
     parts: RecursiveParts<Seed, Step, Body>,
 ) -> WithRecursive<DB, Cols, Seed, Step, Body>
@@ -57,35 +215,148 @@ where
     Seed: QueryFragment<DB>,
     Step: QueryFragment<DB>,
     Body: QueryFragment<DB>,
-    ColSpec: Into<Columns<Cols>>,
+    ColSpec: Into<CteColumns<Cols>>,
 {
     WithRecursive {
-        cte_name,
+        cte_name: cte_name.into(),
         columns: columns.into(),
         seed: parts.seed,
         step: parts.step,
+        union: parts.union,
+        materialization: parts.materialization,
+        search: parts.search,
+        cycle: parts.cycle,
+        depth: None,
         body: parts.body,
+        rest: (),
         _marker: std::marker::PhantomData,
     }
 }
 
+/// Build a recursive CTE query whose seed and step must match `columns`.
+///
+/// Behaves like [`with_recursive`], but takes a typed column list such as one
+/// built by [`columns!`](macro@crate::columns) or
+/// [`CteTable::columns`](crate::columns::CteTable::columns) and requires the
+/// seed and step to be Diesel queries returning the columns' SQL types. A
+/// mismatch is a compile error rather than a database error:
+///
+/// ```compile_fail
+/// use diesel::{dsl::sql, sql_types::{Integer, Text}, sqlite::Sqlite};
+/// use diesel_cte_ext::{RecursiveParts, builders, cte_table, CteTable};
+///
+/// cte_table! {
+///     tree (id) {
+///         id -> Integer,
+///         depth -> Integer,
+///     }
+/// }
+///
+/// let query = builders::with_recursive_checked::<Sqlite, _, _, _, _>(
+///     tree::table::NAME,
+///     tree::table::columns(),
+///     RecursiveParts::new(
+///         sql::<(Integer, Text)>("SELECT 1, 'root'"),
+///         sql::<(Integer, Integer)>("SELECT id + 1, depth + 1 FROM tree"),
+///         sql::<Integer>("SELECT id FROM tree"),
+///     ),
+/// );
+/// ```
+///
+/// Seeds and steps assembled with [`seed_query!`](crate::seed_query) or
+/// [`step_query!`](crate::step_query), and emulated search or cycle columns,
+/// are not covered by the check; use [`with_recursive`] for those.
+pub fn with_recursive_checked<DB, Cols, Seed, Step, Body>(
+    cte_name: impl Into<Cow<'static, str>>,
+    columns: Columns<Cols>,
+    parts: RecursiveParts<Seed, Step, Body>,
+) -> WithRecursive<DB, Cols, Seed, Step, Body>
+where
+    DB: RecursiveBackend,
+    Cols: ColumnTypes,
+    Seed: QueryFragment<DB> + Query<SqlType = Cols::SqlType>,
+    Step: QueryFragment<DB> + Query<SqlType = Cols::SqlType>,
+    Body: QueryFragment<DB>,
+{
+    with_recursive(cte_name, columns, parts)
+}
+
 /// Build a non-recursive CTE query.
-pub fn with_cte<DB, Cols, Cte, Body, ColSpec>(
-    cte_name: &'static str,
-    columns: ColSpec,
+///
+/// The CTE query must implement [`CteQuery`]: a Diesel `SELECT`, raw SQL, or
+/// a fragment wrapping one. On backends implementing
+/// [`DataModifyingBackend`](crate::kind::DataModifyingBackend), which is only
+/// `PostgreSQL`, it may also be an `INSERT`, `UPDATE`, or `DELETE` statement,
+/// typically with a `.returning(...)` clause whose rows the body can read.
+/// `SQLite` does not accept data-modifying statements inside `WITH`, so they
+/// fail to compile there, even when Diesel renders `RETURNING` for `SQLite`:
+///
+/// ```compile_fail
+/// use diesel::{dsl::sql, prelude::*, sql_types::Integer, sqlite::Sqlite};
+/// use diesel_cte_ext::{CteParts, builders};
+///
+/// diesel::table! {
+///     items (id) {
+///         id -> Integer,
+///     }
+/// }
+///
+/// let query = builders::with_cte::<Sqlite, _, _, _, _>(
+///     "removed",
+///     &["id"],
+///     CteParts::new(
+///         diesel::delete(items::table),
+///         sql::<Integer>("SELECT 1"),
+///     ),
+/// );
+/// ```
+///
+/// Data-modifying statements need a `.returning(...)` clause, since a CTE
+/// without output columns cannot be read by the body:
+///
+/// ```compile_fail
+/// # #[cfg(feature = "postgres")]
+/// # fn main() {
+/// use diesel::{dsl::sql, pg::Pg, prelude::*, sql_types::Integer};
+/// use diesel_cte_ext::{CteParts, builders};
+///
+/// diesel::table! {
+///     items (id) {
+///         id -> Integer,
+///     }
+/// }
+///
+/// let query = builders::with_cte::<Pg, _, _, _, _>(
+///     "removed",
+///     &["id"],
+///     CteParts::new(
+///         diesel::delete(items::table),
+///         sql::<Integer>("SELECT 1"),
+///     ),
+/// );
+/// # }
+/// # #[cfg(not(feature = "postgres"))]
+/// # compile_error!("requires the postgres feature");
+/// ```
+///
+/// `Kind` is inferred from the CTE query; see [`CteQuery`].
+pub fn with_cte<DB, Cols, Cte, Body, Kind>(
+    cte_name: impl Into<Cow<'static, str>>,
+    columns: impl Into<CteColumns<Cols>>,
     parts: CteParts<Cte, Body>,
 ) -> WithCte<DB, Cols, Cte, Body>
 where
     DB: Backend,
-    Cte: QueryFragment<DB>,
+    Cte: CteQuery<DB, Kind>,
     Body: QueryFragment<DB>,
-    ColSpec: Into<Columns<Cols>>,
 {
     WithCte {
-        cte_name,
+        cte_name: cte_name.into(),
         columns: columns.into(),
         cte: parts.cte,
+        materialization: parts.materialization,
         body: parts.body,
+        rest: (),
         _marker: std::marker::PhantomData,
     }
 }
@@ -130,4 +401,97 @@ mod tests {
             "WITH \"nums\" (\"n\") AS (SELECT 1) SELECT n FROM nums"
         );
     }
+
+    #[test]
+    fn checked_builder_accepts_matching_seed_and_step() {
+        crate::cte_table! {
+            tree (id) {
+                id -> Integer,
+                depth -> Integer,
+            }
+        }
+
+        let query = with_recursive_checked::<Sqlite, _, _, _, _>(
+            <tree::table as crate::columns::CteTable>::NAME,
+            <tree::table as crate::columns::CteTable>::columns(),
+            RecursiveParts::new(
+                sql::<(Integer, Integer)>("SELECT 1, 0"),
+                sql::<(Integer, Integer)>("SELECT id + 1, depth + 1 FROM tree WHERE id < 3"),
+                sql::<Integer>("SELECT id FROM tree"),
+            ),
+        );
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert_eq!(
+            sql,
+            "WITH RECURSIVE \"tree\" (\"id\", \"depth\") AS (SELECT 1, 0 UNION ALL SELECT id + 1, depth + 1 FROM tree WHERE id < 3) SELECT id FROM tree"
+        );
+    }
+
+    #[test]
+    fn recursive_builder_renders_distinct_union() {
+        let query = with_recursive::<Sqlite, _, _, _, _, _>(
+            "reach",
+            &["n"],
+            RecursiveParts::new(
+                sql::<Integer>("SELECT 1"),
+                sql::<Integer>("SELECT b FROM edges JOIN reach ON a = n"),
+                sql::<Integer>("SELECT n FROM reach"),
+            )
+            .with_union(UnionKind::Distinct),
+        );
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert_eq!(
+            sql,
+            "WITH RECURSIVE \"reach\" (\"n\") AS (SELECT 1 UNION SELECT b FROM edges JOIN reach ON a = n) SELECT n FROM reach"
+        );
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn non_recursive_builder_accepts_data_modifying_statements() {
+        use diesel::{pg::Pg, prelude::*};
+
+        diesel::table! {
+            items (id) {
+                id -> Integer,
+                done -> Bool,
+            }
+        }
+
+        let query = with_cte::<Pg, _, _, _, _>(
+            "removed",
+            &["id"],
+            CteParts::new(
+                diesel::delete(items::table.filter(items::done)).returning(items::id),
+                sql::<Integer>("SELECT id FROM removed"),
+            ),
+        );
+        let sql = normalise_debug_sql(&debug_query::<Pg, _>(&query).to_string());
+        assert_eq!(
+            sql,
+            "WITH \"removed\" (\"id\") AS (DELETE FROM \"items\" WHERE \"items\".\"done\" RETURNING \"items\".\"id\") SELECT id FROM removed"
+        );
+    }
+
+    #[test]
+    fn non_recursive_builder_accepts_nested_cte_queries() {
+        let inner = with_cte::<Sqlite, _, _, _, _>(
+            "one",
+            &["n"],
+            CteParts::new(
+                sql::<Integer>("SELECT 1"),
+                sql::<Integer>("SELECT n FROM one"),
+            ),
+        );
+        let query = with_cte::<Sqlite, _, _, _, _>(
+            "outer_nums",
+            &["n"],
+            CteParts::new(inner, sql::<Integer>("SELECT n FROM outer_nums")),
+        );
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert_eq!(
+            sql,
+            "WITH \"outer_nums\" (\"n\") AS (WITH \"one\" (\"n\") AS (SELECT 1) SELECT n FROM one) SELECT n FROM outer_nums"
+        );
+    }
 }
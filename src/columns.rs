@@ -4,9 +4,18 @@
 //! column types. Helper macros build these lists from individual column paths or
 //! complete table definitions. The provided tuple implementations cover up to
 //! sixteen columns (`A` through `P`). Extend the `tuple_column_names!` macro
-//! invocations if you need support for more columns.
+//! invocations if you need support for more columns. Names known only at
+//! runtime are held by [`CteColumns`], which the builders accept alongside
+//! static [`Columns`] that stay borrowed without allocating. [`cte_table!`](crate::cte_table) declares a
+//! CTE as a Diesel table so seeds, steps, and bodies can use the query DSL.
 
-use diesel::{Table, query_source::Column};
+use std::borrow::Cow;
+
+use diesel::{
+    Expression, Table,
+    internal::table_macro::{Identifier, InfixNode, StaticQueryFragment},
+    query_source::Column,
+};
 
 /// Runtime column names with associated type-level metadata.
 #[derive(Debug, Clone, Copy)]
@@ -72,24 +81,218 @@ impl<const N: usize> From<&'static [&'static str; N]> for Columns<()> {
     }
 }
 
+/// Column names of a CTE, either borrowed from static data or owned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColumnList {
+    /// Names known at compile time; no allocation is needed.
+    Static(&'static [&'static str]),
+    /// Names built at runtime, for example from configuration.
+    Owned(Vec<Cow<'static, str>>),
+}
+
+impl ColumnList {
+    /// Number of column names.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        match self {
+            Self::Static(names) => names.len(),
+            Self::Owned(names) => names.len(),
+        }
+    }
+
+    /// Whether the list holds no names.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Borrow the names as string slices.
+    ///
+    /// Static lists are returned as is; owned lists collect references to
+    /// their names.
+    #[must_use]
+    pub fn as_strs(&self) -> Cow<'_, [&str]> {
+        match self {
+            Self::Static(names) => Cow::Borrowed(names),
+            Self::Owned(names) => Cow::Owned(names.iter().map(AsRef::as_ref).collect()),
+        }
+    }
+}
+
+/// Column list held by a CTE: static [`Columns`] or names known only at
+/// runtime.
+///
+/// The builders accept anything converting into this type, so static lists
+/// keep their zero-allocation path while [`CteColumns::owned`] carries names
+/// read from configuration.
+#[derive(Debug, Clone)]
+pub struct CteColumns<T> {
+    /// Column names in query order.
+    pub names: ColumnList,
+    _marker: core::marker::PhantomData<T>,
+}
+
+impl CteColumns<()> {
+    /// Build a column list from names known only at runtime.
+    ///
+    /// Names are quoted when the query is rendered, like static names.
+    #[must_use]
+    pub fn owned<I, S>(names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<Cow<'static, str>>,
+    {
+        Self {
+            names: ColumnList::Owned(names.into_iter().map(Into::into).collect()),
+            _marker: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<T> From<Columns<T>> for CteColumns<T> {
+    fn from(columns: Columns<T>) -> Self {
+        Self {
+            names: ColumnList::Static(columns.names),
+            _marker: core::marker::PhantomData,
+        }
+    }
+}
+
+impl From<Vec<String>> for CteColumns<()> {
+    fn from(names: Vec<String>) -> Self {
+        Self::owned(names)
+    }
+}
+
+impl From<Vec<Cow<'static, str>>> for CteColumns<()> {
+    fn from(names: Vec<Cow<'static, str>>) -> Self {
+        Self {
+            names: ColumnList::Owned(names),
+            _marker: core::marker::PhantomData,
+        }
+    }
+}
+
+impl From<&'static [&'static str]> for CteColumns<()> {
+    fn from(names: &'static [&'static str]) -> Self {
+        Columns::raw(names).into()
+    }
+}
+
+impl<const N: usize> From<&'static [&'static str; N]> for CteColumns<()> {
+    fn from(names: &'static [&'static str; N]) -> Self {
+        Columns::raw(&names[..]).into()
+    }
+}
+
 /// Helper trait yielding column name arrays for tuples of Diesel column types.
 pub trait ColumnNames {
     /// Column names in query order.
     const NAMES: &'static [&'static str];
 }
 
-/// Implements [`ColumnNames`] for tuples of Diesel column types.
+/// SQL type a query must return to populate a tuple of Diesel columns.
+///
+/// A single column maps to its own SQL type, matching `select(column)`;
+/// larger tuples map to the tuple of their columns' SQL types.
+pub trait ColumnTypes: ColumnNames {
+    /// SQL type of a row of these columns.
+    type SqlType;
+}
+
+/// Column types that pin the names of a CTE for Diesel's statement cache.
+///
+/// A tuple of Diesel columns stands for a CTE named after the first column's
+/// table with exactly those columns, as [`cte_table!`](crate::cte_table)
+/// declares it. CTE queries over such a tuple get a static query id when their
+/// parts have one, and render without caching if they were built with other
+/// names. `()`, the type of untyped column lists, pins nothing, so those
+/// queries are cached by their SQL.
+pub trait PinnedNames {
+    /// Whether the type pins a CTE name and column list.
+    const PINNED: bool;
+
+    /// Whether `cte_name` and `columns` are the names the type pins.
+    fn pins(cte_name: &str, columns: &[&str]) -> bool;
+}
+
+impl PinnedNames for () {
+    const PINNED: bool = false;
+
+    fn pins(_cte_name: &str, _columns: &[&str]) -> bool {
+        false
+    }
+}
+
+/// Static identifier of a table generated by [`diesel::table!`].
+pub trait TableIdentifier {
+    /// Name of the table, or `None` when it is qualified by a schema.
+    fn unqualified_name(&self) -> Option<&str>;
+}
+
+impl TableIdentifier for Identifier<'_> {
+    fn unqualified_name(&self) -> Option<&str> {
+        Some(self.0)
+    }
+}
+
+impl<T, U, M> TableIdentifier for InfixNode<T, U, M> {
+    fn unqualified_name(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Implements [`ColumnNames`], [`ColumnTypes`], and [`PinnedNames`] for tuples
+/// of Diesel column types.
 ///
 /// This macro is expanded below for tuples of up to sixteen columns. If you
 /// need to support a larger tuple, simply extend the invocations using
 /// additional identifiers.
 macro_rules! tuple_column_names {
+    ($name:ident) => {
+        tuple_column_names!(@names $name);
+
+        impl<$name> ColumnTypes for ($name,)
+        where
+            $name: Column,
+        {
+            type SqlType = <$name as Expression>::SqlType;
+        }
+    };
     ($($name:ident),+) => {
-        impl<$($name),+> ColumnNames for ($($name,)+)
+        tuple_column_names!(@names $($name),+);
+
+        impl<$($name),+> ColumnTypes for ($($name,)+)
         where
             $($name: Column,)+
         {
-            const NAMES: &'static [&'static str] = &[$($name::NAME),+];
+            type SqlType = ($(<$name as Expression>::SqlType,)+);
+        }
+    };
+    (@names $first:ident $(, $name:ident)*) => {
+        impl<$first, $($name),*> ColumnNames for ($first, $($name,)*)
+        where
+            $first: Column,
+            $($name: Column,)*
+        {
+            const NAMES: &'static [&'static str] = &[$first::NAME, $($name::NAME),*];
+        }
+
+        impl<$first, $($name),*> PinnedNames for ($first, $($name,)*)
+        where
+            $first: Column,
+            $($name: Column,)*
+            <$first as Column>::Table: StaticQueryFragment,
+            <<$first as Column>::Table as StaticQueryFragment>::Component: TableIdentifier,
+        {
+            const PINNED: bool = true;
+
+            fn pins(cte_name: &str, columns: &[&str]) -> bool {
+                <<$first as Column>::Table as StaticQueryFragment>::STATIC_COMPONENT
+                    .unqualified_name()
+                    == Some(cte_name)
+                    && columns == <Self as ColumnNames>::NAMES
+            }
         }
     };
 }
@@ -112,6 +315,63 @@ tuple_column_names!(A, B, C, D, E, F, G, H, I, J, K, L, M, N);
 tuple_column_names!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O);
 tuple_column_names!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P);
 
+/// CTE declared as a Diesel table through [`cte_table!`](crate::cte_table).
+///
+/// The table's name and columns double as the CTE name and column list, so
+/// the declaration is the single source of truth for both.
+pub trait CteTable: Table {
+    /// Name of the CTE.
+    const NAME: &'static str;
+
+    /// Column list of the CTE, in declaration order.
+    #[must_use]
+    fn columns() -> Columns<Self::AllColumns>
+    where
+        Self::AllColumns: ColumnNames,
+    {
+        Columns::default()
+    }
+}
+
+/// Declares a CTE as a Diesel table with typed columns.
+///
+/// The input follows [`diesel::table!`]: a name, a key column in parentheses,
+/// and the columns with their SQL types. The generated module exposes `table`
+/// and one item per column for use in the query DSL, and `table` implements
+/// [`CteTable`](crate::columns::CteTable) so the CTE name and column list can
+/// be passed to the builders. A `#[sql_name = "..."]` attribute renames the
+/// CTE as it does the table. Joins with other tables still need
+/// [`diesel::allow_tables_to_appear_in_same_query!`].
+#[macro_export]
+macro_rules! cte_table {
+    (
+        $(#[$($meta:tt)*])*
+        $name:ident ($($key:ident),+ $(,)?) {
+            $($(#[$col_meta:meta])* $col:ident -> $ty:ty),+ $(,)?
+        }
+    ) => {
+        ::diesel::table! {
+            $(#[$($meta)*])*
+            $name ($($key),+) {
+                $($(#[$col_meta])* $col -> $ty,)+
+            }
+        }
+
+        impl $crate::columns::CteTable for $name::table {
+            const NAME: &'static str = $crate::cte_table!(@name $name $(#[$($meta)*])*);
+        }
+    };
+    (@name $name:ident #[sql_name = $sql_name:literal] $($rest:tt)*) => {
+        $sql_name
+    };
+    (@name $name:ident #[$($meta:tt)*] $($rest:tt)*) => {
+        $crate::cte_table!(@name $name $($rest)*)
+    };
+    (@name $name:ident) => {
+        ::core::stringify!($name)
+    };
+}
+
 /// Builds a [`Columns`](crate::columns::Columns) value from explicit column paths.
 #[macro_export]
 macro_rules! columns {
@@ -161,6 +421,54 @@ mod tests {
         }
     }
 
+    crate::cte_table! {
+        tree (id) {
+            id -> diesel::sql_types::Integer,
+            depth -> diesel::sql_types::Integer,
+        }
+    }
+
+    crate::cte_table! {
+        /// Renamed CTE.
+        #[sql_name = "renamed_tree"]
+        renamed (id) {
+            id -> diesel::sql_types::Integer,
+        }
+    }
+
+    #[test]
+    fn cte_table_honours_sql_name() {
+        assert_eq!(<renamed::table as CteTable>::NAME, "renamed_tree");
+    }
+
+    #[test]
+    fn cte_table_exposes_name_and_columns() {
+        assert_eq!(<tree::table as CteTable>::NAME, "tree");
+        assert_eq!(tree::table::columns().names, &["id", "depth"]);
+    }
+
+    #[test]
+    fn column_types_follow_select_shape() {
+        fn sql_type_of<Cols: ColumnTypes>(
+            _: Columns<Cols>,
+        ) -> core::marker::PhantomData<Cols::SqlType> {
+            core::marker::PhantomData
+        }
+
+        let single: core::marker::PhantomData<diesel::sql_types::Integer> =
+            sql_type_of(columns!(sample::id));
+        let pair: core::marker::PhantomData<(diesel::sql_types::Integer, diesel::sql_types::Text)> =
+            sql_type_of(table_columns!(sample::table));
+        assert_eq!((single, pair), Default::default());
+    }
+
+    #[test]
+    fn owned_columns_accept_runtime_names() {
+        let prefix = String::from("col");
+        let cols = CteColumns::owned([format!("{prefix}_a"), format!("{prefix}_b")]);
+        assert_eq!(cols.names.as_strs(), &["col_a", "col_b"][..]);
+    }
+
     #[test]
     fn raw_columns_exposes_names() {
         let cols = Columns::<()>::raw(&["a", "b"]);
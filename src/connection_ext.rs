@@ -3,89 +3,139 @@
 //! Both helpers delegate to the builders module whilst inferring the backend
 //! from the connection type, so callers never pass the backend explicitly.
 
-use diesel::query_builder::QueryFragment;
+use std::borrow::Cow;
 
-#[cfg(all(feature = "async", feature = "sqlite"))]
-use diesel_async::sync_connection_wrapper::SyncConnectionWrapper;
+use diesel::query_builder::{Query, QueryFragment};
 
 use crate::{
     builders::{self, CteParts, RecursiveParts},
-    columns::Columns,
+    columns::{ColumnTypes, Columns, CteColumns},
     cte::{RecursiveBackend, WithCte, WithRecursive},
+    kind::CteQuery,
 };
 
+/// Default methods shared by [`RecursiveCTEExt`] and `AsyncRecursiveCTEExt`.
+macro_rules! connection_methods {
+    () => {
+        /// Create a [`WithRecursive`] builder for this connection's backend.
+        ///
+        /// See [`builders::with_recursive`] for parameter details.
+        #[doc(alias = "builders::with_recursive")]
+        fn with_recursive<Cols, Seed, Step, Body, ColSpec>(
+            &self,
+            cte_name: impl Into<Cow<'static, str>>,
+            columns: ColSpec,
+            parts: RecursiveParts<Seed, Step, Body>,
+        ) -> WithRecursive<Self::Backend, Cols, Seed, Step, Body>
+        where
+            Seed: QueryFragment<Self::Backend>,
+            Step: QueryFragment<Self::Backend>,
+            Body: QueryFragment<Self::Backend>,
+            ColSpec: Into<CteColumns<Cols>>,
+        {
+            let _ = self;
+            builders::with_recursive::<Self::Backend, Cols, _, _, _, _>(cte_name, columns, parts)
+        }
+
+        /// Create a [`WithRecursive`] builder whose seed and step must return the
+        /// SQL types of `columns`.
+        ///
+        /// See [`builders::with_recursive_checked`] for parameter details.
+        #[doc(alias = "builders::with_recursive_checked")]
+        fn with_recursive_checked<Cols, Seed, Step, Body>(
+            &self,
+            cte_name: impl Into<Cow<'static, str>>,
+            columns: Columns<Cols>,
+            parts: RecursiveParts<Seed, Step, Body>,
+        ) -> WithRecursive<Self::Backend, Cols, Seed, Step, Body>
+        where
+            Cols: ColumnTypes,
+            Seed: QueryFragment<Self::Backend> + Query<SqlType = Cols::SqlType>,
+            Step: QueryFragment<Self::Backend> + Query<SqlType = Cols::SqlType>,
+            Body: QueryFragment<Self::Backend>,
+        {
+            let _ = self;
+            builders::with_recursive_checked::<Self::Backend, Cols, _, _, _>(
+                cte_name, columns, parts,
+            )
+        }
+
+        /// Create a [`WithCte`] builder for this connection's backend.
+        #[doc(alias = "builders::with_cte")]
+        fn with_cte<Cols, Cte, Body, QueryKind>(
+            &self,
+            cte_name: impl Into<Cow<'static, str>>,
+            columns: impl Into<CteColumns<Cols>>,
+            parts: CteParts<Cte, Body>,
+        ) -> WithCte<Self::Backend, Cols, Cte, Body>
+        where
+            Cte: CteQuery<Self::Backend, QueryKind>,
+            Body: QueryFragment<Self::Backend>,
+        {
+            let _ = self;
+            builders::with_cte::<Self::Backend, Cols, _, _, _>(cte_name, columns, parts)
+        }
+    };
+}
+
 /// Extension trait providing convenient `with_recursive` and `with_cte` methods
 /// on connection types.
 ///
 /// The backend is inferred from the connection, so callers do not need to
-/// specify it explicitly.
+/// specify it explicitly. The trait is implemented for every blocking Diesel
+/// connection whose backend implements [`RecursiveBackend`]; `diesel_async`
+/// connections use `AsyncRecursiveCTEExt` instead.
 pub trait RecursiveCTEExt {
     /// Backend associated with the connection.
     type Backend: RecursiveBackend;
 
-    /// Create a [`WithRecursive`] builder for this connection's backend.
-    ///
-    /// See [`builders::with_recursive`] for parameter details.
-    #[doc(alias = "builders::with_recursive")]
-    fn with_recursive<Cols, Seed, Step, Body, ColSpec>(
-        &self,
-        cte_name: &'static str,
-        columns: ColSpec,
-        parts: RecursiveParts<Seed, Step, Body>,
-    ) -> WithRecursive<Self::Backend, Cols, Seed, Step, Body>
-    where
-        Seed: QueryFragment<Self::Backend>,
-        Step: QueryFragment<Self::Backend>,
-        Body: QueryFragment<Self::Backend>,
-        ColSpec: Into<Columns<Cols>>,
-    {
-        let _ = self;
-        builders::with_recursive::<Self::Backend, Cols, _, _, _, _>(cte_name, columns, parts)
-    }
-
-    /// Create a [`WithCte`] builder for this connection's backend.
-    #[doc(alias = "builders::with_cte")]
-    fn with_cte<Cols, Cte, Body, ColSpec>(
-        &self,
-        cte_name: &'static str,
-        columns: ColSpec,
-        parts: CteParts<Cte, Body>,
-    ) -> WithCte<Self::Backend, Cols, Cte, Body>
-    where
-        Cte: QueryFragment<Self::Backend>,
-        Body: QueryFragment<Self::Backend>,
-        ColSpec: Into<Columns<Cols>>,
-    {
-        let _ = self;
-        builders::with_cte::<Self::Backend, Cols, _, _, _>(cte_name, columns, parts)
-    }
+    connection_methods!();
 }
 
-/// Implementation of [`RecursiveCTEExt`] for synchronous `PostgreSQL` connections.
-#[cfg(feature = "postgres")]
-impl RecursiveCTEExt for diesel::pg::PgConnection {
-    type Backend = diesel::pg::Pg;
-}
+/// Extension trait providing `with_recursive` and `with_cte` on
+/// `diesel_async` connections.
+///
+/// The methods match [`RecursiveCTEExt`]'s. The trait is separate because a
+/// second blanket implementation of [`RecursiveCTEExt`] would overlap with the
+/// one for blocking connections.
+#[cfg(feature = "async")]
+pub trait AsyncRecursiveCTEExt {
+    /// Backend associated with the connection.
+    type Backend: RecursiveBackend;
 
-/// Implementation of [`RecursiveCTEExt`] for synchronous `SQLite` connections.
-#[cfg(feature = "sqlite")]
-impl RecursiveCTEExt for diesel::sqlite::SqliteConnection {
-    type Backend = diesel::sqlite::Sqlite;
+    connection_methods!();
 }
 
-/// Implementation of [`RecursiveCTEExt`] for `diesel_async` `PostgreSQL` connections.
-#[cfg(all(feature = "async", feature = "postgres"))]
-impl RecursiveCTEExt for diesel_async::AsyncPgConnection {
-    type Backend = diesel::pg::Pg;
+/// Implementation of [`RecursiveCTEExt`] for every blocking Diesel connection
+/// whose backend supports recursive CTEs.
+///
+/// This covers the built-in connections as well as wrappers that implement
+/// [`diesel::Connection`] themselves, such as instrumented connections,
+/// `diesel_async`'s `AsyncConnectionWrapper`, or `r2d2` pooled connections
+/// (enable the `r2d2` feature).
+impl<C> RecursiveCTEExt for C
+where
+    C: diesel::Connection,
+    C::Backend: RecursiveBackend,
+{
+    type Backend = C::Backend;
 }
 
-/// Implementation of [`RecursiveCTEExt`] for Diesel's async `SQLite` wrapper.
+/// Implementation of [`AsyncRecursiveCTEExt`] for every `diesel_async`
+/// connection whose backend supports recursive CTEs.
 ///
-/// `diesel_async` exposes `SQLite` via [`SyncConnectionWrapper`], so we forward the
-/// helper to that type instead of an `AsyncSqliteConnection` newtype.
-#[cfg(all(feature = "async", feature = "sqlite"))]
-impl<B> RecursiveCTEExt for SyncConnectionWrapper<diesel::sqlite::SqliteConnection, B> {
-    type Backend = diesel::sqlite::Sqlite;
+/// Besides `AsyncPgConnection`, `AsyncMysqlConnection`, and the `SQLite`
+/// [`SyncConnectionWrapper`](diesel_async::sync_connection_wrapper::SyncConnectionWrapper),
+/// this covers any type that dereferences to one of them, including the
+/// `deadpool` and `bb8` pooled connections behind the features of the same
+/// name.
+#[cfg(feature = "async")]
+impl<C> AsyncRecursiveCTEExt for C
+where
+    C: diesel_async::AsyncConnectionCore,
+    C::Backend: RecursiveBackend,
+{
+    type Backend = C::Backend;
 }
 
 #[cfg(test)]
@@ -138,26 +188,75 @@ mod tests {
         assert_eq!(sql, expected_recursive_sql());
     }
 
+    #[cfg(feature = "mysql")]
+    #[test]
+    fn mysql_backend_quotes_identifiers_with_backticks() {
+        use diesel::mysql::Mysql;
+
+        let conn = DummyConn::<Mysql>::default();
+        let query = conn.with_recursive("nums", &["n"], sample_parts());
+        let sql = debug_query::<Mysql, _>(&query).to_string();
+        assert!(sql.starts_with(
+            "WITH RECURSIVE `nums` (`n`) AS (SELECT 1 UNION ALL SELECT n + 1 FROM nums WHERE n < 5)"
+        ));
+        assert_eq!(normalise_debug_sql(&sql), expected_recursive_sql());
+    }
+
     #[test]
     fn connection_types_implement_recursive_ext() {
         fn assert_impl<T: RecursiveCTEExt>() {}
+        #[cfg(feature = "async")]
+        fn assert_async_impl<T: AsyncRecursiveCTEExt>() {}
 
         #[cfg(feature = "sqlite")]
         {
             assert_impl::<diesel::sqlite::SqliteConnection>();
             #[cfg(feature = "async")]
-            assert_impl::<
+            assert_async_impl::<
                 diesel_async::sync_connection_wrapper::SyncConnectionWrapper<
                     diesel::sqlite::SqliteConnection,
                 >,
             >();
+            #[cfg(feature = "r2d2")]
+            assert_impl::<
+                diesel::r2d2::PooledConnection<
+                    diesel::r2d2::ConnectionManager<diesel::sqlite::SqliteConnection>,
+                >,
+            >();
         }
 
+        #[cfg(all(feature = "postgres", feature = "deadpool"))]
+        assert_async_impl::<
+            diesel_async::pooled_connection::deadpool::Object<diesel_async::AsyncPgConnection>,
+        >();
+
+        #[cfg(all(feature = "postgres", feature = "bb8"))]
+        assert_async_impl::<
+            diesel_async::pooled_connection::bb8::PooledConnection<
+                'static,
+                diesel_async::AsyncPgConnection,
+            >,
+        >();
+
         #[cfg(feature = "postgres")]
         {
             assert_impl::<diesel::pg::PgConnection>();
             #[cfg(feature = "async")]
-            assert_impl::<diesel_async::AsyncPgConnection>();
+            {
+                assert_async_impl::<diesel_async::AsyncPgConnection>();
+                assert_impl::<
+                    diesel_async::async_connection_wrapper::AsyncConnectionWrapper<
+                        diesel_async::AsyncPgConnection,
+                    >,
+                >();
+            }
+        }
+
+        #[cfg(feature = "mysql")]
+        {
+            assert_impl::<diesel::mysql::MysqlConnection>();
+            #[cfg(feature = "async")]
+            assert_async_impl::<diesel_async::AsyncMysqlConnection>();
         }
     }
 
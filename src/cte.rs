@@ -1,10 +1,12 @@
 //! Core types modelling CTE queries.
 //!
 //! [`WithRecursive`] represents recursive CTEs and [`WithCte`] represents
-//! non-recursive CTEs, both as raw query fragments. [`RecursiveBackend`] marks
-//! Diesel backends that support recursive queries.
+//! non-recursive CTEs, both as raw query fragments. Further definitions can be
+//! appended to either query; they are rendered under the same `WITH` keyword
+//! via the [`CteList`] trait. [`RecursiveBackend`] marks Diesel backends that
+//! support recursive queries.
 
-use std::collections::BTreeSet;
+use std::{borrow::Cow, collections::BTreeSet};
 
 use diesel::{
     backend::Backend,
@@ -12,18 +14,30 @@ use diesel::{
     result::{Error, QueryResult},
 };
 
-use crate::columns::Columns;
+use crate::{
+    boxed::BoxedCteQuery,
+    builders::RecursiveTerms,
+    columns::{CteColumns, PinnedNames},
+    cycle::{CycleCheck, CycleKey},
+    depth::{CountedSeed, DepthLimit, DepthStep},
+    kind::CteQuery,
+    search::{SearchKey, SearchOrder},
+};
 
 macro_rules! impl_cte_traits {
     ($name:ident<$($gen:ident),*>, $body_ty:ident) => {
+        // CTE names are runtime values, so the id only stands for the query
+        // when `Cols` pins them; `walk_ast` opts out of the cache for queries
+        // built with other names or options.
         impl<DB, Cols, $($gen),*> QueryId for $name<DB, Cols, $($gen),*>
         where
-            DB: Backend + 'static,
-            Cols: 'static,
-            $($gen: 'static),*
+            DB: Backend,
+            Cols: PinnedNames + 'static,
+            $($gen: QueryId,)*
         {
-            type QueryId = Self;
-            const HAS_STATIC_QUERY_ID: bool = true;
+            type QueryId = (Cols, $(<$gen as QueryId>::QueryId,)*);
+            const HAS_STATIC_QUERY_ID: bool =
+                Cols::PINNED $(&& <$gen as QueryId>::HAS_STATIC_QUERY_ID)*;
         }
 
         impl<DB, Cols, $($gen),*> Query for $name<DB, Cols, $($gen),*>
@@ -39,19 +53,51 @@ macro_rules! impl_cte_traits {
         where
             DB: Backend,
             Conn: diesel::connection::Connection<Backend = DB>,
-            Self: QueryFragment<DB> + QueryId + Query,
+            Self: QueryFragment<DB> + QueryId,
         {}
     };
 }
 
-fn push_identifiers<DB, Cols>(
+fn push_definition_header<DB>(
     out: &mut AstPass<'_, '_, DB>,
-    cols: &Columns<Cols>,
+    cte_name: &str,
+    names: &[&str],
+    materialization: Materialization,
 ) -> QueryResult<()>
+where
+    DB: RecursiveBackend,
+{
+    if materialization != Materialization::Default && !DB::MATERIALIZATION_HINTS {
+        return Err(Error::QueryBuilderError(
+            "materialization hints are not supported by this backend".into(),
+        ));
+    }
+    out.push_identifier(cte_name)?;
+    push_identifiers(out, names)?;
+    out.push_sql(materialization.as_sql());
+    Ok(())
+}
+
+fn push_recursive_union<'b, DB, Seed, Step>(
+    out: &mut AstPass<'_, 'b, DB>,
+    seed: &'b Seed,
+    union: UnionKind,
+    step: &'b Step,
+) -> QueryResult<()>
+where
+    DB: Backend,
+    Seed: QueryFragment<DB>,
+    Step: QueryFragment<DB>,
+{
+    seed.walk_ast(out.reborrow())?;
+    out.push_sql(union.as_sql());
+    step.walk_ast(out.reborrow())
+}
+
+fn push_identifiers<DB>(out: &mut AstPass<'_, '_, DB>, ids: &[&str]) -> QueryResult<()>
 where
     DB: Backend,
 {
-    let ids = cols.names;
     if ids.is_empty() {
         return Ok(());
     }
@@ -67,7 +113,34 @@ where
     Ok(())
 }
 
-fn ensure_unique_columns(names: &[&str]) -> QueryResult<()> {
+/// Render `table.column` with both identifiers quoted.
+pub(crate) fn push_qualified<DB>(
+    out: &mut AstPass<'_, '_, DB>,
+    table: &str,
+    column: &str,
+) -> QueryResult<()>
+where
+    DB: Backend,
+{
+    out.push_identifier(table)?;
+    out.push_sql(".");
+    out.push_identifier(column)
+}
+
+/// Column of a CTE or derived table, rendered as `"table"."column"`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Qualified(pub(crate) &'static str, pub(crate) &'static str);
+
+impl<DB> QueryFragment<DB> for Qualified
+where
+    DB: Backend,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        push_qualified(&mut out, self.0, self.1)
+    }
+}
+
+pub(crate) fn ensure_unique_columns(names: &[&str]) -> QueryResult<()> {
     let mut seen = BTreeSet::new();
     for name in names {
         if !seen.insert(name) {
@@ -79,81 +152,949 @@ fn ensure_unique_columns(names: &[&str]) -> QueryResult<()> {
     Ok(())
 }
 
-/// Marker trait for backends that support `WITH RECURSIVE`.
-pub trait RecursiveBackend: Backend {}
+fn ensure_unique_cte_names<DB, Rest>(primary: &str, rest: &Rest) -> QueryResult<()>
+where
+    DB: Backend,
+    Rest: CteList<DB>,
+{
+    let mut names = vec![primary];
+    rest.collect_names(&mut names);
+    let mut seen = BTreeSet::new();
+    for name in names {
+        if !seen.insert(name) {
+            return Err(Error::QueryBuilderError(
+                format!("duplicate CTE name '{name}' in WITH clause").into(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Planner hint rendered between a CTE's column list and its query.
+///
+/// `PostgreSQL` 12+ and `SQLite` 3.35+ accept both hints. Other backends
+/// reject them when the query is rendered; see
+/// [`RecursiveBackend::MATERIALIZATION_HINTS`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Materialization {
+    /// No hint: the planner decides whether to inline the CTE.
+    #[default]
+    Default,
+    /// `AS MATERIALIZED`: evaluate the CTE once and reuse the result.
+    Materialized,
+    /// `AS NOT MATERIALIZED`: allow the CTE to be inlined into its consumers.
+    NotMaterialized,
+}
+
+impl Materialization {
+    const fn as_sql(self) -> &'static str {
+        match self {
+            Self::Default => " AS (",
+            Self::Materialized => " AS MATERIALIZED (",
+            Self::NotMaterialized => " AS NOT MATERIALIZED (",
+        }
+    }
+}
+
+/// Set operator joining the seed and step of a recursive CTE.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum UnionKind {
+    /// `UNION ALL`: keep every row produced by the step, including repeats.
+    #[default]
+    All,
+    /// `UNION`: discard rows already produced, so traversals over cyclic data
+    /// terminate once no new rows appear.
+    Distinct,
+}
+
+impl UnionKind {
+    const fn as_sql(self) -> &'static str {
+        match self {
+            Self::All => " UNION ALL ",
+            Self::Distinct => " UNION ",
+        }
+    }
+}
+
+/// Trait for backends that support `WITH RECURSIVE`.
+///
+/// Besides marking support for recursive queries, implementations describe
+/// how the backend renders [`SearchOrder`] ordering columns,
+/// [`CycleCheck`] cycle markers, the visited paths of
+/// [`Graph`](crate::graph::Graph) traversals, and the text concatenation used
+/// by [`Breadcrumbs`](crate::breadcrumb::Breadcrumbs).
+pub trait RecursiveBackend: Backend {
+    /// Whether the backend accepts the SQL-standard `SEARCH` clause.
+    ///
+    /// Defaults to `false`, so an empty implementation marks a backend that
+    /// runs recursive CTEs without any of the optional clauses.
+    const NATIVE_SEARCH: bool = false;
+
+    /// Whether the backend accepts the SQL-standard `CYCLE` clause.
+    ///
+    /// Defaults to `false`.
+    const NATIVE_CYCLE: bool = false;
+
+    /// Whether the backend accepts `AS MATERIALIZED` and
+    /// `AS NOT MATERIALIZED`.
+    ///
+    /// Defaults to `false`, in which case any [`Materialization`] other than
+    /// the default fails to render.
+    const MATERIALIZATION_HINTS: bool = false;
+
+    /// Render the expression computing an emulated search key.
+    ///
+    /// Only called when [`Self::NATIVE_SEARCH`] is `false`.
+    ///
+    /// # Errors
+    ///
+    /// The default implementation returns a query builder error because the
+    /// backend has no emulation.
+    fn walk_search_key(key: &SearchKey, out: &mut AstPass<'_, '_, Self>) -> QueryResult<()> {
+        let _ = (key, out);
+        Err(Error::QueryBuilderError(
+            "search keys are not supported by this backend".into(),
+        ))
+    }
+
+    /// Render the marker and path columns of an emulated cycle check.
+    ///
+    /// Only called when [`Self::NATIVE_CYCLE`] is `false`.
+    ///
+    /// # Errors
+    ///
+    /// The default implementation returns a query builder error because the
+    /// backend has no emulation.
+    fn walk_cycle_key(key: &CycleKey, out: &mut AstPass<'_, '_, Self>) -> QueryResult<()> {
+        let _ = (key, out);
+        Err(Error::QueryBuilderError(
+            "cycle keys are not supported by this backend".into(),
+        ))
+    }
+
+    /// Render the visited path of a graph traversal, ending with `node`.
+    ///
+    /// `parent` names the CTE whose `path` column holds the path so far; the
+    /// seed passes `None` to start a path holding only `node`.
+    ///
+    /// # Errors
+    ///
+    /// The default implementation returns a query builder error because the
+    /// backend has no path representation.
+    fn walk_graph_path<'b, Node>(
+        parent: Option<&str>,
+        path: &str,
+        node: &'b Node,
+        out: &mut AstPass<'_, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Node: QueryFragment<Self>,
+    {
+        let _ = (parent, path, node, out);
+        Err(Error::QueryBuilderError(
+            "graph paths are not supported by this backend".into(),
+        ))
+    }
+
+    /// Render a condition holding when `node` is not on the path stored in
+    /// `parent.path`.
+    ///
+    /// # Errors
+    ///
+    /// The default implementation returns a query builder error because the
+    /// backend has no path representation.
+    fn walk_graph_unvisited<'b, Node>(
+        parent: &str,
+        path: &str,
+        node: &'b Node,
+        out: &mut AstPass<'_, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Node: QueryFragment<Self>,
+    {
+        let _ = (parent, path, node, out);
+        Err(Error::QueryBuilderError(
+            "graph paths are not supported by this backend".into(),
+        ))
+    }
+
+    /// Render a condition on whether an item occurs on a `/`-delimited path.
+    ///
+    /// `path` renders text holding the path with a leading and trailing `/`,
+    /// and `item` renders the text of one element, so that `1` does not
+    /// match `/11/2/`. The condition holds when the item is on the path if
+    /// `on_path` is `true`, and when it is not otherwise. Emulated cycle
+    /// checks, graph paths, and breadcrumb keys stored as text all use it.
+    ///
+    /// # Errors
+    ///
+    /// The default implementation returns a query builder error because the
+    /// backend has no text search for paths.
+    fn walk_path_check<'a, 'b, Path, Item>(
+        on_path: bool,
+        path: Path,
+        item: Item,
+        out: &mut AstPass<'a, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Path: FnOnce(&mut AstPass<'a, 'b, Self>) -> QueryResult<()>,
+        Item: FnOnce(&mut AstPass<'a, 'b, Self>) -> QueryResult<()>,
+    {
+        let _ = (on_path, path, item, out);
+        Err(Error::QueryBuilderError(
+            "delimited paths are not supported by this backend".into(),
+        ))
+    }
+
+    /// Render the text concatenation of `head`, the literal `separator`, and
+    /// `tail`.
+    ///
+    /// # Errors
+    ///
+    /// The default implementation returns a query builder error because the
+    /// backend has no portable concatenation operator.
+    fn walk_concat<'b, Head, Tail>(
+        head: &'b Head,
+        separator: &str,
+        tail: &'b Tail,
+        out: &mut AstPass<'_, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Head: QueryFragment<Self>,
+        Tail: QueryFragment<Self>,
+    {
+        let _ = (head, separator, tail, out);
+        Err(Error::QueryBuilderError(
+            "text concatenation is not supported by this backend".into(),
+        ))
+    }
+}
 
 #[cfg(feature = "sqlite")]
-impl RecursiveBackend for diesel::sqlite::Sqlite {}
+impl RecursiveBackend for diesel::sqlite::Sqlite {
+    const NATIVE_SEARCH: bool = false;
+    const NATIVE_CYCLE: bool = false;
+    const MATERIALIZATION_HINTS: bool = true;
+
+    fn walk_search_key(key: &SearchKey, out: &mut AstPass<'_, '_, Self>) -> QueryResult<()> {
+        crate::search::walk_sqlite_key(key, out)
+    }
+
+    fn walk_cycle_key(key: &CycleKey, out: &mut AstPass<'_, '_, Self>) -> QueryResult<()> {
+        crate::cycle::walk_sqlite_key(key, out)
+    }
+
+    fn walk_graph_path<'b, Node>(
+        parent: Option<&str>,
+        path: &str,
+        node: &'b Node,
+        out: &mut AstPass<'_, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Node: QueryFragment<Self>,
+    {
+        crate::graph::walk_sqlite_path(parent, path, node, out)
+    }
+
+    fn walk_graph_unvisited<'b, Node>(
+        parent: &str,
+        path: &str,
+        node: &'b Node,
+        out: &mut AstPass<'_, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Node: QueryFragment<Self>,
+    {
+        Self::walk_path_check(
+            false,
+            |pass| push_qualified(pass, parent, path),
+            |pass| node.walk_ast(pass.reborrow()),
+            out,
+        )
+    }
+
+    fn walk_path_check<'a, 'b, Path, Item>(
+        on_path: bool,
+        path: Path,
+        item: Item,
+        out: &mut AstPass<'a, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Path: FnOnce(&mut AstPass<'a, 'b, Self>) -> QueryResult<()>,
+        Item: FnOnce(&mut AstPass<'a, 'b, Self>) -> QueryResult<()>,
+    {
+        out.push_sql("instr(");
+        path(out)?;
+        out.push_sql(", '/' || ");
+        item(out)?;
+        out.push_sql(if on_path {
+            " || '/') > 0"
+        } else {
+            " || '/') = 0"
+        });
+        Ok(())
+    }
+
+    fn walk_concat<'b, Head, Tail>(
+        head: &'b Head,
+        separator: &str,
+        tail: &'b Tail,
+        out: &mut AstPass<'_, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Head: QueryFragment<Self>,
+        Tail: QueryFragment<Self>,
+    {
+        crate::breadcrumb::walk_pipe_concat(head, separator, tail, out)
+    }
+}
 
 #[cfg(feature = "postgres")]
-impl RecursiveBackend for diesel::pg::Pg {}
+impl RecursiveBackend for diesel::pg::Pg {
+    const NATIVE_SEARCH: bool = true;
+    const NATIVE_CYCLE: bool = true;
+    const MATERIALIZATION_HINTS: bool = true;
+
+    fn walk_graph_path<'b, Node>(
+        parent: Option<&str>,
+        path: &str,
+        node: &'b Node,
+        out: &mut AstPass<'_, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Node: QueryFragment<Self>,
+    {
+        crate::graph::walk_pg_path(parent, path, node, out)
+    }
+
+    fn walk_graph_unvisited<'b, Node>(
+        parent: &str,
+        path: &str,
+        node: &'b Node,
+        out: &mut AstPass<'_, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Node: QueryFragment<Self>,
+    {
+        crate::graph::walk_pg_unvisited(parent, path, node, out)
+    }
+
+    fn walk_concat<'b, Head, Tail>(
+        head: &'b Head,
+        separator: &str,
+        tail: &'b Tail,
+        out: &mut AstPass<'_, 'b, Self>,
+    ) -> QueryResult<()>
+    where
+        Head: QueryFragment<Self>,
+        Tail: QueryFragment<Self>,
+    {
+        crate::breadcrumb::walk_pipe_concat(head, separator, tail, out)
+    }
+}
+
+/// `MySQL` 8 and `MariaDB` 10.2+ run recursive CTEs but have neither the
+/// `SEARCH` and `CYCLE` clauses nor an emulation here, so search and cycle
+/// keys, graph paths, and breadcrumb paths fail to render. Neither accepts
+/// materialization hints.
+#[cfg(feature = "mysql")]
+impl RecursiveBackend for diesel::mysql::Mysql {
+    const NATIVE_SEARCH: bool = false;
+    const NATIVE_CYCLE: bool = false;
+    const MATERIALIZATION_HINTS: bool = false;
+}
+
+/// Definitions appended after the first CTE of a `WITH` clause.
+///
+/// The empty list `()` renders nothing. A single [`CteDefinition`] or
+/// [`RecursiveDefinition`] renders one entry, and the pair `(Prev, Next)`
+/// renders `Prev` followed by `Next`, which is how [`WithCte::with`] and
+/// [`WithCte::with_recursive`] grow the list.
+pub trait CteList<DB: Backend> {
+    /// Whether any definition in the list is recursive, which requires the
+    /// clause to open with `WITH RECURSIVE`.
+    const RECURSIVE: bool;
+
+    /// Append the names of every definition in the list to `names`.
+    fn collect_names<'a>(&'a self, names: &mut Vec<&'a str>);
+
+    /// Render each definition prefixed with `, `.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a definition fails to render.
+    fn walk_definitions<'b>(&'b self, out: &mut AstPass<'_, 'b, DB>) -> QueryResult<()>;
+}
+
+impl<DB: Backend> CteList<DB> for () {
+    const RECURSIVE: bool = false;
+
+    fn collect_names<'a>(&'a self, _names: &mut Vec<&'a str>) {}
+
+    fn walk_definitions<'b>(&'b self, _out: &mut AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        Ok(())
+    }
+}
+
+impl<DB, Prev, Next> CteList<DB> for (Prev, Next)
+where
+    DB: Backend,
+    Prev: CteList<DB>,
+    Next: CteList<DB>,
+{
+    const RECURSIVE: bool = Prev::RECURSIVE || Next::RECURSIVE;
+
+    fn collect_names<'a>(&'a self, names: &mut Vec<&'a str>) {
+        self.0.collect_names(names);
+        self.1.collect_names(names);
+    }
+
+    fn walk_definitions<'b>(&'b self, out: &mut AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        self.0.walk_definitions(out)?;
+        self.1.walk_definitions(out)
+    }
+}
+
+/// Definition list produced by appending a [`CteDefinition`] to `Rest`.
+pub type AppendCte<Rest, Cols, Cte> = (Rest, CteDefinition<Cols, Cte>);
+
+/// Definition list produced by appending a [`RecursiveDefinition`] to `Rest`.
+pub type AppendRecursive<Rest, Cols, Seed, Step> = (Rest, RecursiveDefinition<Cols, Seed, Step>);
+
+/// A named, non-recursive CTE appended to an existing `WITH` clause.
+#[derive(Debug, Clone)]
+pub struct CteDefinition<Cols, Cte> {
+    pub(crate) cte_name: Cow<'static, str>,
+    pub(crate) columns: CteColumns<Cols>,
+    pub(crate) cte: Cte,
+    pub(crate) materialization: Materialization,
+}
+
+impl<Cols, Cte> CteDefinition<Cols, Cte> {
+    /// Name the query `cte` and attach its column list.
+    pub fn new<ColSpec>(cte_name: impl Into<Cow<'static, str>>, columns: ColSpec, cte: Cte) -> Self
+    where
+        ColSpec: Into<CteColumns<Cols>>,
+    {
+        Self {
+            cte_name: cte_name.into(),
+            columns: columns.into(),
+            cte,
+            materialization: Materialization::Default,
+        }
+    }
+
+    /// Render `materialization` between the column list and the query.
+    #[must_use]
+    pub const fn with_materialization(mut self, materialization: Materialization) -> Self {
+        self.materialization = materialization;
+        self
+    }
+}
+
+// Appended definitions carry runtime names that no type pins.
+impl<Cols, Cte> QueryId for CteDefinition<Cols, Cte> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<DB, Cols, Cte> CteList<DB> for CteDefinition<Cols, Cte>
+where
+    DB: RecursiveBackend,
+    Cte: QueryFragment<DB>,
+{
+    const RECURSIVE: bool = false;
+
+    fn collect_names<'a>(&'a self, names: &mut Vec<&'a str>) {
+        names.push(&self.cte_name);
+    }
+
+    fn walk_definitions<'b>(&'b self, out: &mut AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        out.push_sql(", ");
+        push_definition_header(
+            out,
+            &self.cte_name,
+            &self.columns.names.as_strs(),
+            self.materialization,
+        )?;
+        self.cte.walk_ast(out.reborrow())?;
+        out.push_sql(")");
+        Ok(())
+    }
+}
+
+/// A named, recursive CTE appended to an existing `WITH` clause.
+#[derive(Debug, Clone)]
+pub struct RecursiveDefinition<Cols, Seed, Step> {
+    pub(crate) cte_name: Cow<'static, str>,
+    pub(crate) columns: CteColumns<Cols>,
+    pub(crate) seed: Seed,
+    pub(crate) step: Step,
+    pub(crate) union: UnionKind,
+    pub(crate) materialization: Materialization,
+}
+
+impl<Cols, Seed, Step> RecursiveDefinition<Cols, Seed, Step> {
+    /// Name the union of `terms.seed` and `terms.step` and attach its column
+    /// list.
+    pub fn new<ColSpec>(
+        cte_name: impl Into<Cow<'static, str>>,
+        columns: ColSpec,
+        terms: RecursiveTerms<Seed, Step>,
+    ) -> Self
+    where
+        ColSpec: Into<CteColumns<Cols>>,
+    {
+        Self {
+            cte_name: cte_name.into(),
+            columns: columns.into(),
+            seed: terms.seed,
+            step: terms.step,
+            union: terms.union,
+            materialization: terms.materialization,
+        }
+    }
+}
+
+impl<Cols, Seed, Step> QueryId for RecursiveDefinition<Cols, Seed, Step> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<DB, Cols, Seed, Step> CteList<DB> for RecursiveDefinition<Cols, Seed, Step>
+where
+    DB: RecursiveBackend,
+    Seed: QueryFragment<DB>,
+    Step: QueryFragment<DB>,
+{
+    const RECURSIVE: bool = true;
+
+    fn collect_names<'a>(&'a self, names: &mut Vec<&'a str>) {
+        names.push(&self.cte_name);
+    }
+
+    fn walk_definitions<'b>(&'b self, out: &mut AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        out.push_sql(", ");
+        push_definition_header(
+            out,
+            &self.cte_name,
+            &self.columns.names.as_strs(),
+            self.materialization,
+        )?;
+        push_recursive_union(out, &self.seed, self.union, &self.step)?;
+        out.push_sql(")");
+        Ok(())
+    }
+}
 
 /// Representation of a recursive CTE query.
+///
+/// `Rest` holds any definitions appended with [`WithRecursive::with`] or
+/// [`WithRecursive::with_recursive`].
 #[derive(Debug, Clone)]
-pub struct WithRecursive<DB: Backend, Cols, Seed, Step, Body> {
-    pub(crate) cte_name: &'static str,
-    pub(crate) columns: Columns<Cols>,
+pub struct WithRecursive<DB: Backend, Cols, Seed, Step, Body, Rest = ()> {
+    pub(crate) cte_name: Cow<'static, str>,
+    pub(crate) columns: CteColumns<Cols>,
     pub(crate) seed: Seed,
     pub(crate) step: Step,
+    pub(crate) union: UnionKind,
+    pub(crate) materialization: Materialization,
+    pub(crate) search: Option<SearchOrder>,
+    pub(crate) cycle: Option<CycleCheck>,
+    pub(crate) depth: Option<DepthLimit>,
     pub(crate) body: Body,
+    pub(crate) rest: Rest,
     pub(crate) _marker: std::marker::PhantomData<DB>,
 }
 
-impl<DB, Cols, Seed, Step, Body> QueryFragment<DB> for WithRecursive<DB, Cols, Seed, Step, Body>
+impl<DB, Cols, Seed, Step, Body, Rest> WithRecursive<DB, Cols, Seed, Step, Body, Rest>
+where
+    DB: RecursiveBackend,
+{
+    /// Append a non-recursive CTE to this query's `WITH RECURSIVE` clause.
+    ///
+    /// See [`WithCte::with`] for the naming rules.
+    pub fn with<NextCols, NextCte, ColSpec, Kind>(
+        self,
+        cte_name: impl Into<Cow<'static, str>>,
+        columns: ColSpec,
+        cte: NextCte,
+    ) -> WithRecursive<DB, Cols, Seed, Step, Body, AppendCte<Rest, NextCols, NextCte>>
+    where
+        NextCte: CteQuery<DB, Kind>,
+        ColSpec: Into<CteColumns<NextCols>>,
+    {
+        self.with_definition(CteDefinition::new(cte_name, columns, cte))
+    }
+
+    /// Append another recursive CTE to this query's `WITH RECURSIVE` clause.
+    pub fn with_recursive<NextCols, NextSeed, NextStep, ColSpec>(
+        self,
+        cte_name: impl Into<Cow<'static, str>>,
+        columns: ColSpec,
+        terms: RecursiveTerms<NextSeed, NextStep>,
+    ) -> WithRecursive<
+        DB,
+        Cols,
+        Seed,
+        Step,
+        Body,
+        AppendRecursive<Rest, NextCols, NextSeed, NextStep>,
+    >
+    where
+        NextSeed: QueryFragment<DB>,
+        NextStep: QueryFragment<DB>,
+        ColSpec: Into<CteColumns<NextCols>>,
+    {
+        self.with_definition(RecursiveDefinition::new(cte_name, columns, terms))
+    }
+
+    /// Append a prebuilt [`CteDefinition`] or [`RecursiveDefinition`].
+    ///
+    /// Use this when the definition needs options that [`Self::with`] does not
+    /// take, such as a [`Materialization`] hint.
+    pub fn with_definition<Next>(
+        self,
+        next: Next,
+    ) -> WithRecursive<DB, Cols, Seed, Step, Body, (Rest, Next)>
+    where
+        Next: CteList<DB>,
+    {
+        WithRecursive {
+            cte_name: self.cte_name,
+            columns: self.columns,
+            seed: self.seed,
+            step: self.step,
+            union: self.union,
+            materialization: self.materialization,
+            search: self.search,
+            cycle: self.cycle,
+            depth: self.depth,
+            body: self.body,
+            rest: (self.rest, next),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Box the query so its type depends only on the backend and the body's
+    /// SQL type.
+    pub fn into_boxed<'a>(self) -> BoxedCteQuery<'a, DB, Body::SqlType>
+    where
+        Self: QueryFragment<DB> + Send + 'a,
+        Body: Query,
+    {
+        BoxedCteQuery::new(self)
+    }
+}
+
+impl<DB, Cols, Seed, Step, Body, Rest> WithRecursive<DB, Cols, Seed, Step, Body, Rest>
+where
+    DB: RecursiveBackend,
+{
+    /// Expose a depth counter column on the CTE and stop recursing at
+    /// `depth.max()`.
+    ///
+    /// The seed is wrapped as a derived table selecting its columns followed
+    /// by the counter. The step is either a Diesel `SELECT` statement, which
+    /// gets the counter after its select list and the guard in its `WHERE`
+    /// clause, or a [`DepthTerm`](crate::depth::DepthTerm) for steps written
+    /// in raw SQL. The counter follows any search or cycle key, and the step
+    /// must refer to the CTE by its name.
+    #[must_use]
+    pub fn with_depth(
+        self,
+        depth: DepthLimit,
+    ) -> WithRecursive<DB, Cols, CountedSeed<Seed>, Step::Counted, Body, Rest>
+    where
+        Step: DepthStep,
+    {
+        WithRecursive {
+            seed: CountedSeed::new(self.seed, depth.clone()),
+            step: self.step.count_from_parent(&depth, self.cte_name.clone()),
+            cte_name: self.cte_name,
+            columns: self.columns,
+            union: self.union,
+            materialization: self.materialization,
+            search: self.search,
+            cycle: self.cycle,
+            depth: Some(depth),
+            body: self.body,
+            rest: self.rest,
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<DB, Cols, Seed, Step, Body, Rest> WithRecursive<DB, Cols, Seed, Step, Body, Rest>
+where
+    DB: RecursiveBackend,
+{
+    /// Column names of the CTE, including any emulated search or cycle
+    /// columns and the depth column.
+    ///
+    /// Backends with native `SEARCH` and `CYCLE` clauses add those generated
+    /// columns themselves, so they are only listed when emulated. The depth
+    /// counter follows them, as it is appended after the keys that end the
+    /// select lists. Generated columns are only listed when an explicit
+    /// column list is present.
+    fn column_names(&self) -> Cow<'_, [&str]> {
+        let listed = self.columns.names.as_strs();
+        let mut generated = Vec::new();
+        if let Some(order) = self.search.as_ref().filter(|_| !DB::NATIVE_SEARCH) {
+            generated.push(order.set());
+        }
+        if let Some(check) = self.cycle.as_ref().filter(|_| !DB::NATIVE_CYCLE) {
+            generated.extend([check.set(), check.using()]);
+        }
+        if let Some(depth) = &self.depth {
+            generated.push(depth.column());
+        }
+        if generated.is_empty() || listed.is_empty() {
+            return listed;
+        }
+        Cow::Owned(listed.iter().copied().chain(generated).collect())
+    }
+}
+
+impl<DB, Cols, Seed, Step, Body, Rest> WithRecursive<DB, Cols, Seed, Step, Body, Rest>
 where
     DB: Backend,
+{
+    /// Whether the query renders the SQL its static query id stands for: the
+    /// names pinned by `Cols` and none of the runtime options.
+    fn renders_pinned_sql(&self) -> bool
+    where
+        Cols: PinnedNames,
+    {
+        Cols::pins(&self.cte_name, &self.columns.names.as_strs())
+            && self.union == UnionKind::All
+            && self.materialization == Materialization::Default
+            && self.search.is_none()
+            && self.cycle.is_none()
+            && self.depth.is_none()
+    }
+
+    /// Replace the body, keeping every definition.
+    pub(crate) fn map_body<NewBody>(
+        self,
+        f: impl FnOnce(Body) -> NewBody,
+    ) -> WithRecursive<DB, Cols, Seed, Step, NewBody, Rest> {
+        WithRecursive {
+            cte_name: self.cte_name,
+            columns: self.columns,
+            seed: self.seed,
+            step: self.step,
+            union: self.union,
+            materialization: self.materialization,
+            search: self.search,
+            cycle: self.cycle,
+            depth: self.depth,
+            body: f(self.body),
+            rest: self.rest,
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<DB, Cols, Seed, Step, Body, Rest> QueryFragment<DB>
+    for WithRecursive<DB, Cols, Seed, Step, Body, Rest>
+where
+    DB: RecursiveBackend,
     Seed: QueryFragment<DB>,
     Step: QueryFragment<DB>,
     Body: QueryFragment<DB>,
+    Rest: CteList<DB>,
+    Cols: PinnedNames,
+    Self: QueryId,
 {
     fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        ensure_unique_cte_names::<DB, _>(&self.cte_name, &self.rest)?;
+        if Self::HAS_STATIC_QUERY_ID && !self.renders_pinned_sql() {
+            out.unsafe_to_cache_prepared();
+        }
         out.push_sql("WITH RECURSIVE ");
-        out.push_identifier(self.cte_name)?;
-        push_identifiers(&mut out, &self.columns)?;
-        out.push_sql(" AS (");
-        self.seed.walk_ast(out.reborrow())?;
-        out.push_sql(" UNION ALL ");
-        self.step.walk_ast(out.reborrow())?;
-        out.push_sql(") ");
+        let names = self.column_names();
+        push_definition_header(&mut out, &self.cte_name, &names, self.materialization)?;
+        push_recursive_union(&mut out, &self.seed, self.union, &self.step)?;
+        out.push_sql(")");
+        if let Some(search) = self.search.as_ref().filter(|_| DB::NATIVE_SEARCH) {
+            search.walk_clause(&mut out)?;
+        }
+        if let Some(cycle) = self.cycle.as_ref().filter(|_| DB::NATIVE_CYCLE) {
+            cycle.walk_clause(&mut out)?;
+        }
+        self.rest.walk_definitions(&mut out)?;
+        out.push_sql(" ");
         self.body.walk_ast(out.reborrow())
     }
 }
 
 /// Representation of a non-recursive CTE query.
+///
+/// `Rest` holds any definitions appended with [`WithCte::with`]; they are
+/// rendered after the first CTE under the same `WITH` keyword.
 #[derive(Debug, Clone)]
-pub struct WithCte<DB: Backend, Cols, Cte, Body> {
-    pub(crate) cte_name: &'static str,
-    pub(crate) columns: Columns<Cols>,
+pub struct WithCte<DB: Backend, Cols, Cte, Body, Rest = ()> {
+    pub(crate) cte_name: Cow<'static, str>,
+    pub(crate) columns: CteColumns<Cols>,
     pub(crate) cte: Cte,
+    pub(crate) materialization: Materialization,
     pub(crate) body: Body,
+    pub(crate) rest: Rest,
     pub(crate) _marker: std::marker::PhantomData<DB>,
 }
 
-impl<DB, Cols, Cte, Body> QueryFragment<DB> for WithCte<DB, Cols, Cte, Body>
+impl<DB, Cols, Cte, Body, Rest> WithCte<DB, Cols, Cte, Body, Rest>
 where
     DB: Backend,
+{
+    /// Append another named CTE to this query's `WITH` clause.
+    ///
+    /// The new definition may reference every CTE declared before it, and the
+    /// body can reference all of them. Names must be unique across the list;
+    /// duplicates are reported as a query builder error when the query is
+    /// rendered.
+    pub fn with<NextCols, NextCte, ColSpec, Kind>(
+        self,
+        cte_name: impl Into<Cow<'static, str>>,
+        columns: ColSpec,
+        cte: NextCte,
+    ) -> WithCte<DB, Cols, Cte, Body, AppendCte<Rest, NextCols, NextCte>>
+    where
+        DB: RecursiveBackend,
+        NextCte: CteQuery<DB, Kind>,
+        ColSpec: Into<CteColumns<NextCols>>,
+    {
+        self.with_definition(CteDefinition::new(cte_name, columns, cte))
+    }
+
+    /// Append a recursive CTE to this query's `WITH` clause.
+    ///
+    /// The clause is rendered as `WITH RECURSIVE` once any appended definition
+    /// is recursive, so plain CTEs declared earlier can feed the recursive one.
+    pub fn with_recursive<NextCols, NextSeed, NextStep, ColSpec>(
+        self,
+        cte_name: impl Into<Cow<'static, str>>,
+        columns: ColSpec,
+        terms: RecursiveTerms<NextSeed, NextStep>,
+    ) -> WithCte<DB, Cols, Cte, Body, AppendRecursive<Rest, NextCols, NextSeed, NextStep>>
+    where
+        DB: RecursiveBackend,
+        NextSeed: QueryFragment<DB>,
+        NextStep: QueryFragment<DB>,
+        ColSpec: Into<CteColumns<NextCols>>,
+    {
+        self.with_definition(RecursiveDefinition::new(cte_name, columns, terms))
+    }
+
+    /// Append a prebuilt [`CteDefinition`] or [`RecursiveDefinition`].
+    ///
+    /// Use this when the definition needs options that [`Self::with`] does not
+    /// take, such as a [`Materialization`] hint.
+    pub fn with_definition<Next>(self, next: Next) -> WithCte<DB, Cols, Cte, Body, (Rest, Next)>
+    where
+        Next: CteList<DB>,
+    {
+        WithCte {
+            cte_name: self.cte_name,
+            columns: self.columns,
+            cte: self.cte,
+            materialization: self.materialization,
+            body: self.body,
+            rest: (self.rest, next),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Box the query so its type depends only on the backend and the body's
+    /// SQL type.
+    pub fn into_boxed<'a>(self) -> BoxedCteQuery<'a, DB, Body::SqlType>
+    where
+        Self: QueryFragment<DB> + Send + 'a,
+        Body: Query,
+    {
+        BoxedCteQuery::new(self)
+    }
+
+    /// Whether the query renders the SQL its static query id stands for: the
+    /// names pinned by `Cols` and no materialization hint.
+    fn renders_pinned_sql(&self) -> bool
+    where
+        Cols: PinnedNames,
+    {
+        Cols::pins(&self.cte_name, &self.columns.names.as_strs())
+            && self.materialization == Materialization::Default
+    }
+
+    /// Replace the body, keeping every definition.
+    pub(crate) fn map_body<NewBody>(
+        self,
+        f: impl FnOnce(Body) -> NewBody,
+    ) -> WithCte<DB, Cols, Cte, NewBody, Rest> {
+        WithCte {
+            cte_name: self.cte_name,
+            columns: self.columns,
+            cte: self.cte,
+            materialization: self.materialization,
+            body: f(self.body),
+            rest: self.rest,
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<DB, Cols, Cte, Body, Rest> QueryFragment<DB> for WithCte<DB, Cols, Cte, Body, Rest>
+where
+    DB: RecursiveBackend,
     Cte: QueryFragment<DB>,
     Body: QueryFragment<DB>,
+    Rest: CteList<DB>,
+    Cols: PinnedNames,
+    Self: QueryId,
 {
     fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
-        out.push_sql("WITH ");
-        out.push_identifier(self.cte_name)?;
-        push_identifiers(&mut out, &self.columns)?;
-        out.push_sql(" AS (");
+        ensure_unique_cte_names::<DB, _>(&self.cte_name, &self.rest)?;
+        if Self::HAS_STATIC_QUERY_ID && !self.renders_pinned_sql() {
+            out.unsafe_to_cache_prepared();
+        }
+        out.push_sql(if Rest::RECURSIVE {
+            "WITH RECURSIVE "
+        } else {
+            "WITH "
+        });
+        push_definition_header(
+            &mut out,
+            &self.cte_name,
+            &self.columns.names.as_strs(),
+            self.materialization,
+        )?;
         self.cte.walk_ast(out.reborrow())?;
-        out.push_sql(") ");
+        out.push_sql(")");
+        self.rest.walk_definitions(&mut out)?;
+        out.push_sql(" ");
         self.body.walk_ast(out.reborrow())
     }
 }
 
-impl_cte_traits!(WithRecursive<Seed, Step, Body>, Body);
-impl_cte_traits!(WithCte<Cte, Body>, Body);
+impl_cte_traits!(WithRecursive<Seed, Step, Body, Rest>, Body);
+impl_cte_traits!(WithCte<Cte, Body, Rest>, Body);
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
         builders::{self, RecursiveParts},
+        depth::DepthTerm,
         test_support::normalise_debug_sql,
     };
     use diesel::{debug_query, dsl::sql, sql_types::Integer, sqlite::Sqlite};
@@ -223,4 +1164,494 @@ mod tests {
             "WITH RECURSIVE \"nums\" AS (SELECT 1 UNION ALL SELECT n + 1 FROM nums WHERE n < 2) SELECT n FROM nums"
         );
     }
+
+    #[test]
+    fn runtime_names_are_quoted_like_static_names() {
+        let cte_name = format!("report_{}", 7);
+        let query = builders::with_cte::<Sqlite, _, _, _, _>(
+            cte_name,
+            CteColumns::owned([String::from("total `sum`")]),
+            crate::builders::CteParts::new(sql::<Integer>("SELECT 42"), sql::<Integer>("SELECT 1")),
+        )
+        .with("static", &["n"], sql::<Integer>("SELECT 1"));
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert_eq!(
+            sql,
+            "WITH \"report_7\" (\"total \"\"sum\"\"\") AS (SELECT 42), \"static\" (\"n\") AS (SELECT 1) SELECT 1"
+        );
+    }
+
+    #[test]
+    fn query_ids_defer_to_rendered_sql() {
+        use diesel::{ExpressionMethods, IntoSql, QueryDsl};
+
+        diesel::table! {
+            nodes (id) {
+                id -> Integer,
+            }
+        }
+
+        fn has_static_id<T: QueryId>(_: &T) -> bool {
+            T::query_id().is_some()
+        }
+
+        let cte = || diesel::select(1.into_sql::<Integer>());
+        assert!(has_static_id(&cte()));
+
+        let cached = builders::with_cte::<Sqlite, _, _, _, _>(
+            "a",
+            &["n"],
+            crate::builders::CteParts::new(cte(), nodes::table.select(nodes::id)),
+        );
+        assert!(!has_static_id(&cached));
+        assert!(
+            QueryFragment::<Sqlite>::is_safe_to_cache_prepared(&cached, &Sqlite)
+                .expect("walk cached query")
+        );
+
+        let uncached = builders::with_cte::<Sqlite, _, _, _, _>(
+            "a",
+            &["n"],
+            crate::builders::CteParts::new(
+                cte(),
+                nodes::table
+                    .filter(nodes::id.eq_any(vec![1, 2]))
+                    .select(nodes::id),
+            ),
+        );
+        assert!(
+            !QueryFragment::<Sqlite>::is_safe_to_cache_prepared(&uncached, &Sqlite)
+                .expect("walk uncached query")
+        );
+    }
+
+    #[test]
+    fn typed_columns_pin_names_into_query_ids() {
+        use crate::columns::{Columns, CteTable};
+        use diesel::{ExpressionMethods, IntoSql, QueryDsl};
+
+        crate::cte_table! {
+            picked (id) {
+                id -> Integer,
+                rank -> Integer,
+            }
+        }
+
+        fn has_static_id<T: QueryId>(_: &T) -> bool {
+            T::query_id().is_some()
+        }
+
+        fn is_cached<T: QueryFragment<Sqlite>>(query: &T) -> bool {
+            query
+                .is_safe_to_cache_prepared(&Sqlite)
+                .expect("walk query")
+        }
+
+        let query = |name: &'static str,
+                     columns: Columns<(picked::id, picked::rank)>,
+                     materialization: Materialization| {
+            builders::with_cte::<Sqlite, _, _, _, _>(
+                name,
+                columns,
+                crate::builders::CteParts::new(
+                    diesel::select((1.into_sql::<Integer>(), 2.into_sql::<Integer>())),
+                    picked::table.filter(picked::rank.gt(0)).select(picked::id),
+                )
+                .with_materialization(materialization),
+            )
+        };
+        let columns = picked::table::columns();
+
+        let pinned = query("picked", columns, Materialization::Default);
+        assert!(has_static_id(&pinned));
+        assert!(is_cached(&pinned));
+
+        let renamed = query("other", columns, Materialization::Default);
+        assert!(has_static_id(&renamed));
+        assert!(!is_cached(&renamed));
+
+        let reordered = query(
+            "picked",
+            Columns::raw(&["rank", "id"]),
+            Materialization::Default,
+        );
+        assert!(!is_cached(&reordered));
+
+        let hinted = query("picked", columns, Materialization::Materialized);
+        assert!(!is_cached(&hinted));
+    }
+
+    #[test]
+    fn data_modifying_body_renders_after_with_clause() {
+        use diesel::{QueryDsl, sql_types::Bool};
+
+        diesel::table! {
+            nodes (id) {
+                id -> Integer,
+            }
+        }
+
+        let query = builders::with_recursive::<Sqlite, _, _, _, _, _>(
+            "subtree",
+            &["n"],
+            RecursiveParts::new(
+                sql::<Integer>("SELECT 2"),
+                sql::<Integer>("SELECT id FROM nodes JOIN subtree ON parent_id = n"),
+                diesel::delete(nodes::table.filter(sql::<Bool>("id IN (SELECT n FROM subtree)"))),
+            ),
+        );
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert_eq!(
+            sql,
+            "WITH RECURSIVE \"subtree\" (\"n\") AS (SELECT 2 UNION ALL SELECT id FROM nodes JOIN subtree ON parent_id = n) DELETE FROM \"nodes\" WHERE id IN (SELECT n FROM subtree)"
+        );
+    }
+
+    #[test]
+    fn materialization_hints_render_before_query() {
+        let query = builders::with_cte::<Sqlite, _, _, _, _>(
+            "seed",
+            &["value"],
+            crate::builders::CteParts::new(
+                sql::<Integer>("SELECT 42"),
+                sql::<Integer>("SELECT value FROM seed, other"),
+            )
+            .with_materialization(Materialization::Materialized),
+        )
+        .with_definition(
+            CteDefinition::new("other", &["n"], sql::<Integer>("SELECT 1"))
+                .with_materialization(Materialization::NotMaterialized),
+        );
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert_eq!(
+            sql,
+            "WITH \"seed\" (\"value\") AS MATERIALIZED (SELECT 42), \"other\" (\"n\") AS NOT MATERIALIZED (SELECT 1) SELECT value FROM seed, other"
+        );
+    }
+
+    #[test]
+    fn chained_ctes_render_under_single_with() {
+        let query = builders::with_cte::<Sqlite, _, _, _, _>(
+            "a",
+            &["x"],
+            crate::builders::CteParts::new(
+                sql::<Integer>("SELECT 1"),
+                sql::<Integer>("SELECT y FROM b"),
+            ),
+        )
+        .with("b", &["y"], sql::<Integer>("SELECT x + 1 FROM a"));
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert_eq!(
+            sql,
+            "WITH \"a\" (\"x\") AS (SELECT 1), \"b\" (\"y\") AS (SELECT x + 1 FROM a) SELECT y FROM b"
+        );
+    }
+
+    #[test]
+    fn duplicate_cte_names_are_rejected() {
+        let query = builders::with_cte::<Sqlite, _, _, _, _>(
+            "a",
+            &["x"],
+            crate::builders::CteParts::new(
+                sql::<Integer>("SELECT 1"),
+                sql::<Integer>("SELECT x FROM a"),
+            ),
+        )
+        .with("b", &["y"], sql::<Integer>("SELECT 2"))
+        .with("a", &["z"], sql::<Integer>("SELECT 3"));
+        let mut builder = diesel::sqlite::SqliteQueryBuilder::new();
+        let err = QueryFragment::<Sqlite>::to_sql(&query, &mut builder, &Sqlite)
+            .expect_err("duplicate CTE names should fail to render");
+        assert!(err.to_string().contains("duplicate CTE name 'a'"));
+    }
+
+    #[test]
+    fn recursive_entry_switches_keyword_to_with_recursive() {
+        let query = builders::with_cte::<Sqlite, _, _, _, _>(
+            "params",
+            &["lim"],
+            crate::builders::CteParts::new(
+                sql::<Integer>("SELECT 3"),
+                sql::<Integer>("SELECT n FROM nums"),
+            ),
+        )
+        .with_recursive(
+            "nums",
+            &["n"],
+            RecursiveTerms::new(
+                sql::<Integer>("SELECT 1"),
+                sql::<Integer>("SELECT n + 1 FROM nums, params WHERE n < lim"),
+            ),
+        );
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert_eq!(
+            sql,
+            "WITH RECURSIVE \"params\" (\"lim\") AS (SELECT 3), \"nums\" (\"n\") AS (SELECT 1 UNION ALL SELECT n + 1 FROM nums, params WHERE n < lim) SELECT n FROM nums"
+        );
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn recursive_query_accepts_appended_entries_on_postgres() {
+        use diesel::pg::Pg;
+
+        let query = builders::with_recursive::<Pg, _, _, _, _, _>(
+            "nums",
+            &["n"],
+            RecursiveParts::new(
+                sql::<Integer>("SELECT 1"),
+                sql::<Integer>("SELECT n + 1 FROM nums WHERE n < 3"),
+                sql::<Integer>("SELECT n FROM evens"),
+            ),
+        )
+        .with(
+            "evens",
+            &["n"],
+            sql::<Integer>("SELECT n FROM nums WHERE n % 2 = 0"),
+        )
+        .with_recursive(
+            "ones",
+            &["k"],
+            RecursiveTerms::new(
+                sql::<Integer>("SELECT 1"),
+                sql::<Integer>("SELECT k FROM ones WHERE false"),
+            ),
+        );
+        let sql = normalise_debug_sql(&debug_query::<Pg, _>(&query).to_string());
+        assert_eq!(
+            sql,
+            "WITH RECURSIVE \"nums\" (\"n\") AS (SELECT 1 UNION ALL SELECT n + 1 FROM nums WHERE n < 3), \"evens\" (\"n\") AS (SELECT n FROM nums WHERE n % 2 = 0), \"ones\" (\"k\") AS (SELECT 1 UNION ALL SELECT k FROM ones WHERE false) SELECT n FROM evens"
+        );
+    }
+
+    fn searched_tree_query<DB>(search: SearchOrder) -> impl QueryFragment<DB>
+    where
+        DB: RecursiveBackend + diesel::backend::DieselReserveSpecialization,
+    {
+        builders::with_recursive::<DB, _, _, _, _, _>(
+            "tree",
+            &["id"],
+            RecursiveParts::new(
+                crate::seed_query!(
+                    sql::<Integer>("SELECT n.id"),
+                    search.seed_key("n", "id"),
+                    sql::<Integer>(" FROM nodes n WHERE n.parent_id IS NULL"),
+                ),
+                crate::step_query!(
+                    sql::<Integer>("SELECT n.id"),
+                    search.step_key("n", "id", "tree"),
+                    sql::<Integer>(" FROM nodes n JOIN tree ON n.parent_id = tree.id"),
+                ),
+                sql::<Integer>("SELECT id FROM tree ORDER BY ord"),
+            )
+            .with_search(search),
+        )
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn search_renders_native_clause_on_postgres() {
+        use diesel::pg::Pg;
+
+        let query = searched_tree_query::<Pg>(SearchOrder::depth_first("id", "ord"));
+        let sql = normalise_debug_sql(&debug_query::<Pg, _>(&query).to_string());
+        assert_eq!(
+            sql,
+            "WITH RECURSIVE \"tree\" (\"id\") AS (SELECT n.id FROM nodes n WHERE n.parent_id IS NULL UNION ALL SELECT n.id FROM nodes n JOIN tree ON n.parent_id = tree.id) SEARCH DEPTH FIRST BY \"id\" SET \"ord\" SELECT id FROM tree ORDER BY ord"
+        );
+    }
+
+    #[test]
+    fn search_is_emulated_on_sqlite() {
+        let query = searched_tree_query::<Sqlite>(SearchOrder::depth_first("id", "ord"));
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert_eq!(
+            sql,
+            "WITH RECURSIVE \"tree\" (\"id\", \"ord\") AS (SELECT n.id, printf('%020d', \"n\".\"id\") AS \"ord\" FROM nodes n WHERE n.parent_id IS NULL UNION ALL SELECT n.id, \"tree\".\"ord\" || printf('%020d', \"n\".\"id\") AS \"ord\" FROM nodes n JOIN tree ON n.parent_id = tree.id) SELECT id FROM tree ORDER BY ord"
+        );
+    }
+
+    fn cycle_checked_walk<DB>(check: CycleCheck) -> impl QueryFragment<DB>
+    where
+        DB: RecursiveBackend + diesel::backend::DieselReserveSpecialization,
+    {
+        builders::with_recursive::<DB, _, _, _, _, _>(
+            "walk",
+            &["id"],
+            RecursiveParts::new(
+                crate::seed_query!(
+                    sql::<Integer>("SELECT s.id"),
+                    check.seed_key("s", "id"),
+                    sql::<Integer>(" FROM (SELECT 1 AS id) s"),
+                ),
+                crate::step_query!(
+                    sql::<Integer>("SELECT e.dst"),
+                    check.step_key("e", "dst", "walk"),
+                    sql::<Integer>(" FROM edges e JOIN walk ON e.src = walk.id WHERE "),
+                    check.step_guard("walk"),
+                ),
+                sql::<Integer>("SELECT id FROM walk"),
+            )
+            .with_cycle(check),
+        )
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn cycle_renders_native_clause_on_postgres() {
+        use diesel::pg::Pg;
+
+        let query = cycle_checked_walk::<Pg>(CycleCheck::new("id", "is_cycle", "path"));
+        let sql = normalise_debug_sql(&debug_query::<Pg, _>(&query).to_string());
+        assert_eq!(
+            sql,
+            "WITH RECURSIVE \"walk\" (\"id\") AS (SELECT s.id FROM (SELECT 1 AS id) s UNION ALL SELECT e.dst FROM edges e JOIN walk ON e.src = walk.id WHERE TRUE) CYCLE \"id\" SET \"is_cycle\" USING \"path\" SELECT id FROM walk"
+        );
+    }
+
+    #[test]
+    fn cycle_columns_are_listed_when_emulated() {
+        let query = cycle_checked_walk::<Sqlite>(CycleCheck::new("id", "is_cycle", "path"));
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert!(
+            sql.starts_with(
+                "WITH RECURSIVE \"walk\" (\"id\", \"is_cycle\", \"path\") AS (SELECT s.id, 0 AS \"is_cycle\""
+            ),
+            "unexpected SQL: {sql}"
+        );
+        assert!(sql.contains("WHERE NOT \"walk\".\"is_cycle\") SELECT id FROM walk"));
+    }
+
+    fn depth_limited_walk<DB>(check: CycleCheck) -> impl QueryFragment<DB>
+    where
+        DB: RecursiveBackend + diesel::backend::DieselReserveSpecialization,
+    {
+        builders::with_recursive::<DB, _, _, _, _, _>(
+            "walk",
+            &["id"],
+            RecursiveParts::new(
+                DepthTerm::new(
+                    crate::seed_query!(sql::<Integer>("s.id"), check.seed_key("s", "id")),
+                    sql::<Integer>("(SELECT 1 AS id) s"),
+                ),
+                DepthTerm::new(
+                    crate::step_query!(sql::<Integer>("e.dst"), check.step_key("e", "dst", "walk")),
+                    sql::<Integer>("edges e JOIN walk ON e.src = walk.id"),
+                )
+                .filter(check.step_guard("walk")),
+                sql::<Integer>("SELECT id FROM walk"),
+            )
+            .with_cycle(check),
+        )
+        .with_depth(DepthLimit::new("depth", 4))
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn depth_counter_and_guard_are_added_to_the_terms() {
+        use diesel::pg::Pg;
+
+        let query = depth_limited_walk::<Pg>(CycleCheck::new("id", "is_cycle", "path"));
+        let sql = normalise_debug_sql(&debug_query::<Pg, _>(&query).to_string());
+        assert_eq!(
+            sql,
+            "WITH RECURSIVE \"walk\" (\"id\", \"depth\") AS (SELECT \"seed\".*, 0 AS \"depth\" FROM (SELECT s.id FROM (SELECT 1 AS id) s) AS \"seed\" UNION ALL SELECT e.dst, \"walk\".\"depth\" + 1 AS \"depth\" FROM edges e JOIN walk ON e.src = walk.id WHERE \"walk\".\"depth\" < 4 AND (TRUE)) CYCLE \"id\" SET \"is_cycle\" USING \"path\" SELECT id FROM walk"
+        );
+    }
+
+    #[test]
+    fn depth_column_is_listed_after_emulated_columns() {
+        let query = depth_limited_walk::<Sqlite>(CycleCheck::new("id", "is_cycle", "path"));
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert!(
+            sql.starts_with(
+                "WITH RECURSIVE \"walk\" (\"id\", \"is_cycle\", \"path\", \"depth\") AS (SELECT \"seed\".*, 0 AS \"depth\" FROM (SELECT s.id, 0 AS \"is_cycle\", '/' || \"s\".\"id\" || '/' AS \"path\" FROM"
+            ),
+            "unexpected SQL: {sql}"
+        );
+        assert!(
+            sql.contains("WHERE \"walk\".\"depth\" < 4 AND (NOT \"walk\".\"is_cycle\"))"),
+            "unexpected SQL: {sql}"
+        );
+    }
+
+    #[cfg(feature = "mysql")]
+    #[test]
+    fn search_and_cycle_keys_are_rejected_on_mysql() {
+        use crate::test_support::assert_query_builder_error;
+        use diesel::mysql::{Mysql, MysqlQueryBuilder};
+
+        let search = searched_tree_query::<Mysql>(SearchOrder::depth_first("id", "ord"));
+        let search_err = search
+            .to_sql(&mut MysqlQueryBuilder::new(), &Mysql)
+            .expect_err("MySQL has no search emulation");
+        assert_query_builder_error(&search_err, "search keys are not supported");
+
+        let cycle = cycle_checked_walk::<Mysql>(CycleCheck::new("id", "is_cycle", "path"));
+        let cycle_err = cycle
+            .to_sql(&mut MysqlQueryBuilder::new(), &Mysql)
+            .expect_err("MySQL has no cycle emulation");
+        assert_query_builder_error(&cycle_err, "cycle keys are not supported");
+    }
+
+    #[cfg(feature = "mysql")]
+    #[test]
+    fn plain_ctes_render_on_mysql() {
+        use diesel::mysql::Mysql;
+
+        let query = builders::with_cte::<Mysql, _, _, _, _>(
+            "seed",
+            &["value"],
+            crate::builders::CteParts::new(
+                sql::<Integer>("SELECT 42"),
+                sql::<Integer>("SELECT value FROM seed"),
+            ),
+        );
+        let sql = debug_query::<Mysql, _>(&query).to_string();
+        assert!(
+            sql.starts_with("WITH `seed` (`value`) AS (SELECT 42) SELECT value FROM seed"),
+            "unexpected SQL: {sql}"
+        );
+    }
+
+    #[cfg(feature = "mysql")]
+    #[test]
+    fn materialization_hints_are_rejected_on_mysql() {
+        use crate::test_support::assert_query_builder_error;
+        use diesel::mysql::{Mysql, MysqlQueryBuilder};
+
+        for materialization in [
+            Materialization::Materialized,
+            Materialization::NotMaterialized,
+        ] {
+            let plain = builders::with_cte::<Mysql, _, _, _, _>(
+                "seed",
+                &["value"],
+                crate::builders::CteParts::new(
+                    sql::<Integer>("SELECT 42"),
+                    sql::<Integer>("SELECT value FROM seed"),
+                )
+                .with_materialization(materialization),
+            );
+            let plain_err = plain
+                .to_sql(&mut MysqlQueryBuilder::new(), &Mysql)
+                .expect_err("MySQL has no materialization hints");
+            assert_query_builder_error(&plain_err, "materialization hints are not supported");
+
+            let recursive = builders::with_recursive::<Mysql, _, _, _, _, _>(
+                "nums",
+                &["n"],
+                RecursiveParts::new(
+                    sql::<Integer>("SELECT 1"),
+                    sql::<Integer>("SELECT n + 1 FROM nums WHERE n < 2"),
+                    sql::<Integer>("SELECT n FROM nums"),
+                )
+                .with_materialization(materialization),
+            );
+            let recursive_err = recursive
+                .to_sql(&mut MysqlQueryBuilder::new(), &Mysql)
+                .expect_err("MySQL has no materialization hints");
+            assert_query_builder_error(&recursive_err, "materialization hints are not supported");
+        }
+    }
 }
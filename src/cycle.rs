@@ -0,0 +1,305 @@
+//! Portable `CYCLE` detection for recursive CTEs.
+//!
+//! [`CycleCheck`] adds a boolean cycle marker and a path column to a
+//! recursive CTE. Rows that revisit a value already on their path are marked
+//! and not expanded further, so traversals over cyclic data terminate.
+//! `PostgreSQL` renders the SQL-standard `CYCLE ... SET ... USING ...` clause
+//! natively. Backends without it emulate the clause: the marker and path
+//! columns are appended to the CTE's column list and computed by
+//! [`CycleKey`] fragments spliced into the seed and step projections, while a
+//! [`CycleGuard`] in the step's `WHERE` clause stops expansion of marked rows.
+
+use std::borrow::Cow;
+
+use diesel::{
+    query_builder::{AstPass, QueryFragment, QueryId},
+    result::QueryResult,
+};
+
+use crate::cte::RecursiveBackend;
+use crate::cte::push_qualified;
+
+/// Cycle marker and path columns added to a recursive CTE.
+///
+/// `column` names the CTE column whose values identify a row, `set` names
+/// the generated marker column and `using` names the generated path column.
+///
+/// `PostgreSQL` stores the path as an array of records; emulated paths are
+/// `/`-delimited strings, so emulated `column` values must not contain `/`.
+/// Names may be static strings or built at runtime.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleCheck {
+    pub(crate) column: Cow<'static, str>,
+    pub(crate) set: Cow<'static, str>,
+    pub(crate) using: Cow<'static, str>,
+}
+
+impl CycleCheck {
+    /// Detect cycles on `column`, exposing the marker as `set` and the path as
+    /// `using`.
+    #[must_use]
+    pub fn new(
+        column: impl Into<Cow<'static, str>>,
+        set: impl Into<Cow<'static, str>>,
+        using: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        Self {
+            column: column.into(),
+            set: set.into(),
+            using: using.into(),
+        }
+    }
+
+    /// Marker and path fragment to append to the seed's select list.
+    ///
+    /// `table.column` is the seed's source for the value of the checked
+    /// column.
+    #[must_use]
+    pub fn seed_key(
+        &self,
+        table: impl Into<Cow<'static, str>>,
+        column: impl Into<Cow<'static, str>>,
+    ) -> CycleKey {
+        CycleKey {
+            check: self.clone(),
+            table: table.into(),
+            column: column.into(),
+            parent: None,
+        }
+    }
+
+    /// Marker and path fragment to append to the step's select list.
+    ///
+    /// `table.column` is the step's source for the new row's checked value
+    /// and `parent` is the name (or alias) under which the step references the
+    /// CTE itself.
+    #[must_use]
+    pub fn step_key(
+        &self,
+        table: impl Into<Cow<'static, str>>,
+        column: impl Into<Cow<'static, str>>,
+        parent: impl Into<Cow<'static, str>>,
+    ) -> CycleKey {
+        CycleKey {
+            check: self.clone(),
+            table: table.into(),
+            column: column.into(),
+            parent: Some(parent.into()),
+        }
+    }
+
+    /// Boolean condition for the step's `WHERE` clause.
+    ///
+    /// Renders `TRUE` where the `CYCLE` clause is native and otherwise
+    /// excludes parents already marked as cycles.
+    #[must_use]
+    pub fn step_guard(&self, parent: impl Into<Cow<'static, str>>) -> CycleGuard {
+        CycleGuard {
+            check: self.clone(),
+            parent: parent.into(),
+        }
+    }
+
+    /// Column whose values identify a row.
+    #[must_use]
+    pub fn column(&self) -> &str {
+        &self.column
+    }
+
+    /// Name of the generated cycle marker column.
+    #[must_use]
+    pub fn set(&self) -> &str {
+        &self.set
+    }
+
+    /// Name of the generated path column.
+    #[must_use]
+    pub fn using(&self) -> &str {
+        &self.using
+    }
+
+    pub(crate) fn walk_clause<DB>(&self, out: &mut AstPass<'_, '_, DB>) -> QueryResult<()>
+    where
+        DB: RecursiveBackend,
+    {
+        out.push_sql(" CYCLE ");
+        out.push_identifier(&self.column)?;
+        out.push_sql(" SET ");
+        out.push_identifier(&self.set)?;
+        out.push_sql(" USING ");
+        out.push_identifier(&self.using)
+    }
+}
+
+/// Cycle marker and path computed by the seed or step of an emulated check.
+///
+/// Renders `, <marker> AS <set>, <path> AS <using>` on backends without a
+/// native `CYCLE` clause and nothing otherwise. Place it at the end of the
+/// select list, after any [`SearchKey`](crate::search::SearchKey).
+#[derive(Debug, Clone)]
+pub struct CycleKey {
+    pub(crate) check: CycleCheck,
+    pub(crate) table: Cow<'static, str>,
+    pub(crate) column: Cow<'static, str>,
+    pub(crate) parent: Option<Cow<'static, str>>,
+}
+
+impl CycleKey {
+    /// Cycle check the key belongs to.
+    #[must_use]
+    pub const fn check(&self) -> &CycleCheck {
+        &self.check
+    }
+
+    /// Table or alias providing the current row's checked value.
+    #[must_use]
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// Column of [`Self::table`] holding the current row's checked value.
+    #[must_use]
+    pub fn column(&self) -> &str {
+        &self.column
+    }
+
+    /// Name under which the step references the CTE, or `None` in the seed.
+    #[must_use]
+    pub fn parent(&self) -> Option<&str> {
+        self.parent.as_deref()
+    }
+}
+
+impl<DB> QueryFragment<DB> for CycleKey
+where
+    DB: RecursiveBackend,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        if DB::NATIVE_CYCLE {
+            return Ok(());
+        }
+        out.push_sql(", ");
+        DB::walk_cycle_key(self, &mut out)
+    }
+}
+
+impl QueryId for CycleKey {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+/// Step condition that stops expansion of rows marked as cycles.
+#[derive(Debug, Clone)]
+pub struct CycleGuard {
+    pub(crate) check: CycleCheck,
+    pub(crate) parent: Cow<'static, str>,
+}
+
+impl<DB> QueryFragment<DB> for CycleGuard
+where
+    DB: RecursiveBackend,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        if DB::NATIVE_CYCLE {
+            out.push_sql("TRUE");
+            return Ok(());
+        }
+        out.push_sql("NOT ");
+        push_qualified(&mut out, &self.parent, &self.check.set)
+    }
+}
+
+impl QueryId for CycleGuard {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+/// Render an emulated marker and path for `SQLite`.
+///
+/// Paths are `/`-delimited with a leading and trailing delimiter, checked
+/// with [`RecursiveBackend::walk_path_check`].
+#[cfg(feature = "sqlite")]
+pub(crate) fn walk_sqlite_key(
+    key: &CycleKey,
+    out: &mut AstPass<'_, '_, diesel::sqlite::Sqlite>,
+) -> QueryResult<()> {
+    let check = &key.check;
+    match key.parent() {
+        None => {
+            out.push_sql("0 AS ");
+            out.push_identifier(&check.set)?;
+            out.push_sql(", '/' || ");
+        }
+        Some(parent) => {
+            diesel::sqlite::Sqlite::walk_path_check(
+                true,
+                |pass| push_qualified(pass, parent, &check.using),
+                |pass| push_qualified(pass, &key.table, &key.column),
+                out,
+            )?;
+            out.push_sql(" AS ");
+            out.push_identifier(&check.set)?;
+            out.push_sql(", ");
+            push_qualified(out, parent, &check.using)?;
+            out.push_sql(" || ");
+        }
+    }
+    push_qualified(out, &key.table, &key.column)?;
+    out.push_sql(" || '/' AS ");
+    out.push_identifier(&check.using)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::normalise_debug_sql;
+    use diesel::debug_query;
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_keys_track_paths() {
+        use diesel::sqlite::Sqlite;
+
+        let check = CycleCheck::new("id", "is_cycle", "path");
+        let seed =
+            normalise_debug_sql(&debug_query::<Sqlite, _>(&check.seed_key("e", "id")).to_string());
+        let step = normalise_debug_sql(
+            &debug_query::<Sqlite, _>(&check.step_key("e", "id", "walk")).to_string(),
+        );
+        assert_eq!(
+            seed,
+            ", 0 AS \"is_cycle\", '/' || \"e\".\"id\" || '/' AS \"path\""
+        );
+        assert_eq!(
+            step,
+            ", instr(\"walk\".\"path\", '/' || \"e\".\"id\" || '/') > 0 AS \"is_cycle\", \"walk\".\"path\" || \"e\".\"id\" || '/' AS \"path\""
+        );
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_guard_excludes_marked_parents() {
+        use diesel::sqlite::Sqlite;
+
+        let check = CycleCheck::new("id", "is_cycle", "path");
+        let guard =
+            normalise_debug_sql(&debug_query::<Sqlite, _>(&check.step_guard("walk")).to_string());
+        assert_eq!(guard, "NOT \"walk\".\"is_cycle\"");
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn postgres_keys_and_guards_defer_to_native_clause() {
+        use diesel::{dsl::sql, pg::Pg, sql_types::Integer};
+
+        let check = CycleCheck::new("id", "is_cycle", "path");
+        let step = crate::step_query!(
+            sql::<Integer>("SELECT e.id"),
+            check.step_key("e", "id", "walk"),
+            sql::<Integer>(" FROM e WHERE "),
+            check.step_guard("walk"),
+        );
+        let sql = normalise_debug_sql(&debug_query::<Pg, _>(&step).to_string());
+        assert_eq!(sql, "SELECT e.id FROM e WHERE TRUE");
+    }
+}
@@ -0,0 +1,466 @@
+//! Depth counter and maximum-depth guard for recursive CTEs.
+//!
+//! [`DepthLimit`] adds a column holding each row's distance from the seed and
+//! stops the recursion once that distance reaches a maximum.
+//! [`WithRecursive::with_depth`] wraps the seed as a derived table selecting
+//! its columns followed by `0`. Recursive CTEs may not reference themselves
+//! from a subquery, so the step cannot be wrapped the same way: a Diesel
+//! `SELECT` statement is re-rendered with the counter after its select list
+//! and the guard added to its `WHERE` clause, and a step written in raw SQL
+//! is given as a [`DepthTerm`], which keeps the select list, the `FROM`
+//! clause, and the filter apart. The column is appended to the CTE's column
+//! list as well, and the same SQL renders on every backend.
+//!
+//! [`WithRecursive::with_depth`]: crate::cte::WithRecursive::with_depth
+
+use std::borrow::Cow;
+
+use diesel::{
+    backend::Backend,
+    expression::{AppearsOnTable, Expression, SelectableExpression, ValidGrouping, is_aggregate},
+    internal::derives::multiconnection::{SelectStatement, SelectStatementAccessor},
+    query_builder::{AstPass, QueryFragment, QueryId},
+    query_dsl::methods::FilterDsl,
+    result::QueryResult,
+    sql_types::Bool,
+};
+
+use crate::cte::push_qualified;
+
+/// Depth column and maximum depth of a recursive CTE.
+///
+/// Seed rows have depth `0` and each step adds one. Rows at depth `max` are
+/// returned but not expanded, so the recursion always terminates. The
+/// column name may be a static string or built at runtime.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepthLimit {
+    pub(crate) column: Cow<'static, str>,
+    pub(crate) max: u32,
+}
+
+impl DepthLimit {
+    /// Track depth in `column` and stop expanding rows at depth `max`.
+    #[must_use]
+    pub fn new(column: impl Into<Cow<'static, str>>, max: u32) -> Self {
+        Self {
+            column: column.into(),
+            max,
+        }
+    }
+
+    /// Name of the generated depth column.
+    #[must_use]
+    pub fn column(&self) -> &str {
+        &self.column
+    }
+
+    /// Depth at which rows stop being expanded.
+    #[must_use]
+    pub const fn max(&self) -> u32 {
+        self.max
+    }
+
+    /// Render a row's depth: `0` in the seed, or one more than the depth of
+    /// the row in `parent` that the step expands.
+    pub(crate) fn walk_counter<DB>(
+        &self,
+        parent: Option<&str>,
+        out: &mut AstPass<'_, '_, DB>,
+    ) -> QueryResult<()>
+    where
+        DB: Backend,
+    {
+        match parent {
+            None => out.push_sql("0"),
+            Some(cte) => {
+                push_qualified(out, cte, &self.column)?;
+                out.push_sql(" + 1");
+            }
+        }
+        Ok(())
+    }
+
+    /// Render the condition excluding rows of `parent` already at the
+    /// maximum depth.
+    pub(crate) fn walk_guard<DB>(
+        &self,
+        parent: &str,
+        out: &mut AstPass<'_, '_, DB>,
+    ) -> QueryResult<()>
+    where
+        DB: Backend,
+    {
+        push_qualified(out, parent, &self.column)?;
+        out.push_sql(" < ");
+        out.push_sql(&self.max.to_string());
+        Ok(())
+    }
+}
+
+/// Step of a recursive CTE that [`WithRecursive::with_depth`] can extend
+/// with a depth counter and guard.
+///
+/// Implemented for Diesel `SELECT` statements and for [`DepthTerm`]. Steps
+/// written in raw SQL cannot be extended from outside, as the counter has to
+/// be added to their select list; write them as a [`DepthTerm`] instead.
+/// Passing one anyway fails to compile:
+///
+/// ```compile_fail
+/// use diesel::{dsl::sql, sql_types::Integer, sqlite::Sqlite};
+/// use diesel_cte_ext::{DepthLimit, RecursiveParts, builders};
+///
+/// let query = builders::with_recursive::<Sqlite, _, _, _, _, _>(
+///     "nums",
+///     &["n"],
+///     RecursiveParts::new(
+///         sql::<Integer>("SELECT 1"),
+///         sql::<Integer>("SELECT n + 1 FROM nums"),
+///         sql::<Integer>("SELECT n FROM nums"),
+///     ),
+/// )
+/// .with_depth(DepthLimit::new("depth", 3));
+/// ```
+///
+/// [`WithRecursive::with_depth`]: crate::cte::WithRecursive::with_depth
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` cannot be given a depth counter",
+    note = "write a step in raw SQL as a `DepthTerm` so the counter can be added to its select list"
+)]
+pub trait DepthStep {
+    /// Step with the counter and guard added.
+    type Counted;
+
+    /// Count depth in this step of the CTE named `parent`.
+    fn count_from_parent(self, limit: &DepthLimit, parent: Cow<'static, str>) -> Self::Counted;
+}
+
+/// Depth counter added to a step, with the name of the CTE it expands.
+#[derive(Debug, Clone)]
+struct Counter {
+    limit: DepthLimit,
+    parent: Cow<'static, str>,
+}
+
+impl Counter {
+    /// Render `, <parent>.<column> + 1 AS <column>` after a select list.
+    fn walk_column<DB>(&self, out: &mut AstPass<'_, '_, DB>) -> QueryResult<()>
+    where
+        DB: Backend,
+    {
+        out.push_sql(", ");
+        self.limit.walk_counter(Some(&self.parent), out)?;
+        out.push_sql(" AS ");
+        out.push_identifier(&self.limit.column)
+    }
+}
+
+/// Seed of a recursive CTE with a depth counter, rendered as
+/// `SELECT "seed".*, 0 AS <column> FROM (<seed>) AS "seed"`.
+///
+/// Built by [`WithRecursive::with_depth`]. On `MySQL` the columns of the
+/// seed must have distinct names, as it rejects derived tables repeating a
+/// name.
+///
+/// [`WithRecursive::with_depth`]: crate::cte::WithRecursive::with_depth
+#[derive(Debug, Clone)]
+pub struct CountedSeed<Seed> {
+    seed: Seed,
+    limit: DepthLimit,
+}
+
+impl<Seed> CountedSeed<Seed> {
+    /// Count depth from `seed`.
+    pub(crate) const fn new(seed: Seed, limit: DepthLimit) -> Self {
+        Self { seed, limit }
+    }
+}
+
+impl<DB, Seed> QueryFragment<DB> for CountedSeed<Seed>
+where
+    DB: Backend,
+    Seed: QueryFragment<DB>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        out.push_sql("SELECT ");
+        out.push_identifier(SEED_ALIAS)?;
+        out.push_sql(".*, ");
+        self.limit.walk_counter(None, &mut out)?;
+        out.push_sql(" AS ");
+        out.push_identifier(&self.limit.column)?;
+        out.push_sql(" FROM (");
+        self.seed.walk_ast(out.reborrow())?;
+        out.push_sql(") AS ");
+        out.push_identifier(SEED_ALIAS)
+    }
+}
+
+impl<Seed> QueryId for CountedSeed<Seed> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+/// Alias of the derived table wrapping a counted seed.
+const SEED_ALIAS: &str = "seed";
+
+/// Condition excluding rows of the CTE already at the maximum depth, added to
+/// the `WHERE` clause of a Diesel `SELECT` step.
+#[derive(Debug, Clone)]
+pub struct DepthGuard(Counter);
+
+impl Expression for DepthGuard {
+    type SqlType = Bool;
+}
+
+impl<QS> AppearsOnTable<QS> for DepthGuard {}
+
+impl<QS> SelectableExpression<QS> for DepthGuard {}
+
+impl<GroupByClause> ValidGrouping<GroupByClause> for DepthGuard {
+    type IsAggregate = is_aggregate::No;
+}
+
+impl<DB> QueryFragment<DB> for DepthGuard
+where
+    DB: Backend,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        self.0.limit.walk_guard(&self.0.parent, &mut out)
+    }
+}
+
+impl QueryId for DepthGuard {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+/// Diesel `SELECT` step with a depth counter after its select list.
+///
+/// The guard is already part of the statement's `WHERE` clause. Built by
+/// [`WithRecursive::with_depth`].
+///
+/// [`WithRecursive::with_depth`]: crate::cte::WithRecursive::with_depth
+#[derive(Debug, Clone)]
+pub struct CountedStep<Step> {
+    step: Step,
+    counter: Counter,
+}
+
+impl<F, S, D, W, O, LOf, G, H, LC> DepthStep for SelectStatement<F, S, D, W, O, LOf, G, H, LC>
+where
+    Self: FilterDsl<DepthGuard>,
+{
+    type Counted = CountedStep<diesel::dsl::Filter<Self, DepthGuard>>;
+
+    fn count_from_parent(self, limit: &DepthLimit, parent: Cow<'static, str>) -> Self::Counted {
+        let counter = Counter {
+            limit: limit.clone(),
+            parent,
+        };
+        CountedStep {
+            step: FilterDsl::filter(self, DepthGuard(counter.clone())),
+            counter,
+        }
+    }
+}
+
+impl<DB, Step> QueryFragment<DB> for CountedStep<Step>
+where
+    DB: Backend,
+    Step: SelectStatementAccessor,
+    Step::Select: QueryFragment<DB>,
+    Step::From: QueryFragment<DB>,
+    Step::Distinct: QueryFragment<DB>,
+    Step::Where: QueryFragment<DB>,
+    Step::Order: QueryFragment<DB>,
+    Step::LimitOffset: QueryFragment<DB>,
+    Step::GroupBy: QueryFragment<DB>,
+    Step::Having: QueryFragment<DB>,
+    Step::Locking: QueryFragment<DB>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        let step = &self.step;
+        out.push_sql("SELECT ");
+        step.distinct_clause().walk_ast(out.reborrow())?;
+        step.select_clause().walk_ast(out.reborrow())?;
+        self.counter.walk_column(&mut out)?;
+        step.from_clause().walk_ast(out.reborrow())?;
+        step.where_clause().walk_ast(out.reborrow())?;
+        step.group_by_clause().walk_ast(out.reborrow())?;
+        step.having_clause().walk_ast(out.reborrow())?;
+        step.order_clause().walk_ast(out.reborrow())?;
+        step.limit_offset_clause().walk_ast(out.reborrow())?;
+        step.locking_clause().walk_ast(out.reborrow())
+    }
+}
+
+impl<Step> QueryId for CountedStep<Step> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+/// Step of a recursive CTE written in raw SQL, split so a [`DepthLimit`] can
+/// extend it.
+///
+/// Renders `SELECT <select> FROM <from>`, followed by `WHERE <filter>` when a
+/// filter is set. Once the query is given a depth limit, the counter is
+/// appended to the select list and the guard is prepended to the filter.
+/// Write `select` without the `SELECT` keyword and `from` without `FROM`;
+/// the step must refer to the CTE by its name rather than an alias.
+#[derive(Debug, Clone)]
+pub struct DepthTerm<Select, From, Filter = ()> {
+    select: Select,
+    from: From,
+    filter: Option<Filter>,
+    counter: Option<Counter>,
+}
+
+impl<Select, From> DepthTerm<Select, From> {
+    /// Select `select` from `from`, without a filter.
+    pub const fn new(select: Select, from: From) -> Self {
+        Self {
+            select,
+            from,
+            filter: None,
+            counter: None,
+        }
+    }
+
+    /// Keep only the rows matching `filter`, rendered after `WHERE`.
+    pub fn filter<Filter>(self, filter: Filter) -> DepthTerm<Select, From, Filter> {
+        DepthTerm {
+            select: self.select,
+            from: self.from,
+            filter: Some(filter),
+            counter: self.counter,
+        }
+    }
+}
+
+impl<Select, From, Filter> DepthStep for DepthTerm<Select, From, Filter> {
+    type Counted = Self;
+
+    fn count_from_parent(mut self, limit: &DepthLimit, parent: Cow<'static, str>) -> Self {
+        self.counter = Some(Counter {
+            limit: limit.clone(),
+            parent,
+        });
+        self
+    }
+}
+
+impl<DB, Select, From, Filter> QueryFragment<DB> for DepthTerm<Select, From, Filter>
+where
+    DB: Backend,
+    Select: QueryFragment<DB>,
+    From: QueryFragment<DB>,
+    Filter: QueryFragment<DB>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        out.push_sql("SELECT ");
+        self.select.walk_ast(out.reborrow())?;
+        if let Some(counter) = &self.counter {
+            counter.walk_column(&mut out)?;
+        }
+        out.push_sql(" FROM ");
+        self.from.walk_ast(out.reborrow())?;
+        match (&self.counter, &self.filter) {
+            (None, None) => {}
+            (None, Some(filter)) => {
+                out.push_sql(" WHERE ");
+                filter.walk_ast(out.reborrow())?;
+            }
+            (Some(counter), None) => {
+                out.push_sql(" WHERE ");
+                counter.limit.walk_guard(&counter.parent, &mut out)?;
+            }
+            (Some(counter), Some(filter)) => {
+                out.push_sql(" WHERE ");
+                counter.limit.walk_guard(&counter.parent, &mut out)?;
+                out.push_sql(" AND (");
+                filter.walk_ast(out.reborrow())?;
+                out.push_sql(")");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<Select, From, Filter> QueryId for DepthTerm<Select, From, Filter> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CountedSeed, DepthLimit, DepthStep, DepthTerm};
+    use crate::test_support::normalise_debug_sql;
+    use diesel::{debug_query, dsl::sql, sql_types::Integer, sqlite::Sqlite};
+
+    #[test]
+    fn terms_without_a_limit_render_plain_selects() {
+        let term = DepthTerm::new(sql::<Integer>("n.id"), sql::<Integer>("nodes n"));
+        let filtered = term.clone().filter(sql::<Integer>("n.id > 1"));
+        assert_eq!(
+            normalise_debug_sql(&debug_query::<Sqlite, _>(&term).to_string()),
+            "SELECT n.id FROM nodes n"
+        );
+        assert_eq!(
+            normalise_debug_sql(&debug_query::<Sqlite, _>(&filtered).to_string()),
+            "SELECT n.id FROM nodes n WHERE n.id > 1"
+        );
+    }
+
+    #[test]
+    fn counters_count_steps_from_the_seed() {
+        let limit = DepthLimit::new("depth", 3);
+        let seed = CountedSeed::new(sql::<Integer>("SELECT id FROM nodes"), limit.clone());
+        let step = DepthTerm::new(
+            sql::<Integer>("n.id"),
+            sql::<Integer>("nodes n JOIN tree ON n.parent_id = tree.id"),
+        )
+        .count_from_parent(&limit, "tree".into());
+        assert_eq!(
+            normalise_debug_sql(&debug_query::<Sqlite, _>(&seed).to_string()),
+            "SELECT \"seed\".*, 0 AS \"depth\" FROM (SELECT id FROM nodes) AS \"seed\""
+        );
+        assert_eq!(
+            normalise_debug_sql(&debug_query::<Sqlite, _>(&step).to_string()),
+            "SELECT n.id, \"tree\".\"depth\" + 1 AS \"depth\" FROM nodes n JOIN tree ON n.parent_id = tree.id WHERE \"tree\".\"depth\" < 3"
+        );
+    }
+
+    #[test]
+    fn guard_is_combined_with_the_step_filter() {
+        let limit = DepthLimit::new("depth", 3);
+        let step = DepthTerm::new(sql::<Integer>("n.id"), sql::<Integer>("nodes n, tree"))
+            .filter(sql::<Integer>("n.parent_id = tree.id OR n.id = 0"))
+            .count_from_parent(&limit, "tree".into());
+        assert_eq!(
+            normalise_debug_sql(&debug_query::<Sqlite, _>(&step).to_string()),
+            "SELECT n.id, \"tree\".\"depth\" + 1 AS \"depth\" FROM nodes n, tree WHERE \"tree\".\"depth\" < 3 AND (n.parent_id = tree.id OR n.id = 0)"
+        );
+    }
+
+    diesel::table! {
+        nodes (id) {
+            id -> Integer,
+            parent_id -> Nullable<Integer>,
+        }
+    }
+
+    #[test]
+    fn select_steps_get_the_counter_and_guard() {
+        use diesel::{ExpressionMethods, QueryDsl};
+
+        let limit = DepthLimit::new("depth", 3);
+        let step = nodes::table
+            .filter(nodes::parent_id.is_not_null())
+            .order(nodes::id)
+            .select(nodes::id)
+            .count_from_parent(&limit, "tree".into());
+        assert_eq!(
+            normalise_debug_sql(&debug_query::<Sqlite, _>(&step).to_string()),
+            "SELECT \"nodes\".\"id\", \"tree\".\"depth\" + 1 AS \"depth\" FROM \"nodes\" WHERE ((\"nodes\".\"parent_id\" IS NOT NULL) AND \"tree\".\"depth\" < 3) ORDER BY \"nodes\".\"id\""
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! Diesel query DSL methods applied to the body of a CTE query.
+//!
+//! [`WithRecursive`] and [`WithCte`] implement Diesel's `QueryDsl` and forward
+//! `filter`, `or_filter`, `order_by`, `then_order_by`, `limit`, `offset`, and
+//! `select` to their body, keeping every CTE definition intact. Starting the
+//! body from the table declared by [`cte_table!`](crate::cte_table) therefore
+//! lets callers refine the CTE's output like any Diesel table. Raw SQL bodies
+//! do not implement these methods, so they must still spell out the full
+//! query.
+
+use diesel::{
+    Expression, QueryDsl,
+    backend::Backend,
+    query_dsl::methods::{
+        FilterDsl, LimitDsl, OffsetDsl, OrFilterDsl, OrderDsl, SelectDsl, ThenOrderDsl,
+    },
+};
+
+use crate::cte::{WithCte, WithRecursive};
+
+/// Implements `QueryDsl` and the forwarded DSL methods for a CTE query whose
+/// generics end with `Body, Rest`.
+macro_rules! forward_body_dsl {
+    ($name:ident<$($pre:ident),*>) => {
+        impl<DB, Cols, $($pre,)* Body, Rest> QueryDsl for $name<DB, Cols, $($pre,)* Body, Rest>
+        where
+            DB: Backend,
+        {
+        }
+
+        impl<DB, Cols, $($pre,)* Body, Rest, Predicate> FilterDsl<Predicate>
+            for $name<DB, Cols, $($pre,)* Body, Rest>
+        where
+            DB: Backend,
+            Body: FilterDsl<Predicate>,
+        {
+            type Output = $name<DB, Cols, $($pre,)* Body::Output, Rest>;
+
+            fn filter(self, predicate: Predicate) -> Self::Output {
+                self.map_body(|body| body.filter(predicate))
+            }
+        }
+
+        impl<DB, Cols, $($pre,)* Body, Rest, Predicate> OrFilterDsl<Predicate>
+            for $name<DB, Cols, $($pre,)* Body, Rest>
+        where
+            DB: Backend,
+            Body: OrFilterDsl<Predicate>,
+        {
+            type Output = $name<DB, Cols, $($pre,)* Body::Output, Rest>;
+
+            fn or_filter(self, predicate: Predicate) -> Self::Output {
+                self.map_body(|body| body.or_filter(predicate))
+            }
+        }
+
+        impl<DB, Cols, $($pre,)* Body, Rest, Expr> OrderDsl<Expr>
+            for $name<DB, Cols, $($pre,)* Body, Rest>
+        where
+            DB: Backend,
+            Expr: Expression,
+            Body: OrderDsl<Expr>,
+        {
+            type Output = $name<DB, Cols, $($pre,)* Body::Output, Rest>;
+
+            fn order(self, expr: Expr) -> Self::Output {
+                self.map_body(|body| body.order(expr))
+            }
+        }
+
+        impl<DB, Cols, $($pre,)* Body, Rest, Expr> ThenOrderDsl<Expr>
+            for $name<DB, Cols, $($pre,)* Body, Rest>
+        where
+            DB: Backend,
+            Body: ThenOrderDsl<Expr>,
+        {
+            type Output = $name<DB, Cols, $($pre,)* Body::Output, Rest>;
+
+            fn then_order_by(self, expr: Expr) -> Self::Output {
+                self.map_body(|body| body.then_order_by(expr))
+            }
+        }
+
+        impl<DB, Cols, $($pre,)* Body, Rest> LimitDsl for $name<DB, Cols, $($pre,)* Body, Rest>
+        where
+            DB: Backend,
+            Body: LimitDsl,
+        {
+            type Output = $name<DB, Cols, $($pre,)* Body::Output, Rest>;
+
+            fn limit(self, limit: i64) -> Self::Output {
+                self.map_body(|body| body.limit(limit))
+            }
+        }
+
+        impl<DB, Cols, $($pre,)* Body, Rest> OffsetDsl for $name<DB, Cols, $($pre,)* Body, Rest>
+        where
+            DB: Backend,
+            Body: OffsetDsl,
+        {
+            type Output = $name<DB, Cols, $($pre,)* Body::Output, Rest>;
+
+            fn offset(self, offset: i64) -> Self::Output {
+                self.map_body(|body| body.offset(offset))
+            }
+        }
+
+        impl<DB, Cols, $($pre,)* Body, Rest, Selection> SelectDsl<Selection>
+            for $name<DB, Cols, $($pre,)* Body, Rest>
+        where
+            DB: Backend,
+            Selection: Expression,
+            Body: SelectDsl<Selection>,
+        {
+            type Output = $name<DB, Cols, $($pre,)* Body::Output, Rest>;
+
+            fn select(self, selection: Selection) -> Self::Output {
+                self.map_body(|body| body.select(selection))
+            }
+        }
+    };
+}
+
+forward_body_dsl!(WithRecursive<Seed, Step>);
+forward_body_dsl!(WithCte<Cte>);
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builders::{self, CteParts, RecursiveParts},
+        columns::CteTable,
+        test_support::normalise_debug_sql,
+    };
+    use diesel::{
+        BoolExpressionMethods, ExpressionMethods, QueryDsl, debug_query, dsl::sql,
+        sql_types::Integer, sqlite::Sqlite,
+    };
+
+    crate::cte_table! {
+        tree (id) {
+            id -> Integer,
+            depth -> Integer,
+        }
+    }
+
+    #[test]
+    fn dsl_methods_refine_the_recursive_body() {
+        let query = builders::with_recursive::<Sqlite, _, _, _, _, _>(
+            tree::table::NAME,
+            tree::table::columns(),
+            RecursiveParts::new(
+                sql::<(Integer, Integer)>("SELECT 1, 0"),
+                sql::<(Integer, Integer)>("SELECT id + 1, depth + 1 FROM tree WHERE id < 5"),
+                tree::table,
+            ),
+        )
+        .filter(tree::depth.gt(0).and(tree::id.ne(3)))
+        .or_filter(tree::id.eq(1))
+        .order_by(tree::depth.desc())
+        .then_order_by(tree::id)
+        .limit(2)
+        .offset(1)
+        .select(tree::id);
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert_eq!(
+            sql,
+            "WITH RECURSIVE \"tree\" (\"id\", \"depth\") AS (SELECT 1, 0 UNION ALL SELECT id + 1, depth + 1 FROM tree WHERE id < 5) SELECT \"tree\".\"id\" FROM \"tree\" WHERE (((\"tree\".\"depth\" > ?) AND (\"tree\".\"id\" != ?)) OR (\"tree\".\"id\" = ?)) ORDER BY \"tree\".\"depth\" DESC, \"tree\".\"id\" LIMIT ? OFFSET ?"
+        );
+    }
+
+    #[test]
+    fn dsl_methods_refine_the_plain_body() {
+        let query = builders::with_cte::<Sqlite, _, _, _, _>(
+            tree::table::NAME,
+            tree::table::columns(),
+            CteParts::new(sql::<(Integer, Integer)>("SELECT 1, 0"), tree::table),
+        )
+        .select(tree::depth)
+        .filter(tree::id.eq(1));
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert_eq!(
+            sql,
+            "WITH \"tree\" (\"id\", \"depth\") AS (SELECT 1, 0) SELECT \"tree\".\"depth\" FROM \"tree\" WHERE (\"tree\".\"id\" = ?)"
+        );
+    }
+}
@@ -0,0 +1,692 @@
+//! Reachability queries over edge tables.
+//!
+//! [`Graph`] describes a directed graph stored as one row per edge, through
+//! the columns holding each edge's source and target nodes.
+//! [`Graph::reachable_from`] builds a [`WithRecursive`] query that follows
+//! the edges from a start node, bound as a query parameter, and returns every
+//! reachable node once together with the fewest hops needed to reach it.
+//!
+//! Each row of the CTE carries the path of nodes visited to reach it, and the
+//! step never follows an edge back onto that path, so traversals of cyclic
+//! graphs terminate. [`Orientation::Undirected`] follows every edge in both
+//! directions; the path check then also keeps the traversal from walking
+//! straight back along the edge it arrived by. `PostgreSQL` stores the path
+//! as an array; `SQLite` uses a `/`-delimited string, so node keys must not
+//! contain `/` there.
+//!
+//! The path check does not stop the CTE from visiting a node once per simple
+//! path leading to it, and the number of such paths grows factorially in
+//! densely connected graphs: a complete graph of ten nodes already produces
+//! close to a million rows. [`Graph::with_max_hops`] bounds the length of the
+//! paths followed. [`Graph::nodes_reachable_from`] drops the hop count and
+//! the path and joins the terms with `UNION`, so every node enters the CTE at
+//! most once; it needs no path representation and renders on every backend.
+
+use std::marker::PhantomData;
+
+use diesel::{
+    Expression, Table,
+    backend::Backend,
+    dsl::AsExprOf,
+    expression::{AsExpression, TypedExpressionType},
+    query_builder::{AstPass, Query, QueryFragment, QueryId},
+    query_source::Column,
+    result::QueryResult,
+    sql_types::{Integer, SqlType},
+};
+
+use crate::{
+    builders::{self, RecursiveParts},
+    cte::{Qualified, RecursiveBackend, UnionKind, WithRecursive, push_qualified},
+    depth::DepthLimit,
+};
+
+/// Name of the CTE built by [`Graph::reachable_from`].
+const CTE_NAME: &str = "reachable";
+
+/// CTE column holding the node reached.
+const NODE: &str = "node";
+
+/// CTE column counting the edges followed from the start node.
+const HOPS: &str = "hops";
+
+/// CTE column holding the nodes visited on the way.
+const PATH: &str = "path";
+
+/// Columns of the CTE built by [`Graph::reachable_from`].
+const CTE_COLUMNS: &[&str] = &[NODE, HOPS, PATH];
+
+/// Alias of the edges followed in both directions by an undirected step.
+const EDGE_ALIAS: &str = "edge";
+
+/// Source node of an edge followed by an undirected step.
+const EDGE_SOURCE: Qualified = Qualified(EDGE_ALIAS, "source");
+
+/// Target node of an edge followed by an undirected step.
+const EDGE_TARGET: Qualified = Qualified(EDGE_ALIAS, "target");
+
+/// Query returned by [`Graph::reachable_from`].
+///
+/// `Start` is the bound start node.
+pub type GraphQuery<DB, Tbl, Src, Dst, Start> = WithRecursive<
+    DB,
+    (),
+    GraphSeed<Start>,
+    GraphStep<Tbl, Src, Dst>,
+    GraphBody<<Src as Expression>::SqlType>,
+>;
+
+/// Query returned by [`Graph::nodes_reachable_from`].
+///
+/// `Start` is the bound start node.
+pub type GraphNodesQuery<DB, Tbl, Src, Dst, Start> = WithRecursive<
+    DB,
+    (),
+    GraphNodesSeed<Start>,
+    GraphNodesStep<Tbl, Src, Dst>,
+    GraphNodesBody<<Src as Expression>::SqlType>,
+>;
+
+/// Direction in which a [`Graph`] traversal follows its edges.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Orientation {
+    /// Follow each edge from its source to its target only.
+    #[default]
+    Directed,
+    /// Follow each edge both ways, for graphs storing every connection once.
+    Undirected,
+}
+
+/// Graph stored as an edge table.
+///
+/// Both columns must belong to the same table and hold the same SQL type.
+#[derive(Debug, Clone, Copy)]
+pub struct Graph<Tbl, Src, Dst> {
+    table: Tbl,
+    source: Src,
+    target: Dst,
+    orientation: Orientation,
+    max_hops: Option<u32>,
+}
+
+impl<Tbl, Src, Dst> Graph<Tbl, Src, Dst>
+where
+    Tbl: Table + Copy,
+    Src: Column<Table = Tbl> + Copy,
+    Dst: Column<Table = Tbl, SqlType = Src::SqlType> + Copy,
+    Src::SqlType: SqlType + TypedExpressionType,
+{
+    /// Describe the graph whose edges lead from `source` to `target`.
+    #[must_use]
+    pub fn new(source: Src, target: Dst) -> Self
+    where
+        Tbl: Default,
+    {
+        Self {
+            table: Tbl::default(),
+            source,
+            target,
+            orientation: Orientation::Directed,
+            max_hops: None,
+        }
+    }
+
+    /// Follow edges as `orientation` describes; edges are directed unless
+    /// set otherwise.
+    #[must_use]
+    pub const fn with_orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    /// Follow paths of at most `max` edges in [`Self::reachable_from`].
+    ///
+    /// Nodes further than `max` hops from the start are not returned. The
+    /// limit bounds the number of paths the CTE enumerates, which otherwise
+    /// grows factorially with the number of edges between the nodes.
+    #[must_use]
+    pub const fn with_max_hops(mut self, max: u32) -> Self {
+        self.max_hops = Some(max);
+        self
+    }
+
+    /// Nodes reachable from `start`, each with its fewest hops from `start`.
+    ///
+    /// `start` itself is returned with `0` hops. Rows are ordered by hops,
+    /// then by node. The CTE holds one row per simple path from `start`, so
+    /// bound dense graphs with [`Self::with_max_hops`] or use
+    /// [`Self::nodes_reachable_from`] when the hop count is not needed.
+    pub fn reachable_from<DB, Start>(
+        &self,
+        start: Start,
+    ) -> GraphQuery<DB, Tbl, Src, Dst, AsExprOf<Start, Src::SqlType>>
+    where
+        DB: RecursiveBackend,
+        Start: AsExpression<Src::SqlType>,
+        Tbl: QueryFragment<DB>,
+        Src: QueryFragment<DB>,
+        Dst: QueryFragment<DB>,
+        AsExprOf<Start, Src::SqlType>: QueryFragment<DB>,
+    {
+        builders::with_recursive(
+            CTE_NAME,
+            CTE_COLUMNS,
+            RecursiveParts::new(
+                GraphSeed {
+                    start: start.as_expression(),
+                },
+                GraphStep { graph: *self },
+                GraphBody {
+                    _marker: PhantomData,
+                },
+            ),
+        )
+    }
+
+    /// Nodes reachable from `start`, including `start`, ordered by node.
+    ///
+    /// The CTE keeps only the nodes and joins its terms with `UNION`, so each
+    /// node is expanded once and the query stays cheap on dense graphs. No
+    /// path is tracked, so the query renders on every backend; the maximum
+    /// set by [`Self::with_max_hops`] does not apply.
+    pub fn nodes_reachable_from<DB, Start>(
+        &self,
+        start: Start,
+    ) -> GraphNodesQuery<DB, Tbl, Src, Dst, AsExprOf<Start, Src::SqlType>>
+    where
+        DB: RecursiveBackend,
+        Start: AsExpression<Src::SqlType>,
+        Tbl: QueryFragment<DB>,
+        Src: QueryFragment<DB>,
+        Dst: QueryFragment<DB>,
+        AsExprOf<Start, Src::SqlType>: QueryFragment<DB>,
+    {
+        builders::with_recursive(
+            CTE_NAME,
+            &[NODE],
+            RecursiveParts::new(
+                GraphNodesSeed {
+                    start: start.as_expression(),
+                },
+                GraphNodesStep { graph: *self },
+                GraphNodesBody {
+                    _marker: PhantomData,
+                },
+            )
+            .with_union(UnionKind::Distinct),
+        )
+    }
+}
+
+/// Seed selecting the start node with `0` hops.
+#[derive(Debug, Clone, Copy)]
+pub struct GraphSeed<Start> {
+    start: Start,
+}
+
+impl<Start> QueryId for GraphSeed<Start> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<DB, Start> QueryFragment<DB> for GraphSeed<Start>
+where
+    DB: RecursiveBackend,
+    Start: QueryFragment<DB>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        out.push_sql("SELECT ");
+        self.start.walk_ast(out.reborrow())?;
+        out.push_sql(", 0, ");
+        DB::walk_graph_path(None, PATH, &self.start, &mut out)
+    }
+}
+
+/// Step following each edge whose target is not yet on the path, up to the
+/// graph's maximum number of hops.
+#[derive(Debug, Clone, Copy)]
+pub struct GraphStep<Tbl, Src, Dst> {
+    graph: Graph<Tbl, Src, Dst>,
+}
+
+impl<Tbl, Src, Dst> QueryId for GraphStep<Tbl, Src, Dst> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<DB, Tbl, Src, Dst> QueryFragment<DB> for GraphStep<Tbl, Src, Dst>
+where
+    DB: RecursiveBackend,
+    Tbl: QueryFragment<DB>,
+    Src: QueryFragment<DB>,
+    Dst: QueryFragment<DB>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        let graph = &self.graph;
+        match graph.orientation {
+            Orientation::Directed => walk_step(graph, &graph.source, &graph.target, &mut out),
+            Orientation::Undirected => walk_step(graph, &EDGE_SOURCE, &EDGE_TARGET, &mut out),
+        }
+    }
+}
+
+/// Render a step following edges from `source` to `target`.
+///
+/// Undirected graphs read their edges from a derived table holding both
+/// orientations of every edge, since the step may reference the CTE only
+/// once.
+fn walk_step<'b, DB, Tbl, Src, Dst, S, T>(
+    graph: &'b Graph<Tbl, Src, Dst>,
+    source: &'b S,
+    target: &'b T,
+    out: &mut AstPass<'_, 'b, DB>,
+) -> QueryResult<()>
+where
+    DB: RecursiveBackend,
+    Tbl: QueryFragment<DB>,
+    Src: QueryFragment<DB>,
+    Dst: QueryFragment<DB>,
+    S: QueryFragment<DB>,
+    T: QueryFragment<DB>,
+{
+    out.push_sql("SELECT ");
+    target.walk_ast(out.reborrow())?;
+    out.push_sql(", ");
+    push_qualified(out, CTE_NAME, HOPS)?;
+    out.push_sql(" + 1, ");
+    DB::walk_graph_path(Some(CTE_NAME), PATH, target, out)?;
+    walk_joined_edges(graph, source, out)?;
+    out.push_sql(" WHERE ");
+    if let Some(max) = graph.max_hops {
+        DepthLimit::new(HOPS, max).walk_guard(CTE_NAME, out)?;
+        out.push_sql(" AND ");
+    }
+    DB::walk_graph_unvisited(CTE_NAME, PATH, target, out)
+}
+
+/// Render ` FROM` the edges joined to the CTE rows at their `source`.
+fn walk_joined_edges<'b, DB, Tbl, Src, Dst, S>(
+    graph: &'b Graph<Tbl, Src, Dst>,
+    source: &'b S,
+    out: &mut AstPass<'_, 'b, DB>,
+) -> QueryResult<()>
+where
+    DB: Backend,
+    Tbl: QueryFragment<DB>,
+    Src: QueryFragment<DB>,
+    Dst: QueryFragment<DB>,
+    S: QueryFragment<DB>,
+{
+    out.push_sql(" FROM ");
+    match graph.orientation {
+        Orientation::Directed => graph.table.walk_ast(out.reborrow())?,
+        Orientation::Undirected => walk_both_orientations(graph, out)?,
+    }
+    out.push_sql(" INNER JOIN ");
+    out.push_identifier(CTE_NAME)?;
+    out.push_sql(" ON ");
+    source.walk_ast(out.reborrow())?;
+    out.push_sql(" = ");
+    push_qualified(out, CTE_NAME, NODE)
+}
+
+/// Render the edges in both orientations as the derived table `edge`.
+fn walk_both_orientations<'b, DB, Tbl, Src, Dst>(
+    graph: &'b Graph<Tbl, Src, Dst>,
+    out: &mut AstPass<'_, 'b, DB>,
+) -> QueryResult<()>
+where
+    DB: Backend,
+    Tbl: QueryFragment<DB>,
+    Src: QueryFragment<DB>,
+    Dst: QueryFragment<DB>,
+{
+    out.push_sql("(SELECT ");
+    graph.source.walk_ast(out.reborrow())?;
+    out.push_sql(" AS ");
+    out.push_identifier(EDGE_SOURCE.1)?;
+    out.push_sql(", ");
+    graph.target.walk_ast(out.reborrow())?;
+    out.push_sql(" AS ");
+    out.push_identifier(EDGE_TARGET.1)?;
+    out.push_sql(" FROM ");
+    graph.table.walk_ast(out.reborrow())?;
+    out.push_sql(" UNION ALL SELECT ");
+    graph.target.walk_ast(out.reborrow())?;
+    out.push_sql(", ");
+    graph.source.walk_ast(out.reborrow())?;
+    out.push_sql(" FROM ");
+    graph.table.walk_ast(out.reborrow())?;
+    out.push_sql(") AS ");
+    out.push_identifier(EDGE_ALIAS)
+}
+
+/// Body returning each reachable node once with its fewest hops.
+#[derive(Debug, Clone, Copy)]
+pub struct GraphBody<ST> {
+    _marker: PhantomData<ST>,
+}
+
+impl<DB, ST> QueryFragment<DB> for GraphBody<ST>
+where
+    DB: Backend,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        out.push_sql("SELECT ");
+        push_qualified(&mut out, CTE_NAME, NODE)?;
+        out.push_sql(", MIN(");
+        push_qualified(&mut out, CTE_NAME, HOPS)?;
+        out.push_sql(") FROM ");
+        out.push_identifier(CTE_NAME)?;
+        out.push_sql(" GROUP BY ");
+        push_qualified(&mut out, CTE_NAME, NODE)?;
+        out.push_sql(" ORDER BY 2, 1");
+        Ok(())
+    }
+}
+
+impl<ST> Query for GraphBody<ST>
+where
+    ST: SqlType + TypedExpressionType,
+{
+    type SqlType = (ST, Integer);
+}
+
+impl<ST> QueryId for GraphBody<ST> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+/// Seed selecting the start node alone.
+#[derive(Debug, Clone, Copy)]
+pub struct GraphNodesSeed<Start> {
+    start: Start,
+}
+
+impl<Start> QueryId for GraphNodesSeed<Start> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<DB, Start> QueryFragment<DB> for GraphNodesSeed<Start>
+where
+    DB: Backend,
+    Start: QueryFragment<DB>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        out.push_sql("SELECT ");
+        self.start.walk_ast(out.reborrow())
+    }
+}
+
+/// Step following every edge from the nodes found so far.
+#[derive(Debug, Clone, Copy)]
+pub struct GraphNodesStep<Tbl, Src, Dst> {
+    graph: Graph<Tbl, Src, Dst>,
+}
+
+impl<Tbl, Src, Dst> QueryId for GraphNodesStep<Tbl, Src, Dst> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<DB, Tbl, Src, Dst> QueryFragment<DB> for GraphNodesStep<Tbl, Src, Dst>
+where
+    DB: Backend,
+    Tbl: QueryFragment<DB>,
+    Src: QueryFragment<DB>,
+    Dst: QueryFragment<DB>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        let graph = &self.graph;
+        out.push_sql("SELECT ");
+        match graph.orientation {
+            Orientation::Directed => {
+                graph.target.walk_ast(out.reborrow())?;
+                walk_joined_edges(graph, &graph.source, &mut out)
+            }
+            Orientation::Undirected => {
+                EDGE_TARGET.walk_ast(out.reborrow())?;
+                walk_joined_edges(graph, &EDGE_SOURCE, &mut out)
+            }
+        }
+    }
+}
+
+/// Body returning each reachable node.
+#[derive(Debug, Clone, Copy)]
+pub struct GraphNodesBody<ST> {
+    _marker: PhantomData<ST>,
+}
+
+impl<DB, ST> QueryFragment<DB> for GraphNodesBody<ST>
+where
+    DB: Backend,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        out.push_sql("SELECT ");
+        push_qualified(&mut out, CTE_NAME, NODE)?;
+        out.push_sql(" FROM ");
+        out.push_identifier(CTE_NAME)?;
+        out.push_sql(" ORDER BY 1");
+        Ok(())
+    }
+}
+
+impl<ST> Query for GraphNodesBody<ST>
+where
+    ST: SqlType + TypedExpressionType,
+{
+    type SqlType = ST;
+}
+
+impl<ST> QueryId for GraphNodesBody<ST> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+/// Render a path as a `PostgreSQL` array, appending with `||`.
+#[cfg(feature = "postgres")]
+pub(crate) fn walk_pg_path<'b, DB, Node>(
+    parent: Option<&str>,
+    path: &str,
+    node: &'b Node,
+    out: &mut AstPass<'_, 'b, DB>,
+) -> QueryResult<()>
+where
+    DB: Backend,
+    Node: QueryFragment<DB>,
+{
+    if let Some(cte) = parent {
+        push_qualified(out, cte, path)?;
+        out.push_sql(" || ");
+        return node.walk_ast(out.reborrow());
+    }
+    out.push_sql("ARRAY[");
+    node.walk_ast(out.reborrow())?;
+    out.push_sql("]");
+    Ok(())
+}
+
+/// Render `NOT (<node> = ANY(<parent>.<path>))`.
+#[cfg(feature = "postgres")]
+pub(crate) fn walk_pg_unvisited<'b, DB, Node>(
+    parent: &str,
+    path: &str,
+    node: &'b Node,
+    out: &mut AstPass<'_, 'b, DB>,
+) -> QueryResult<()>
+where
+    DB: Backend,
+    Node: QueryFragment<DB>,
+{
+    out.push_sql("NOT (");
+    node.walk_ast(out.reborrow())?;
+    out.push_sql(" = ANY(");
+    push_qualified(out, parent, path)?;
+    out.push_sql("))");
+    Ok(())
+}
+
+/// Render a path as a `/`-delimited string with a leading and trailing
+/// delimiter, so a node is on the path exactly when `/<node>/` occurs in it.
+#[cfg(feature = "sqlite")]
+pub(crate) fn walk_sqlite_path<'b, DB, Node>(
+    parent: Option<&str>,
+    path: &str,
+    node: &'b Node,
+    out: &mut AstPass<'_, 'b, DB>,
+) -> QueryResult<()>
+where
+    DB: Backend,
+    Node: QueryFragment<DB>,
+{
+    match parent {
+        Some(cte) => push_qualified(out, cte, path)?,
+        None => out.push_sql("'/'"),
+    }
+    out.push_sql(" || ");
+    node.walk_ast(out.reborrow())?;
+    out.push_sql(" || '/'");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Graph, Orientation};
+    use crate::test_support::normalise_debug_sql;
+    use diesel::{debug_query, sqlite::Sqlite};
+
+    diesel::table! {
+        edges (source, target) {
+            source -> Integer,
+            target -> Integer,
+        }
+    }
+
+    #[test]
+    fn sqlite_paths_are_delimited_strings() {
+        let graph = Graph::new(edges::source, edges::target);
+        let query = graph.reachable_from::<Sqlite, _>(1);
+        let rendered = debug_query::<Sqlite, _>(&query).to_string();
+        assert_eq!(
+            normalise_debug_sql(&rendered),
+            "WITH RECURSIVE \"reachable\" (\"node\", \"hops\", \"path\") AS (\
+             SELECT ?, 0, '/' || ? || '/' \
+             UNION ALL \
+             SELECT \"edges\".\"target\", \"reachable\".\"hops\" + 1, \
+             \"reachable\".\"path\" || \"edges\".\"target\" || '/' \
+             FROM \"edges\" INNER JOIN \"reachable\" ON \"edges\".\"source\" = \"reachable\".\"node\" \
+             WHERE instr(\"reachable\".\"path\", '/' || \"edges\".\"target\" || '/') = 0) \
+             SELECT \"reachable\".\"node\", MIN(\"reachable\".\"hops\") FROM \"reachable\" \
+             GROUP BY \"reachable\".\"node\" ORDER BY 2, 1"
+        );
+        assert!(rendered.ends_with("-- binds: [1, 1]"));
+    }
+
+    #[test]
+    fn undirected_steps_read_both_orientations() {
+        let graph =
+            Graph::new(edges::source, edges::target).with_orientation(Orientation::Undirected);
+        let query = graph.reachable_from::<Sqlite, _>(1);
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert!(
+            sql.contains(
+                "UNION ALL SELECT \"edge\".\"target\", \"reachable\".\"hops\" + 1, \
+                 \"reachable\".\"path\" || \"edge\".\"target\" || '/' \
+                 FROM (SELECT \"edges\".\"source\" AS \"source\", \"edges\".\"target\" AS \"target\" FROM \"edges\" \
+                 UNION ALL SELECT \"edges\".\"target\", \"edges\".\"source\" FROM \"edges\") AS \"edge\" \
+                 INNER JOIN \"reachable\" ON \"edge\".\"source\" = \"reachable\".\"node\" \
+                 WHERE instr(\"reachable\".\"path\", '/' || \"edge\".\"target\" || '/') = 0)"
+            ),
+            "unexpected SQL: {sql}"
+        );
+    }
+
+    #[test]
+    fn max_hops_guard_the_step() {
+        let graph = Graph::new(edges::source, edges::target).with_max_hops(3);
+        let query = graph.reachable_from::<Sqlite, _>(1);
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert!(
+            sql.contains(
+                "WHERE \"reachable\".\"hops\" < 3 \
+                 AND instr(\"reachable\".\"path\", '/' || \"edges\".\"target\" || '/') = 0)"
+            ),
+            "unexpected SQL: {sql}"
+        );
+    }
+
+    #[test]
+    fn reachable_nodes_use_a_distinct_union_without_paths() {
+        let graph = Graph::new(edges::source, edges::target);
+        let query = graph.nodes_reachable_from::<Sqlite, _>(1);
+        let rendered = debug_query::<Sqlite, _>(&query).to_string();
+        assert_eq!(
+            normalise_debug_sql(&rendered),
+            "WITH RECURSIVE \"reachable\" (\"node\") AS (\
+             SELECT ? \
+             UNION \
+             SELECT \"edges\".\"target\" FROM \"edges\" \
+             INNER JOIN \"reachable\" ON \"edges\".\"source\" = \"reachable\".\"node\") \
+             SELECT \"reachable\".\"node\" FROM \"reachable\" ORDER BY 1"
+        );
+        assert!(rendered.ends_with("-- binds: [1]"));
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn postgres_paths_are_arrays() {
+        use diesel::pg::Pg;
+
+        let graph = Graph::new(edges::source, edges::target);
+        let query = graph.reachable_from::<Pg, _>(1);
+        let sql = normalise_debug_sql(&debug_query::<Pg, _>(&query).to_string());
+        assert!(
+            sql.contains("SELECT $1, 0, ARRAY[$2] UNION ALL"),
+            "unexpected SQL: {sql}"
+        );
+        assert!(
+            sql.contains(
+                "\"reachable\".\"path\" || \"edges\".\"target\" FROM \"edges\" INNER JOIN \"reachable\" \
+                 ON \"edges\".\"source\" = \"reachable\".\"node\" \
+                 WHERE NOT (\"edges\".\"target\" = ANY(\"reachable\".\"path\")))"
+            ),
+            "unexpected SQL: {sql}"
+        );
+    }
+
+    #[cfg(feature = "mysql")]
+    #[test]
+    fn paths_are_rejected_on_mysql() {
+        use crate::test_support::assert_query_builder_error;
+        use diesel::{
+            mysql::{Mysql, MysqlQueryBuilder},
+            query_builder::QueryFragment,
+        };
+
+        let graph = Graph::new(edges::source, edges::target);
+        let err = graph
+            .reachable_from::<Mysql, _>(1)
+            .to_sql(&mut MysqlQueryBuilder::new(), &Mysql)
+            .expect_err("MySQL has no path representation");
+        assert_query_builder_error(&err, "graph paths are not supported");
+    }
+
+    #[cfg(feature = "mysql")]
+    #[test]
+    fn reachable_nodes_render_on_mysql() {
+        use diesel::mysql::Mysql;
+
+        let graph =
+            Graph::new(edges::source, edges::target).with_orientation(Orientation::Undirected);
+        let query = graph.nodes_reachable_from::<Mysql, _>(1);
+        let sql = normalise_debug_sql(&debug_query::<Mysql, _>(&query).to_string());
+        assert!(
+            sql.starts_with(
+                "WITH RECURSIVE \"reachable\" (\"node\") AS (SELECT ? UNION SELECT \"edge\".\"target\""
+            ),
+            "unexpected SQL: {sql}"
+        );
+    }
+}
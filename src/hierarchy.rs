@@ -0,0 +1,435 @@
+//! Descendant and ancestor queries over adjacency-list trees.
+//!
+//! [`Hierarchy`] describes a self-referencing table through its key column and
+//! the column holding each row's parent key. [`Hierarchy::descendants_of`] and
+//! [`Hierarchy::ancestors_of`] build [`WithRecursive`] queries that walk the
+//! tree from a starting row, bound as a query parameter, and return the
+//! table's rows together with their distance from that row. The queries
+//! select every column of the table by default; `select` replaces those
+//! columns, for example with a `Selectable` type's `as_select()`, while the
+//! depth stays the last column of each row. The walk follows the parent links
+//! without checking for loops; [`Hierarchy::with_max_depth`] stops it at a
+//! given depth so that cyclic data cannot make it recurse forever.
+
+use diesel::{
+    Expression, Table,
+    backend::Backend,
+    dsl::AsExprOf,
+    expression::{AsExpression, SelectableExpression, TypedExpressionType},
+    query_builder::{AstPass, Query, QueryFragment, QueryId},
+    query_dsl::methods::SelectDsl,
+    query_source::Column,
+    result::QueryResult,
+    sql_types::{Integer, IntoNotNullable, SqlType},
+};
+
+use crate::{
+    breadcrumb::Breadcrumbs,
+    builders::{self, RecursiveParts},
+    columns::Columns,
+    cte::{RecursiveBackend, WithRecursive, push_qualified},
+    depth::DepthLimit,
+};
+
+/// Columns of the CTE built by a [`Hierarchy`] query.
+const CTE_COLUMNS: &[&str] = &["id", "parent", "depth"];
+
+/// Query returned by [`Hierarchy::descendants_of`] and
+/// [`Hierarchy::ancestors_of`].
+///
+/// `Start` is the bound starting key and `Sel` the selected columns, which
+/// default to every column of the table.
+pub type HierarchyQuery<DB, Tbl, Id, Parent, Start, Sel = <Tbl as Table>::AllColumns> =
+    WithRecursive<
+        DB,
+        (),
+        HierarchySeed<Tbl, Id, Parent, Start>,
+        HierarchyStep<Tbl, Id, Parent>,
+        HierarchyBody<Tbl, Id, Sel>,
+    >;
+
+/// Self-referencing table described by its key and parent key columns.
+///
+/// The parent column must belong to the same table and hold the key's SQL
+/// type, optionally nullable so that root rows can have no parent.
+#[derive(Debug, Clone, Copy)]
+pub struct Hierarchy<Tbl, Id, Parent> {
+    pub(crate) table: Tbl,
+    pub(crate) id: Id,
+    pub(crate) parent: Parent,
+    pub(crate) max_depth: Option<u32>,
+}
+
+impl<Tbl, Id, Parent> Hierarchy<Tbl, Id, Parent>
+where
+    Tbl: Table + Copy,
+    Id: Column<Table = Tbl> + Copy,
+    Parent: Column<Table = Tbl> + Copy,
+    Parent::SqlType: IntoNotNullable<NotNullable = Id::SqlType>,
+    Id::SqlType: SqlType + TypedExpressionType,
+{
+    /// Describe the tree formed by `parent` referencing `id`.
+    #[must_use]
+    pub fn new(id: Id, parent: Parent) -> Self
+    where
+        Tbl: Default,
+    {
+        Self {
+            table: Tbl::default(),
+            id,
+            parent,
+            max_depth: None,
+        }
+    }
+
+    /// Stop walking at depth `max`.
+    ///
+    /// Rows at depth `max` are returned but not followed further. Without a
+    /// maximum, a loop in the parent links makes the query recurse forever;
+    /// with one, the rows on the loop are returned repeatedly, once per
+    /// depth, until `max` is reached. [`Self::breadcrumbs`] stop at the
+    /// maximum as well.
+    #[must_use]
+    pub const fn with_max_depth(mut self, max: u32) -> Self {
+        self.max_depth = Some(max);
+        self
+    }
+
+    /// Rows below `root`, starting with `root` itself at depth `0`.
+    ///
+    /// Each child is one level deeper than its parent. Rows are ordered by
+    /// depth, then by key.
+    pub fn descendants_of<DB, Start>(
+        &self,
+        root: Start,
+    ) -> HierarchyQuery<DB, Tbl, Id, Parent, AsExprOf<Start, Id::SqlType>>
+    where
+        DB: RecursiveBackend,
+        Start: AsExpression<Id::SqlType>,
+        Tbl: QueryFragment<DB>,
+        Tbl::AllColumns: QueryFragment<DB>,
+        Id: QueryFragment<DB>,
+        Parent: QueryFragment<DB>,
+        AsExprOf<Start, Id::SqlType>: QueryFragment<DB>,
+    {
+        self.walk(Direction::Descendants, root.as_expression())
+    }
+
+    /// Rows above `node`, starting with `node` itself at depth `0`.
+    ///
+    /// Each parent is one level deeper than its child, so the root of the
+    /// tree comes last.
+    pub fn ancestors_of<DB, Start>(
+        &self,
+        node: Start,
+    ) -> HierarchyQuery<DB, Tbl, Id, Parent, AsExprOf<Start, Id::SqlType>>
+    where
+        DB: RecursiveBackend,
+        Start: AsExpression<Id::SqlType>,
+        Tbl: QueryFragment<DB>,
+        Tbl::AllColumns: QueryFragment<DB>,
+        Id: QueryFragment<DB>,
+        Parent: QueryFragment<DB>,
+        AsExprOf<Start, Id::SqlType>: QueryFragment<DB>,
+    {
+        self.walk(Direction::Ancestors, node.as_expression())
+    }
+
+    /// Breadcrumb paths labelling each row by its `label` column.
+    #[must_use]
+    pub const fn breadcrumbs<Label>(&self, label: Label) -> Breadcrumbs<Tbl, Id, Parent, Label>
+    where
+        Label: Column<Table = Tbl> + Copy,
+    {
+        Breadcrumbs::new(*self, label)
+    }
+
+    fn walk<DB, Start>(
+        &self,
+        direction: Direction,
+        start: Start,
+    ) -> HierarchyQuery<DB, Tbl, Id, Parent, Start>
+    where
+        DB: RecursiveBackend,
+        Tbl: QueryFragment<DB>,
+        Tbl::AllColumns: QueryFragment<DB>,
+        Id: QueryFragment<DB>,
+        Parent: QueryFragment<DB>,
+        Start: QueryFragment<DB>,
+    {
+        let body = HierarchyBody {
+            table: self.table,
+            id: self.id,
+            selection: Tbl::all_columns(),
+            direction,
+        };
+        builders::with_recursive(
+            direction.cte_name(),
+            CTE_COLUMNS,
+            RecursiveParts::new(
+                HierarchySeed {
+                    hierarchy: *self,
+                    start,
+                },
+                HierarchyStep {
+                    hierarchy: *self,
+                    direction,
+                },
+                body,
+            ),
+        )
+    }
+}
+
+impl<Tbl, Id, Parent> From<Columns<(Id, Parent)>> for Hierarchy<Tbl, Id, Parent>
+where
+    Tbl: Table + Copy + Default,
+    Id: Column<Table = Tbl> + Copy + Default,
+    Parent: Column<Table = Tbl> + Copy + Default,
+    Parent::SqlType: IntoNotNullable<NotNullable = Id::SqlType>,
+    Id::SqlType: SqlType + TypedExpressionType,
+{
+    fn from(_: Columns<(Id, Parent)>) -> Self {
+        Self::new(Id::default(), Parent::default())
+    }
+}
+
+/// Direction in which a hierarchy query walks the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Direction {
+    Descendants,
+    Ancestors,
+}
+
+impl Direction {
+    const fn cte_name(self) -> &'static str {
+        match self {
+            Self::Descendants => "descendants",
+            Self::Ancestors => "ancestors",
+        }
+    }
+}
+
+/// Seed selecting the starting row at depth `0`.
+#[derive(Debug, Clone, Copy)]
+pub struct HierarchySeed<Tbl, Id, Parent, Start> {
+    hierarchy: Hierarchy<Tbl, Id, Parent>,
+    start: Start,
+}
+
+impl<Tbl, Id, Parent, Start> QueryId for HierarchySeed<Tbl, Id, Parent, Start> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<DB, Tbl, Id, Parent, Start> QueryFragment<DB> for HierarchySeed<Tbl, Id, Parent, Start>
+where
+    DB: Backend,
+    Tbl: QueryFragment<DB>,
+    Id: QueryFragment<DB>,
+    Parent: QueryFragment<DB>,
+    Start: QueryFragment<DB>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        let Hierarchy {
+            table, id, parent, ..
+        } = &self.hierarchy;
+        out.push_sql("SELECT ");
+        id.walk_ast(out.reborrow())?;
+        out.push_sql(", ");
+        parent.walk_ast(out.reborrow())?;
+        out.push_sql(", 0 FROM ");
+        table.walk_ast(out.reborrow())?;
+        out.push_sql(" WHERE ");
+        id.walk_ast(out.reborrow())?;
+        out.push_sql(" = ");
+        self.start.walk_ast(out.reborrow())
+    }
+}
+
+/// Step joining the table to the rows found so far, one level further, up to
+/// the hierarchy's maximum depth.
+#[derive(Debug, Clone, Copy)]
+pub struct HierarchyStep<Tbl, Id, Parent> {
+    hierarchy: Hierarchy<Tbl, Id, Parent>,
+    direction: Direction,
+}
+
+impl<Tbl, Id, Parent> QueryId for HierarchyStep<Tbl, Id, Parent> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<DB, Tbl, Id, Parent> QueryFragment<DB> for HierarchyStep<Tbl, Id, Parent>
+where
+    DB: Backend,
+    Tbl: QueryFragment<DB>,
+    Id: QueryFragment<DB>,
+    Parent: QueryFragment<DB>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        let Hierarchy {
+            table,
+            id,
+            parent,
+            max_depth,
+        } = &self.hierarchy;
+        let cte = self.direction.cte_name();
+        out.push_sql("SELECT ");
+        id.walk_ast(out.reborrow())?;
+        out.push_sql(", ");
+        parent.walk_ast(out.reborrow())?;
+        out.push_sql(", ");
+        push_qualified(&mut out, cte, "depth")?;
+        out.push_sql(" + 1 FROM ");
+        table.walk_ast(out.reborrow())?;
+        out.push_sql(" INNER JOIN ");
+        out.push_identifier(cte)?;
+        out.push_sql(" ON ");
+        match self.direction {
+            Direction::Descendants => {
+                parent.walk_ast(out.reborrow())?;
+                out.push_sql(" = ");
+                push_qualified(&mut out, cte, "id")?;
+            }
+            Direction::Ancestors => {
+                id.walk_ast(out.reborrow())?;
+                out.push_sql(" = ");
+                push_qualified(&mut out, cte, "parent")?;
+            }
+        }
+        if let Some(max) = *max_depth {
+            out.push_sql(" WHERE ");
+            DepthLimit::new("depth", max).walk_guard(cte, &mut out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Body returning the selected columns of each visited row and its depth.
+#[derive(Debug, Clone, Copy)]
+pub struct HierarchyBody<Tbl, Id, Sel> {
+    table: Tbl,
+    id: Id,
+    selection: Sel,
+    direction: Direction,
+}
+
+impl<DB, Tbl, Id, Sel> QueryFragment<DB> for HierarchyBody<Tbl, Id, Sel>
+where
+    DB: Backend,
+    Tbl: QueryFragment<DB>,
+    Id: QueryFragment<DB>,
+    Sel: QueryFragment<DB>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        let cte = self.direction.cte_name();
+        out.push_sql("SELECT ");
+        self.selection.walk_ast(out.reborrow())?;
+        out.push_sql(", ");
+        push_qualified(&mut out, cte, "depth")?;
+        out.push_sql(" FROM ");
+        self.table.walk_ast(out.reborrow())?;
+        out.push_sql(" INNER JOIN ");
+        out.push_identifier(cte)?;
+        out.push_sql(" ON ");
+        self.id.walk_ast(out.reborrow())?;
+        out.push_sql(" = ");
+        push_qualified(&mut out, cte, "id")?;
+        out.push_sql(" ORDER BY ");
+        push_qualified(&mut out, cte, "depth")?;
+        out.push_sql(", ");
+        self.id.walk_ast(out.reborrow())
+    }
+}
+
+impl<Tbl, Id, Sel> Query for HierarchyBody<Tbl, Id, Sel>
+where
+    Sel: Expression,
+{
+    type SqlType = (Sel::SqlType, Integer);
+}
+
+impl<Tbl, Id, Sel> QueryId for HierarchyBody<Tbl, Id, Sel> {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+/// Replaces the selected columns; the depth column is still appended.
+impl<Tbl, Id, Sel, Selection> SelectDsl<Selection> for HierarchyBody<Tbl, Id, Sel>
+where
+    Selection: SelectableExpression<Tbl>,
+{
+    type Output = HierarchyBody<Tbl, Id, Selection>;
+
+    fn select(self, selection: Selection) -> Self::Output {
+        HierarchyBody {
+            table: self.table,
+            id: self.id,
+            selection,
+            direction: self.direction,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Hierarchy;
+    use crate::test_support::normalise_debug_sql;
+    use diesel::{QueryDsl, debug_query, sqlite::Sqlite};
+
+    diesel::table! {
+        nodes (id) {
+            id -> Integer,
+            parent_id -> Nullable<Integer>,
+            name -> Text,
+        }
+    }
+
+    #[test]
+    fn descendants_walk_down_from_a_bound_root() {
+        let tree = Hierarchy::new(nodes::id, nodes::parent_id);
+        let query = tree.descendants_of::<Sqlite, _>(1);
+        let rendered = debug_query::<Sqlite, _>(&query).to_string();
+        assert_eq!(
+            normalise_debug_sql(&rendered),
+            "WITH RECURSIVE \"descendants\" (\"id\", \"parent\", \"depth\") AS (\
+             SELECT \"nodes\".\"id\", \"nodes\".\"parent_id\", 0 FROM \"nodes\" WHERE \"nodes\".\"id\" = ? \
+             UNION ALL \
+             SELECT \"nodes\".\"id\", \"nodes\".\"parent_id\", \"descendants\".\"depth\" + 1 FROM \"nodes\" \
+             INNER JOIN \"descendants\" ON \"nodes\".\"parent_id\" = \"descendants\".\"id\") \
+             SELECT \"nodes\".\"id\", \"nodes\".\"parent_id\", \"nodes\".\"name\", \"descendants\".\"depth\" \
+             FROM \"nodes\" INNER JOIN \"descendants\" ON \"nodes\".\"id\" = \"descendants\".\"id\" \
+             ORDER BY \"descendants\".\"depth\", \"nodes\".\"id\""
+        );
+        assert!(rendered.ends_with("-- binds: [1]"));
+    }
+
+    #[test]
+    fn max_depth_guards_the_step() {
+        let tree = Hierarchy::new(nodes::id, nodes::parent_id).with_max_depth(3);
+        let query = tree.ancestors_of::<Sqlite, _>(4);
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert!(
+            sql.contains(
+                "INNER JOIN \"ancestors\" ON \"nodes\".\"id\" = \"ancestors\".\"parent\" \
+                 WHERE \"ancestors\".\"depth\" < 3) SELECT"
+            ),
+            "unexpected SQL: {sql}"
+        );
+    }
+
+    #[test]
+    fn ancestors_walk_up_and_keep_depth_after_select() {
+        let tree = Hierarchy::from(crate::columns!(nodes::id, nodes::parent_id));
+        let query = tree.ancestors_of::<Sqlite, _>(4).select(nodes::name);
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert!(
+            sql.contains(
+                "INNER JOIN \"ancestors\" ON \"nodes\".\"id\" = \"ancestors\".\"parent\") \
+                 SELECT \"nodes\".\"name\", \"ancestors\".\"depth\" FROM \"nodes\""
+            ),
+            "unexpected SQL: {sql}"
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! Queries accepted as the body of a non-recursive CTE.
+//!
+//! [`CteQuery`] marks the queries that [`with_cte`](crate::builders::with_cte)
+//! and the `with` methods accept, split into kinds so that Diesel's
+//! `INSERT`, `UPDATE`, and `DELETE` statements are only accepted on backends
+//! implementing [`DataModifyingBackend`], and only with a `.returning(...)`
+//! clause. The kind is inferred from the query and never has to be spelled
+//! out. Diesel renders `RETURNING` on `SQLite` when its
+//! `returning_clauses_for_sqlite_3_35` feature is enabled, but `SQLite` still
+//! rejects statements inside `WITH`, so only `PostgreSQL` implements the
+//! marker.
+//!
+//! A blanket implementation over every `QueryFragment` would also cover the
+//! data-modifying statements, so other fragments either implement
+//! [`CteQuery`] with the [`Raw`] kind themselves or are wrapped in
+//! [`Unchecked`].
+
+use diesel::{
+    backend::Backend,
+    expression::{SqlLiteral, UncheckedBind},
+    query_builder::{
+        AstPass, BoxedSqlQuery, DeleteStatement, InsertStatement, Query, QueryFragment, QueryId,
+        SelectQuery, SqlQuery, UpdateStatement,
+    },
+    query_source::QuerySource,
+    result::QueryResult,
+};
+
+use crate::{
+    boxed::{BoxedCteQuery, BoxedFragment},
+    cte::{WithCte, WithRecursive},
+    macros::{Concat, QueryPart},
+};
+
+/// Kind of Diesel `SELECT` statements, boxed or combined with `UNION`,
+/// `INTERSECT`, or `EXCEPT`.
+#[derive(Debug, Clone, Copy)]
+pub enum Select {}
+
+/// Kind of raw SQL and of fragments wrapping other queries.
+///
+/// Implement [`CteQuery`] with this kind for custom fragments that render a
+/// query returning rows, or wrap them in [`Unchecked`]:
+///
+/// ```
+/// # use diesel::{backend::Backend, query_builder::{AstPass, QueryFragment}, QueryResult};
+/// use diesel_cte_ext::kind::{CteQuery, Raw};
+///
+/// struct Answer;
+///
+/// impl<DB: Backend> QueryFragment<DB> for Answer {
+///     fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+///         out.push_sql("SELECT 42");
+///         Ok(())
+///     }
+/// }
+///
+/// impl<DB: Backend> CteQuery<DB, Raw> for Answer {}
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub enum Raw {}
+
+/// Kind of `INSERT`, `UPDATE`, and `DELETE` statements with a `RETURNING`
+/// clause.
+///
+/// Diesel only implements `Query` for statements built with
+/// `.returning(...)`, which is what the implementations require.
+#[derive(Debug, Clone, Copy)]
+pub enum Modifying {}
+
+/// Query that may be named by a non-recursive CTE on `DB`.
+///
+/// `Kind` is one of [`Select`], [`Raw`], or [`Modifying`]; it only keeps the
+/// implementations apart and is inferred at every call site.
+pub trait CteQuery<DB: Backend, Kind>: QueryFragment<DB> {}
+
+/// Marker for backends accepting data-modifying statements inside `WITH`.
+pub trait DataModifyingBackend: Backend {}
+
+#[cfg(feature = "postgres")]
+impl DataModifyingBackend for diesel::pg::Pg {}
+
+impl<DB, T> CteQuery<DB, Select> for T
+where
+    DB: Backend,
+    T: SelectQuery + QueryFragment<DB>,
+{
+}
+
+impl<DB, ST, T> CteQuery<DB, Raw> for SqlLiteral<ST, T>
+where
+    DB: Backend,
+    Self: QueryFragment<DB>,
+{
+}
+
+impl<DB, Query, Value> CteQuery<DB, Raw> for UncheckedBind<Query, Value>
+where
+    DB: Backend,
+    Self: QueryFragment<DB>,
+{
+}
+
+impl<DB, Inner> CteQuery<DB, Raw> for SqlQuery<Inner>
+where
+    DB: Backend,
+    Self: QueryFragment<DB>,
+{
+}
+
+impl<DB, Query> CteQuery<DB, Raw> for BoxedSqlQuery<'_, DB, Query>
+where
+    DB: Backend,
+    Self: QueryFragment<DB>,
+{
+}
+
+impl<DB> CteQuery<DB, Raw> for BoxedFragment<'_, DB> where DB: Backend {}
+
+impl<DB, ST> CteQuery<DB, Raw> for BoxedCteQuery<'_, DB, ST> where DB: Backend {}
+
+impl<DB, Cols, Cte, Body, Rest> CteQuery<DB, Raw> for WithCte<DB, Cols, Cte, Body, Rest>
+where
+    DB: Backend,
+    Self: QueryFragment<DB>,
+{
+}
+
+impl<DB, Cols, Seed, Step, Body, Rest> CteQuery<DB, Raw>
+    for WithRecursive<DB, Cols, Seed, Step, Body, Rest>
+where
+    DB: Backend,
+    Self: QueryFragment<DB>,
+{
+}
+
+/// Fragment accepted as a CTE query without checking its kind.
+///
+/// Wrapping a query skips the check that keeps data-modifying statements
+/// off backends without [`DataModifyingBackend`], so the database reports
+/// any such statement when the query runs.
+#[derive(Debug, Clone, Copy)]
+pub struct Unchecked<T>(pub T);
+
+impl<DB, T> QueryFragment<DB> for Unchecked<T>
+where
+    DB: Backend,
+    T: QueryFragment<DB>,
+{
+    fn walk_ast<'b>(&'b self, out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        self.0.walk_ast(out)
+    }
+}
+
+impl<T> QueryId for Unchecked<T>
+where
+    T: QueryId,
+{
+    type QueryId = T::QueryId;
+    const HAS_STATIC_QUERY_ID: bool = T::HAS_STATIC_QUERY_ID;
+}
+
+impl<T> Query for Unchecked<T>
+where
+    T: Query,
+{
+    type SqlType = T::SqlType;
+}
+
+impl<DB, T> CteQuery<DB, Raw> for Unchecked<T>
+where
+    DB: Backend,
+    T: QueryFragment<DB>,
+{
+}
+
+impl<DB, T, Kind> CteQuery<DB, Kind> for QueryPart<T>
+where
+    DB: Backend,
+    T: CteQuery<DB, Kind>,
+{
+}
+
+/// Implements [`CteQuery`] for [`Concat`], whose kind lists the kinds of its
+/// elements.
+macro_rules! concat_queries {
+    ($($name:ident: $kind:ident),+) => {
+        impl<DB, $($name, $kind),+> CteQuery<DB, ($($kind,)+)> for Concat<($($name,)+)>
+        where
+            DB: Backend,
+            $($name: CteQuery<DB, $kind>,)+
+        {
+        }
+    };
+}
+
+concat_queries!(A: KA, B: KB);
+concat_queries!(A: KA, B: KB, C: KC);
+concat_queries!(A: KA, B: KB, C: KC, D: KD);
+concat_queries!(A: KA, B: KB, C: KC, D: KD, E: KE);
+concat_queries!(A: KA, B: KB, C: KC, D: KD, E: KE, F: KF);
+
+impl<DB, T, U, Op, Ret> CteQuery<DB, Modifying> for InsertStatement<T, U, Op, Ret>
+where
+    DB: DataModifyingBackend,
+    T: QuerySource,
+    Self: Query + QueryFragment<DB>,
+{
+}
+
+impl<DB, T, U, V, Ret> CteQuery<DB, Modifying> for UpdateStatement<T, U, V, Ret>
+where
+    DB: DataModifyingBackend,
+    T: QuerySource,
+    Self: Query + QueryFragment<DB>,
+{
+}
+
+impl<DB, T, U, Ret> CteQuery<DB, Modifying> for DeleteStatement<T, U, Ret>
+where
+    DB: DataModifyingBackend,
+    T: QuerySource,
+    Self: Query + QueryFragment<DB>,
+{
+}
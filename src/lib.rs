@@ -4,18 +4,36 @@
 //! representing a `WITH RECURSIVE` block that can be executed like any other
 //! query.
 
+pub mod boxed;
+pub mod breadcrumb;
 pub mod builders;
 pub mod columns;
 pub mod connection_ext;
 pub mod cte;
+pub mod cycle;
+pub mod depth;
+pub mod dsl;
+pub mod graph;
+pub mod hierarchy;
+pub mod kind;
 pub mod macros;
+pub mod search;
+#[cfg(feature = "serde")]
+pub mod spec;
+pub mod subquery;
 #[cfg(test)]
 pub(crate) mod test_support;
 
+/// CTE query boxed behind a trait object for dynamic composition.
+pub use boxed::BoxedCteQuery;
+/// Backend able to collect the keys along a breadcrumb.
+pub use breadcrumb::BreadcrumbBackend;
 /// Bundles the CTE and body fragments handed to `with_cte`.
 pub use builders::CteParts;
 /// Bundles the seed, step, and body fragments handed to `with_recursive`.
 pub use builders::RecursiveParts;
+/// Bundles the seed and step fragments of an appended recursive CTE.
+pub use builders::RecursiveTerms;
 /// Builds a simple `WITH` block without the recursive union step.
 pub use builders::with_cte;
 #[doc = "Legacy helper kept for backwards compatibility with 0.1.0 previews."]
@@ -23,9 +41,101 @@ pub use builders::with_cte;
 pub use builders::with_recursive;
 /// Runtime column names paired with compile-time schema metadata.
 pub use columns::Columns;
+/// Column list of a CTE, static or built from runtime names.
+pub use columns::CteColumns;
+/// CTE declared as a Diesel table through `cte_table!`.
+pub use columns::CteTable;
+/// Extension trait exposing the same helpers on `diesel_async` connections.
+#[cfg(feature = "async")]
+pub use connection_ext::AsyncRecursiveCTEExt;
 /// Extension trait exposing the `with_recursive` helper on Diesel connections.
 pub use connection_ext::RecursiveCTEExt;
+/// Planner hint rendered as `AS MATERIALIZED` or `AS NOT MATERIALIZED`.
+pub use cte::Materialization;
 /// Marker trait implemented by Diesel backends that can run recursive CTEs.
 pub use cte::RecursiveBackend;
+/// Set operator joining the seed and step of a recursive CTE.
+pub use cte::UnionKind;
+/// Cycle marker and path columns for recursive CTEs.
+pub use cycle::CycleCheck;
+/// Depth counter column and maximum-depth guard for recursive CTEs.
+pub use depth::DepthLimit;
+/// Seed or step split into select list, `FROM` clause, and filter.
+pub use depth::DepthTerm;
+/// SQL-like macro expanding to the `with_recursive` and `with_cte` builders.
+///
+/// Giving every column an SQL type checks the seed and step against them:
+///
+/// ```
+/// use diesel::{dsl::sql, prelude::*, sql_types::Integer, sqlite::Sqlite};
+/// use diesel_cte_ext::cte;
+///
+/// diesel::table! {
+///     nodes (id) {
+///         id -> Integer,
+///         parent_id -> Integer,
+///     }
+/// }
+///
+/// let query = cte!(recursive tree(id: Integer, parent_id: Integer)
+///     = { nodes::table.select((nodes::id, nodes::parent_id)) }
+///     union all { sql::<(Integer, Integer)>("SELECT n.id, n.parent_id FROM nodes n JOIN tree t ON n.parent_id = t.id") };
+///     select { sql::<Integer>("SELECT id FROM tree") });
+/// let _ = diesel::debug_query::<Sqlite, _>(&query).to_string();
+/// ```
+///
+/// A seed or step returning other columns, or a different number of them,
+/// fails to compile:
+///
+/// ```compile_fail
+/// use diesel::{dsl::sql, prelude::*, sql_types::Integer, sqlite::Sqlite};
+/// use diesel_cte_ext::cte;
+///
+/// diesel::table! {
+///     nodes (id) {
+///         id -> Integer,
+///         parent_id -> Integer,
+///     }
+/// }
+///
+/// let query = cte!(recursive tree(id: Integer, parent_id: Integer)
+///     = { nodes::table.select(nodes::id) }
+///     union all { sql::<(Integer, Integer)>("SELECT n.id, n.parent_id FROM nodes n JOIN tree t ON n.parent_id = t.id") };
+///     select { sql::<Integer>("SELECT id FROM tree") });
+/// let _ = diesel::debug_query::<Sqlite, _>(&query).to_string();
+/// ```
+///
+/// Untyped column lists are not compared with the seed and step, even when
+/// those are typed Diesel queries, so a different number of columns there
+/// only fails when the query runs.
+///
+/// Malformed input, such as a step on a non-recursive CTE, fails to compile:
+///
+/// ```compile_fail
+/// use diesel::{dsl::sql, sql_types::Integer};
+/// use diesel_cte_ext::cte;
+///
+/// let query = cte!(nums(n)
+///     = { sql::<Integer>("SELECT 1") }
+///     union all { sql::<Integer>("SELECT n + 1 FROM nums") };
+///     select { sql::<Integer>("SELECT n FROM nums") });
+/// ```
+#[cfg(feature = "macros")]
+pub use diesel_cte_ext_macros::cte;
+/// Reachability queries over an edge table.
+pub use graph::Graph;
+/// Whether a graph traversal follows edges one way or both ways.
+pub use graph::Orientation;
+/// Descendant and ancestor queries over a self-referencing table.
+pub use hierarchy::Hierarchy;
+/// Query accepted as the body of a non-recursive CTE.
+pub use kind::CteQuery;
+/// Marker for backends accepting data-modifying statements inside `WITH`.
+pub use kind::DataModifyingBackend;
 /// Wrapper for embedding Diesel fragments inside macro-driven queries.
 pub use macros::QueryPart;
+/// Depth-first or breadth-first ordering column for recursive CTEs.
+pub use search::SearchOrder;
+/// CTE query described as data, loaded from TOML or JSON.
+#[cfg(feature = "serde")]
+pub use spec::CteSpec;
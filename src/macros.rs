@@ -1,7 +1,7 @@
 //! Macros and helpers for embedding Diesel expressions inside recursive CTEs.
 use diesel::{
     backend::Backend,
-    query_builder::{AstPass, QueryFragment, QueryId},
+    query_builder::{AstPass, Query, QueryFragment, QueryId},
     result::QueryResult,
 };
 
@@ -40,12 +40,68 @@ where
     const HAS_STATIC_QUERY_ID: bool = T::HAS_STATIC_QUERY_ID;
 }
 
+/// Fragments rendered back to back without separators.
+///
+/// Diesel renders tuples as comma-separated lists; `Concat` instead emits each
+/// element in turn, which lets raw SQL be interleaved with generated fragments
+/// such as [`SearchKey`](crate::search::SearchKey). Build it through the
+/// multi-argument form of [`cte_query!`](crate::cte_query).
+#[derive(Debug, Clone)]
+pub struct Concat<T>(pub T);
+
+macro_rules! concat_fragments {
+    ($($name:ident => $idx:tt),+) => {
+        impl<DB, $($name),+> QueryFragment<DB> for Concat<($($name,)+)>
+        where
+            DB: Backend,
+            $($name: QueryFragment<DB>,)+
+        {
+            fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+                $(self.0.$idx.walk_ast(out.reborrow())?;)+
+                Ok(())
+            }
+        }
+    };
+}
+
+concat_fragments!(A => 0, B => 1);
+concat_fragments!(A => 0, B => 1, C => 2);
+concat_fragments!(A => 0, B => 1, C => 2, D => 3);
+concat_fragments!(A => 0, B => 1, C => 2, D => 3, E => 4);
+concat_fragments!(A => 0, B => 1, C => 2, D => 3, E => 4, F => 5);
+
+impl<T> QueryId for Concat<T>
+where
+    T: QueryId,
+{
+    type QueryId = Concat<T::QueryId>;
+    const HAS_STATIC_QUERY_ID: bool = T::HAS_STATIC_QUERY_ID;
+}
+
+/// Return `query` unchanged, requiring it to load rows of SQL type `ST`.
+///
+/// Used by the `cte!` macro to check seeds and steps against typed column
+/// lists.
+#[doc(hidden)]
+pub const fn expect_sql_type<ST, Q>(query: Q) -> Q
+where
+    Q: Query<SqlType = ST>,
+{
+    query
+}
+
 #[macro_export]
 /// Wrap a Diesel expression for use inside a recursive CTE.
+///
+/// Passing several expressions renders them back to back via
+/// [`Concat`](crate::macros::Concat).
 macro_rules! cte_query {
     ($expr:expr $(,)?) => {
         $crate::QueryPart::new($expr)
     };
+    ($first:expr, $($rest:expr),+ $(,)?) => {
+        $crate::QueryPart::new($crate::macros::Concat(($first, $($rest),+)))
+    };
 }
 
 #[macro_export]
@@ -61,8 +117,8 @@ macro_rules! cte_query {
 #[doc = "let part = seed_query!(sql::<Integer>(\"SELECT 1\"));"]
 #[doc = "```"]
 macro_rules! seed_query {
-    ($expr:expr $(,)?) => {
-        $crate::cte_query!($expr)
+    ($($expr:expr),+ $(,)?) => {
+        $crate::cte_query!($($expr),+)
     };
 }
 
@@ -79,8 +135,8 @@ macro_rules! seed_query {
 #[doc = "let part = step_query!(sql::<Integer>(\"SELECT n + 1 FROM t\"));"]
 #[doc = "```"]
 macro_rules! step_query {
-    ($expr:expr $(,)?) => {
-        $crate::cte_query!($expr)
+    ($($expr:expr),+ $(,)?) => {
+        $crate::cte_query!($($expr),+)
     };
 }
 
@@ -111,6 +167,16 @@ mod tests {
         assert_sql_matches(&wrapped, "SELECT 42");
     }
 
+    #[test]
+    fn cte_query_concatenates_multiple_expressions() {
+        let wrapped = cte_query!(
+            sql::<Integer>("SELECT 1"),
+            sql::<Integer>(" + 2"),
+            sql::<Integer>(" AS n"),
+        );
+        assert_sql_matches(&wrapped, "SELECT 1 + 2 AS n");
+    }
+
     fn assert_sql_matches<T>(part: &QueryPart<T>, expected: &str)
     where
         T: diesel::query_builder::QueryFragment<Sqlite>,
@@ -0,0 +1,309 @@
+//! Portable `SEARCH DEPTH FIRST` and `SEARCH BREADTH FIRST` ordering.
+//!
+//! [`SearchOrder`] asks a recursive CTE to expose an ordering column that
+//! sorts rows in depth-first or breadth-first traversal order. `PostgreSQL`
+//! renders the SQL-standard `SEARCH ... BY ... SET ...` clause natively.
+//! Backends without the clause emulate it: the ordering column is appended to
+//! the CTE's column list and accumulated by [`SearchKey`] fragments spliced
+//! into the seed and step projections. The key fragments render nothing where
+//! the clause is native, so the same seed and step work on every backend.
+
+use std::borrow::Cow;
+
+use diesel::{
+    query_builder::{AstPass, QueryFragment, QueryId},
+    result::QueryResult,
+};
+
+use crate::cte::RecursiveBackend;
+#[cfg(feature = "sqlite")]
+use crate::cte::push_qualified;
+
+/// Traversal order requested by a [`SearchOrder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    /// Visit each row's descendants before its siblings.
+    DepthFirst,
+    /// Visit every row at one depth before moving to the next.
+    BreadthFirst,
+}
+
+impl SearchMode {
+    const fn as_sql(self) -> &'static str {
+        match self {
+            Self::DepthFirst => " SEARCH DEPTH FIRST BY ",
+            Self::BreadthFirst => " SEARCH BREADTH FIRST BY ",
+        }
+    }
+}
+
+/// Ordering column added to a recursive CTE.
+///
+/// `by` names the CTE column whose values order siblings and `set` names the
+/// generated ordering column, which the body can use in `ORDER BY`.
+///
+/// Emulated keys zero-pad the `by` values, so on backends without a native
+/// `SEARCH` clause the column must hold non-negative integers.
+///
+/// Names may be static strings or built at runtime, like the names in a
+/// [`ColumnList`](crate::columns::ColumnList).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchOrder {
+    pub(crate) mode: SearchMode,
+    pub(crate) by: Cow<'static, str>,
+    pub(crate) set: Cow<'static, str>,
+}
+
+impl SearchOrder {
+    /// Order rows depth first by `by`, exposing the ordering as `set`.
+    #[must_use]
+    pub fn depth_first(
+        by: impl Into<Cow<'static, str>>,
+        set: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        Self {
+            mode: SearchMode::DepthFirst,
+            by: by.into(),
+            set: set.into(),
+        }
+    }
+
+    /// Order rows breadth first by `by`, exposing the ordering as `set`.
+    #[must_use]
+    pub fn breadth_first(
+        by: impl Into<Cow<'static, str>>,
+        set: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        Self {
+            mode: SearchMode::BreadthFirst,
+            by: by.into(),
+            set: set.into(),
+        }
+    }
+
+    /// Key fragment to append to the seed's select list.
+    ///
+    /// `table.column` is the seed's source for the value of the `by` column.
+    #[must_use]
+    pub fn seed_key(
+        &self,
+        table: impl Into<Cow<'static, str>>,
+        column: impl Into<Cow<'static, str>>,
+    ) -> SearchKey {
+        SearchKey {
+            search: self.clone(),
+            table: table.into(),
+            column: column.into(),
+            parent: None,
+        }
+    }
+
+    /// Key fragment to append to the step's select list.
+    ///
+    /// `table.column` is the step's source for the new row's `by` value and
+    /// `parent` is the name (or alias) under which the step references the
+    /// CTE itself.
+    #[must_use]
+    pub fn step_key(
+        &self,
+        table: impl Into<Cow<'static, str>>,
+        column: impl Into<Cow<'static, str>>,
+        parent: impl Into<Cow<'static, str>>,
+    ) -> SearchKey {
+        SearchKey {
+            search: self.clone(),
+            table: table.into(),
+            column: column.into(),
+            parent: Some(parent.into()),
+        }
+    }
+
+    /// Traversal order.
+    #[must_use]
+    pub const fn mode(&self) -> SearchMode {
+        self.mode
+    }
+
+    /// Column whose values order siblings.
+    #[must_use]
+    pub fn by(&self) -> &str {
+        &self.by
+    }
+
+    /// Name of the generated ordering column.
+    #[must_use]
+    pub fn set(&self) -> &str {
+        &self.set
+    }
+
+    pub(crate) fn walk_clause<DB>(&self, out: &mut AstPass<'_, '_, DB>) -> QueryResult<()>
+    where
+        DB: RecursiveBackend,
+    {
+        out.push_sql(self.mode.as_sql());
+        out.push_identifier(&self.by)?;
+        out.push_sql(" SET ");
+        out.push_identifier(&self.set)
+    }
+}
+
+/// Ordering key accumulated by the seed or step of an emulated search.
+///
+/// Renders `, <key> AS <set>` on backends without a native `SEARCH` clause and
+/// nothing otherwise. Build it with [`SearchOrder::seed_key`] or
+/// [`SearchOrder::step_key`] and place it at the end of the select list, for
+/// example via the multi-argument form of [`step_query!`](crate::step_query).
+#[derive(Debug, Clone)]
+pub struct SearchKey {
+    pub(crate) search: SearchOrder,
+    pub(crate) table: Cow<'static, str>,
+    pub(crate) column: Cow<'static, str>,
+    pub(crate) parent: Option<Cow<'static, str>>,
+}
+
+impl SearchKey {
+    /// Search order the key belongs to.
+    #[must_use]
+    pub const fn search(&self) -> &SearchOrder {
+        &self.search
+    }
+
+    /// Table or alias providing the current row's `by` value.
+    #[must_use]
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// Column of [`Self::table`] holding the current row's `by` value.
+    #[must_use]
+    pub fn column(&self) -> &str {
+        &self.column
+    }
+
+    /// Name under which the step references the CTE, or `None` in the seed.
+    #[must_use]
+    pub fn parent(&self) -> Option<&str> {
+        self.parent.as_deref()
+    }
+}
+
+impl<DB> QueryFragment<DB> for SearchKey
+where
+    DB: RecursiveBackend,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        if DB::NATIVE_SEARCH {
+            return Ok(());
+        }
+        out.push_sql(", ");
+        DB::walk_search_key(self, &mut out)?;
+        out.push_sql(" AS ");
+        out.push_identifier(&self.search.set)
+    }
+}
+
+impl QueryId for SearchKey {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+/// Render an emulated key using `SQLite`'s `printf`.
+///
+/// Depth-first keys concatenate the zero-padded `by` values along the path
+/// from the root. Breadth-first keys prefix the row's own `by` value with its
+/// zero-padded depth, which is recovered from the parent's key.
+#[cfg(feature = "sqlite")]
+pub(crate) fn walk_sqlite_key<DB>(key: &SearchKey, out: &mut AstPass<'_, '_, DB>) -> QueryResult<()>
+where
+    DB: diesel::backend::Backend,
+{
+    match (key.search.mode, key.parent()) {
+        (SearchMode::DepthFirst, None) => out.push_sql("printf('%020d', "),
+        (SearchMode::DepthFirst, Some(parent)) => {
+            push_qualified(out, parent, &key.search.set)?;
+            out.push_sql(" || printf('%020d', ");
+        }
+        (SearchMode::BreadthFirst, None) => out.push_sql("printf('%010d%020d', 0, "),
+        (SearchMode::BreadthFirst, Some(parent)) => {
+            out.push_sql("printf('%010d%020d', CAST(substr(");
+            push_qualified(out, parent, &key.search.set)?;
+            out.push_sql(", 1, 10) AS INTEGER) + 1, ");
+        }
+    }
+    push_qualified(out, &key.table, &key.column)?;
+    out.push_sql(")");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::normalise_debug_sql;
+    use diesel::debug_query;
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_depth_first_keys_accumulate_paths() {
+        use diesel::sqlite::Sqlite;
+
+        let search = SearchOrder::depth_first("id", "ord");
+        let seed =
+            normalise_debug_sql(&debug_query::<Sqlite, _>(&search.seed_key("n", "id")).to_string());
+        let step = normalise_debug_sql(
+            &debug_query::<Sqlite, _>(&search.step_key("n", "id", "tree")).to_string(),
+        );
+        assert_eq!(seed, ", printf('%020d', \"n\".\"id\") AS \"ord\"");
+        assert_eq!(
+            step,
+            ", \"tree\".\"ord\" || printf('%020d', \"n\".\"id\") AS \"ord\""
+        );
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_breadth_first_keys_track_depth() {
+        use diesel::sqlite::Sqlite;
+
+        let search = SearchOrder::breadth_first("id", "ord");
+        let step = normalise_debug_sql(
+            &debug_query::<Sqlite, _>(&search.step_key("n", "id", "tree")).to_string(),
+        );
+        assert_eq!(
+            step,
+            ", printf('%010d%020d', CAST(substr(\"tree\".\"ord\", 1, 10) AS INTEGER) + 1, \"n\".\"id\") AS \"ord\""
+        );
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn names_may_be_built_at_runtime() {
+        use diesel::sqlite::Sqlite;
+
+        let prefix = "node";
+        let search = SearchOrder::depth_first(format!("{prefix}_id"), format!("{prefix}_ord"));
+        let step = normalise_debug_sql(
+            &debug_query::<Sqlite, _>(&search.step_key("n", format!("{prefix}_id"), "tree"))
+                .to_string(),
+        );
+        assert_eq!(search.set(), "node_ord");
+        assert_eq!(
+            step,
+            ", \"tree\".\"node_ord\" || printf('%020d', \"n\".\"node_id\") AS \"node_ord\""
+        );
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn postgres_keys_render_nothing() {
+        use diesel::{dsl::sql, pg::Pg, sql_types::Integer};
+
+        let search = SearchOrder::depth_first("id", "ord");
+        let step = crate::step_query!(
+            sql::<Integer>("SELECT n.id"),
+            search.step_key("n", "id", "tree"),
+            sql::<Integer>(" FROM n"),
+        );
+        let sql = normalise_debug_sql(&debug_query::<Pg, _>(&step).to_string());
+        assert_eq!(sql, "SELECT n.id FROM n");
+    }
+}
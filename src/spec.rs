@@ -0,0 +1,426 @@
+//! Declarative CTE definitions loaded from configuration.
+//!
+//! [`CteSpec`] describes a CTE query as data rather than code: its name,
+//! column list, seed, optional step, body, and row limit. It derives serde's
+//! `Deserialize`, so definitions can be kept in TOML or JSON files and read
+//! with any serde format crate. [`CteSpec::build`] validates a spec and turns
+//! it into a [`BoxedCteQuery`] through [`builders::with_recursive`] or
+//! [`builders::with_cte`], binding the spec's values as query parameters.
+//!
+//! Each fragment marks its parameters with `?`, whatever the backend; they are
+//! rendered as `$1`, `$2`, ... on `PostgreSQL`. A `?` inside single-quoted
+//! strings or double-quoted identifiers is left alone, and `??` stands for a
+//! literal `?`, so `PostgreSQL`'s `jsonb` operators are written `??`, `??|`,
+//! and `??&`.
+
+use diesel::{
+    query_builder::{AstPass, QueryFragment, QueryId},
+    result::{Error, QueryResult},
+    serialize::ToSql,
+    sql_types::{BigInt, Bool, Double, HasSqlType, Text},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    boxed::BoxedCteQuery,
+    builders::{self, CteParts, RecursiveParts},
+    columns::CteColumns,
+    cte::{Materialization, RecursiveBackend, UnionKind, ensure_unique_columns},
+    kind::{CteQuery, Raw},
+};
+
+/// CTE query described as data.
+///
+/// With a `step` the spec builds a `WITH RECURSIVE` query whose `seed` and
+/// `step` are joined by `union`; without one it builds a plain `WITH` query
+/// whose CTE is the `seed`. A TOML definition looks like:
+///
+/// ```toml
+/// name = "nums"
+/// columns = ["n"]
+/// limit = 10
+///
+/// [seed]
+/// sql = "SELECT ?"
+/// binds = [1]
+///
+/// [step]
+/// sql = "SELECT n + 1 FROM nums WHERE n < ?"
+/// binds = [100]
+///
+/// [body]
+/// sql = "SELECT n FROM nums"
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CteSpec {
+    /// Name of the CTE.
+    pub name: String,
+    /// Column names exposed by the CTE; empty to omit the column list.
+    #[serde(default)]
+    pub columns: Vec<String>,
+    /// Seed of a recursive CTE, or the whole query of a plain one.
+    pub seed: FragmentSpec,
+    /// Step of a recursive CTE.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step: Option<FragmentSpec>,
+    /// Query consuming the CTE.
+    pub body: FragmentSpec,
+    /// Set operator joining the seed and step; defaults to `UNION ALL`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub union: Option<UnionKind>,
+    /// Planner hint for the CTE; defaults to no hint.
+    #[serde(default)]
+    pub materialization: Materialization,
+    /// Maximum number of rows returned by the body, bound as a parameter.
+    ///
+    /// The body is wrapped as `SELECT * FROM (<body>) AS limited LIMIT ?`,
+    /// so it may carry its own `LIMIT` or `OFFSET`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+}
+
+/// Raw SQL fragment with the values bound to its `?` placeholders.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FragmentSpec {
+    /// SQL text, with one `?` per bound value and `??` for a literal `?`.
+    pub sql: String,
+    /// Values bound to the placeholders, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub binds: Vec<SpecValue>,
+}
+
+/// Value bound to a placeholder of a [`FragmentSpec`].
+///
+/// Integers are bound as `BIGINT` and floats as `DOUBLE PRECISION`, so
+/// columns computed from them have those types.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum SpecValue {
+    /// Boolean, bound as `BOOLEAN`.
+    Bool(bool),
+    /// Integer, bound as `BIGINT`.
+    Integer(i64),
+    /// Floating-point number, bound as `DOUBLE PRECISION`.
+    Float(f64),
+    /// String, bound as `TEXT`.
+    Text(String),
+}
+
+/// Backends that can bind every [`SpecValue`] and run the queries built
+/// by [`CteSpec::build`].
+///
+/// Implemented for every [`RecursiveBackend`] accepting booleans, 64-bit
+/// integers, doubles, and text as bind parameters.
+pub trait SpecBackend: RecursiveBackend {
+    /// Render `value` as a bind parameter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backend fails to bind the value.
+    fn walk_value<'b>(value: &'b SpecValue, out: &mut AstPass<'_, 'b, Self>) -> QueryResult<()>;
+}
+
+impl<DB> SpecBackend for DB
+where
+    DB: RecursiveBackend
+        + HasSqlType<Bool>
+        + HasSqlType<BigInt>
+        + HasSqlType<Double>
+        + HasSqlType<Text>,
+    bool: ToSql<Bool, DB>,
+    i64: ToSql<BigInt, DB>,
+    f64: ToSql<Double, DB>,
+    String: ToSql<Text, DB>,
+{
+    fn walk_value<'b>(value: &'b SpecValue, out: &mut AstPass<'_, 'b, Self>) -> QueryResult<()> {
+        match value {
+            SpecValue::Bool(flag) => out.push_bind_param::<Bool, _>(flag),
+            SpecValue::Integer(int) => out.push_bind_param::<BigInt, _>(int),
+            SpecValue::Float(float) => out.push_bind_param::<Double, _>(float),
+            SpecValue::Text(text) => out.push_bind_param::<Text, _>(text),
+        }
+    }
+}
+
+impl CteSpec {
+    /// Validate the spec and build a query loading rows of SQL type `ST`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a query builder error if the name or a column name is empty,
+    /// a column name is repeated, a fragment is empty, ends with `;`, or has
+    /// placeholders that do not match its binds, or a union operator is given
+    /// without a step.
+    pub fn build<DB, ST>(&self) -> QueryResult<BoxedCteQuery<'static, DB, ST>>
+    where
+        DB: SpecBackend + Send + 'static,
+    {
+        if self.name.trim().is_empty() {
+            return Err(spec_error("the CTE name is empty"));
+        }
+        if self.columns.iter().any(|column| column.trim().is_empty()) {
+            return Err(spec_error(&format!(
+                "CTE '{}' has an empty column name",
+                self.name
+            )));
+        }
+        let names: Vec<&str> = self.columns.iter().map(String::as_str).collect();
+        ensure_unique_columns(&names)?;
+        let seed = SpecFragment::new(&self.seed, "seed")?;
+        let mut body = SpecFragment::new(&self.body, "body")?;
+        if let Some(limit) = self.limit {
+            body.limit(limit);
+        }
+        let columns = CteColumns::owned(self.columns.clone());
+        match &self.step {
+            Some(step) => {
+                let parts = RecursiveParts::new(seed, SpecFragment::new(step, "step")?, body)
+                    .with_union(self.union.unwrap_or_default())
+                    .with_materialization(self.materialization);
+                Ok(BoxedCteQuery::new(builders::with_recursive::<
+                    DB,
+                    _,
+                    _,
+                    _,
+                    _,
+                    _,
+                >(
+                    self.name.clone(), columns, parts
+                )))
+            }
+            None if self.union.is_some() => Err(spec_error(&format!(
+                "CTE '{}' sets a union operator without a step",
+                self.name
+            ))),
+            None => {
+                let parts = CteParts::new(seed, body).with_materialization(self.materialization);
+                Ok(BoxedCteQuery::new(builders::with_cte::<DB, _, _, _, _>(
+                    self.name.clone(),
+                    columns,
+                    parts,
+                )))
+            }
+        }
+    }
+}
+
+fn spec_error(message: &str) -> Error {
+    Error::QueryBuilderError(format!("invalid CTE spec: {message}").into())
+}
+
+/// Fragment split at its placeholders, rendered with the binds in between.
+#[derive(Debug, Clone)]
+struct SpecFragment {
+    pieces: Vec<String>,
+    binds: Vec<SpecValue>,
+}
+
+impl SpecFragment {
+    fn new(spec: &FragmentSpec, role: &str) -> QueryResult<Self> {
+        if spec.sql.trim().is_empty() {
+            return Err(spec_error(&format!("the {role} SQL is empty")));
+        }
+        let pieces = split_placeholders(&spec.sql);
+        let placeholders = pieces.len() - 1;
+        if placeholders != spec.binds.len() {
+            return Err(spec_error(&format!(
+                "the {role} has {placeholders} placeholder(s) but {} bind(s)",
+                spec.binds.len()
+            )));
+        }
+        if spec.sql.trim_end().ends_with(';') {
+            return Err(spec_error(&format!("the {role} ends with ';'")));
+        }
+        Ok(Self {
+            pieces,
+            binds: spec.binds.clone(),
+        })
+    }
+
+    /// Wrap the fragment in a derived table returning at most `limit` rows.
+    ///
+    /// Wrapping rather than appending keeps fragments that end in their own
+    /// `LIMIT` or `OFFSET`, or in a `--` comment, valid.
+    fn limit(&mut self, limit: u32) {
+        if let Some(first) = self.pieces.first_mut() {
+            first.insert_str(0, "SELECT * FROM (");
+        }
+        if let Some(last) = self.pieces.last_mut() {
+            last.push_str("\n) AS limited LIMIT ");
+        }
+        self.pieces.push(String::new());
+        self.binds.push(SpecValue::Integer(i64::from(limit)));
+    }
+}
+
+/// Split `sql` at the `?` placeholders outside quotes, unescaping `??`.
+///
+/// Text between single or double quotes is copied unchanged; SQL escapes a
+/// quote inside quotes by doubling it, which closes and reopens the quote.
+fn split_placeholders(sql: &str) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    let mut chars = sql.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match (quote, ch) {
+            (Some(open), _) => {
+                if ch == open {
+                    quote = None;
+                }
+                current.push(ch);
+            }
+            (None, '\'' | '"') => {
+                quote = Some(ch);
+                current.push(ch);
+            }
+            (None, '?') if chars.next_if_eq(&'?').is_some() => current.push('?'),
+            (None, '?') => pieces.push(std::mem::take(&mut current)),
+            (None, _) => current.push(ch),
+        }
+    }
+    pieces.push(current);
+    pieces
+}
+
+impl<DB> QueryFragment<DB> for SpecFragment
+where
+    DB: SpecBackend,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        let mut pieces = self.pieces.iter();
+        if let Some(first) = pieces.next() {
+            out.push_sql(first);
+        }
+        for (value, piece) in self.binds.iter().zip(pieces) {
+            DB::walk_value(value, &mut out)?;
+            out.push_sql(piece);
+        }
+        Ok(())
+    }
+}
+
+// The SQL comes from the spec at runtime, like Diesel's `sql` literals.
+impl QueryId for SpecFragment {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<DB> CteQuery<DB, Raw> for SpecFragment where DB: SpecBackend {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::normalise_debug_sql;
+    use diesel::{debug_query, sql_types::Integer, sqlite::Sqlite};
+
+    const NUMS: &str = r#"
+        name = "nums"
+        columns = ["n"]
+        union = "distinct"
+        limit = 5
+
+        [seed]
+        sql = "SELECT ?"
+        binds = [1]
+
+        [step]
+        sql = "SELECT n + 1 FROM nums WHERE n < ? AND ?"
+        binds = [10, true]
+
+        [body]
+        sql = "SELECT n FROM nums"
+    "#;
+
+    fn render(spec: &CteSpec) -> String {
+        let query = spec.build::<Sqlite, Integer>().expect("valid spec");
+        normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string())
+    }
+
+    #[test]
+    fn toml_spec_builds_recursive_query() {
+        let spec: CteSpec = toml::from_str(NUMS).expect("parse TOML");
+        assert_eq!(
+            spec.step.as_ref().map(|step| step.binds.clone()),
+            Some(vec![SpecValue::Integer(10), SpecValue::Bool(true)])
+        );
+        assert_eq!(
+            render(&spec),
+            "WITH RECURSIVE \"nums\" (\"n\") AS (SELECT ? UNION SELECT n + 1 FROM nums WHERE n < ? AND ?) SELECT * FROM (SELECT n FROM nums\n) AS limited LIMIT ?"
+        );
+    }
+
+    #[test]
+    fn json_spec_without_step_builds_plain_query() {
+        let spec: CteSpec = serde_json::from_str(
+            r#"{
+                "name": "names",
+                "columns": ["label"],
+                "materialization": "materialized",
+                "seed": { "sql": "SELECT ?", "binds": ["root"] },
+                "body": { "sql": "SELECT label FROM names" }
+            }"#,
+        )
+        .expect("parse JSON");
+        assert_eq!(
+            render(&spec),
+            "WITH \"names\" (\"label\") AS MATERIALIZED (SELECT ?) SELECT label FROM names"
+        );
+    }
+
+    #[test]
+    fn quoted_and_escaped_question_marks_are_not_placeholders() {
+        assert_eq!(
+            split_placeholders(r#"SELECT '?', "a?" FROM t WHERE doc ?? 'k' AND n = ?"#),
+            vec![
+                r#"SELECT '?', "a?" FROM t WHERE doc ? 'k' AND n = "#.to_owned(),
+                String::new(),
+            ]
+        );
+        assert_eq!(
+            split_placeholders("SELECT 'it''s?', ? WHERE doc ??| ?"),
+            vec![
+                "SELECT 'it''s?', ".to_owned(),
+                " WHERE doc ?| ".to_owned(),
+                String::new(),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_specs_are_rejected() {
+        let base: CteSpec = toml::from_str(NUMS).expect("parse TOML");
+        let rejects = |spec: CteSpec, needle: &str| {
+            let message = spec
+                .build::<Sqlite, Integer>()
+                .expect_err("invalid spec")
+                .to_string();
+            assert!(message.contains(needle), "{message}");
+        };
+
+        let mut duplicate = base.clone();
+        duplicate.columns = vec!["n".into(), "n".into()];
+        rejects(duplicate, "duplicate column name 'n'");
+
+        let mut mismatched = base.clone();
+        mismatched.seed.binds.clear();
+        rejects(mismatched, "the seed has 1 placeholder(s) but 0 bind(s)");
+
+        let mut stepless = base.clone();
+        stepless.step = None;
+        rejects(stepless, "union operator without a step");
+
+        let mut terminated = base.clone();
+        terminated.body.sql.push_str("; ");
+        rejects(terminated, "the body ends with ';'");
+
+        let mut unnamed = base;
+        unnamed.name = " ".into();
+        rejects(unnamed, "the CTE name is empty");
+
+        assert!(toml::from_str::<CteSpec>(&format!("max_depth = 3\n{NUMS}")).is_err());
+        assert!(serde_json::from_str::<CteSpec>(r#"{"name": "nums"}"#).is_err());
+    }
+}
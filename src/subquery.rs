@@ -0,0 +1,316 @@
+//! CTE queries embedded as subqueries in ordinary Diesel queries.
+//!
+//! [`WithRecursive`], [`WithCte`], and [`BoxedCteQuery`] work with Diesel's
+//! own `eq_any` and `ne_all`, which render `expr IN (WITH ...)` and
+//! `expr NOT IN (WITH ...)`, and with `QueryDsl::single_value`, which renders
+//! the query as a scalar subquery. Diesel's `dsl::exists` only accepts its own
+//! select statements, because the trait checking where a subselect may
+//! appear is private to Diesel, so [`exists`] renders `EXISTS (WITH ...)`
+//! instead. Each result is a Diesel expression, so it can be passed to
+//! `filter`, `select`, or combined with `and`/`or`.
+
+use diesel::{
+    backend::Backend,
+    expression::{
+        AppearsOnTable, Expression, SelectableExpression, TypedExpressionType, ValidGrouping,
+        is_aggregate,
+    },
+    internal::derives::multiconnection::array_comparison::{AsInExpression, InExpression},
+    query_builder::{AstPass, Query, QueryFragment, QueryId},
+    query_dsl::methods::SingleValueDsl,
+    result::QueryResult,
+    sql_types::{Bool, IntoNotNullable, IntoNullable, SqlType},
+};
+
+use crate::{
+    boxed::BoxedCteQuery,
+    cte::{WithCte, WithRecursive},
+};
+
+/// CTE query rendered in parentheses as a subquery.
+///
+/// The subquery must not reference columns of the enclosing query, since
+/// such references cannot be checked when the CTE is written in raw SQL.
+#[derive(Debug, Clone, Copy)]
+pub struct Subquery<Q> {
+    query: Q,
+}
+
+impl<DB, Q> QueryFragment<DB> for Subquery<Q>
+where
+    DB: Backend,
+    Q: QueryFragment<DB>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        out.push_sql("(");
+        self.query.walk_ast(out.reborrow())?;
+        out.push_sql(")");
+        Ok(())
+    }
+}
+
+impl<Q> QueryId for Subquery<Q>
+where
+    Q: QueryId,
+{
+    type QueryId = Subquery<Q::QueryId>;
+    const HAS_STATIC_QUERY_ID: bool = Q::HAS_STATIC_QUERY_ID;
+}
+
+/// Rows of a CTE query on the right of `IN`, `NOT IN`, or `= ANY`.
+///
+/// Produced when a CTE query is passed to Diesel's `eq_any` or `ne_all`.
+/// Diesel supplies the surrounding parentheses. `ST` is the SQL type of the
+/// compared expression, which may differ from the query's in nullability.
+#[derive(Debug, Clone, Copy)]
+pub struct InCte<Q, ST> {
+    query: Q,
+    _sql_type: core::marker::PhantomData<ST>,
+}
+
+impl<Q, ST> Expression for InCte<Q, ST>
+where
+    ST: SqlType + TypedExpressionType,
+{
+    type SqlType = ST;
+}
+
+impl<Q, ST> InExpression for InCte<Q, ST>
+where
+    ST: SqlType,
+{
+    type SqlType = ST;
+
+    fn is_empty(&self) -> bool {
+        false
+    }
+
+    fn is_array(&self) -> bool {
+        false
+    }
+}
+
+impl<DB, Q, ST> QueryFragment<DB> for InCte<Q, ST>
+where
+    DB: Backend,
+    Q: QueryFragment<DB>,
+{
+    fn walk_ast<'b>(&'b self, out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        self.query.walk_ast(out)
+    }
+}
+
+impl<Q, ST> QueryId for InCte<Q, ST>
+where
+    Q: QueryId,
+    ST: 'static,
+{
+    type QueryId = InCte<Q::QueryId, ST>;
+    const HAS_STATIC_QUERY_ID: bool = Q::HAS_STATIC_QUERY_ID;
+}
+
+impl<Q, ST, QS> AppearsOnTable<QS> for InCte<Q, ST> where Self: Expression {}
+
+impl<Q, ST, QS> SelectableExpression<QS> for InCte<Q, ST> where Self: Expression {}
+
+impl<Q, ST, GB> ValidGrouping<GB> for InCte<Q, ST> {
+    type IsAggregate = is_aggregate::Never;
+}
+
+/// Scalar subquery returning the first column of the CTE query's first row.
+///
+/// Built by Diesel's `single_value`. The expression is nullable because the
+/// query may return no rows; `PostgreSQL` rejects queries returning more than
+/// one, while `SQLite` uses the first.
+#[derive(Debug, Clone, Copy)]
+pub struct SingleValue<Q> {
+    subquery: Subquery<Q>,
+}
+
+impl<Q> Expression for SingleValue<Q>
+where
+    Q: Query,
+    Q::SqlType: IntoNullable,
+    <Q::SqlType as IntoNullable>::Nullable: TypedExpressionType,
+{
+    type SqlType = <Q::SqlType as IntoNullable>::Nullable;
+}
+
+/// `EXISTS` test over a CTE query, built by [`exists`].
+#[derive(Debug, Clone, Copy)]
+pub struct Exists<Q> {
+    subquery: Subquery<Q>,
+}
+
+/// Test whether a CTE query returns any rows.
+pub const fn exists<Q>(query: Q) -> Exists<Q>
+where
+    Q: Query,
+{
+    Exists {
+        subquery: Subquery { query },
+    }
+}
+
+impl<Q> Expression for Exists<Q>
+where
+    Q: Query,
+{
+    type SqlType = Bool;
+}
+
+impl<DB, Q> QueryFragment<DB> for SingleValue<Q>
+where
+    DB: Backend,
+    Q: QueryFragment<DB>,
+{
+    fn walk_ast<'b>(&'b self, out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        self.subquery.walk_ast(out)
+    }
+}
+
+impl<DB, Q> QueryFragment<DB> for Exists<Q>
+where
+    DB: Backend,
+    Q: QueryFragment<DB>,
+{
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DB>) -> QueryResult<()> {
+        out.push_sql("EXISTS ");
+        self.subquery.walk_ast(out)
+    }
+}
+
+/// Implements the traits shared by subquery expressions that do not depend
+/// on the enclosing query.
+macro_rules! impl_standalone_subquery {
+    ($name:ident) => {
+        impl<Q> QueryId for $name<Q>
+        where
+            Q: QueryId,
+        {
+            type QueryId = $name<Q::QueryId>;
+            const HAS_STATIC_QUERY_ID: bool = Q::HAS_STATIC_QUERY_ID;
+        }
+
+        impl<Q, QS> AppearsOnTable<QS> for $name<Q> where Self: Expression {}
+
+        impl<Q, QS> SelectableExpression<QS> for $name<Q> where Self: Expression {}
+
+        impl<Q, GB> ValidGrouping<GB> for $name<Q> {
+            type IsAggregate = is_aggregate::Never;
+        }
+    };
+}
+
+impl_standalone_subquery!(SingleValue);
+impl_standalone_subquery!(Exists);
+
+/// Implements Diesel's `AsInExpression` and `SingleValueDsl` for a CTE query
+/// type, so it can be passed to `eq_any`, `ne_all`, and `single_value`.
+///
+/// The compared expression's SQL type must match the query's up to
+/// nullability.
+macro_rules! impl_cte_subquery {
+    (impl<$($gen:tt),*> for $ty:ty) => {
+        impl<$($gen,)* ST> AsInExpression<ST> for $ty
+        where
+            DB: Backend,
+            Self: Query,
+            <Self as Query>::SqlType: IntoNotNullable<NotNullable = ST::NotNullable>,
+            ST: SqlType + TypedExpressionType + IntoNotNullable,
+        {
+            type InExpression = InCte<Self, ST>;
+
+            fn as_in_expression(self) -> Self::InExpression {
+                InCte {
+                    query: self,
+                    _sql_type: core::marker::PhantomData,
+                }
+            }
+        }
+
+        impl<$($gen),*> SingleValueDsl for $ty
+        where
+            DB: Backend,
+            Self: Query,
+            <Self as Query>::SqlType: IntoNullable,
+        {
+            type Output = SingleValue<Self>;
+
+            fn single_value(self) -> Self::Output {
+                SingleValue {
+                    subquery: Subquery { query: self },
+                }
+            }
+        }
+    };
+}
+
+impl_cte_subquery!(impl<DB, Cols, Seed, Step, Body, Rest> for WithRecursive<DB, Cols, Seed, Step, Body, Rest>);
+impl_cte_subquery!(impl<DB, Cols, Cte, Body, Rest> for WithCte<DB, Cols, Cte, Body, Rest>);
+impl_cte_subquery!(impl<'a, DB, QueryST> for BoxedCteQuery<'a, DB, QueryST>);
+
+#[cfg(test)]
+mod tests {
+    use super::exists;
+    use crate::{
+        boxed::BoxedCteQuery,
+        builders::{self, RecursiveParts},
+        test_support::normalise_debug_sql,
+    };
+    use diesel::{
+        BoolExpressionMethods, ExpressionMethods, NullableExpressionMethods, QueryDsl, debug_query,
+        dsl::sql, sql_types::Integer, sqlite::Sqlite,
+    };
+
+    diesel::table! {
+        nodes (id) {
+            id -> Integer,
+            parent_id -> Nullable<Integer>,
+        }
+    }
+
+    fn subtree() -> BoxedCteQuery<'static, Sqlite, Integer> {
+        builders::with_recursive::<Sqlite, _, _, _, _, _>(
+            "subtree",
+            &["n"],
+            RecursiveParts::new(
+                sql::<Integer>("SELECT 2"),
+                sql::<Integer>("SELECT id FROM nodes JOIN subtree ON parent_id = n"),
+                sql::<Integer>("SELECT n FROM subtree"),
+            ),
+        )
+        .into_boxed()
+    }
+
+    #[test]
+    fn eq_any_renders_in_subquery() {
+        let query = nodes::table
+            .filter(nodes::parent_id.eq_any(subtree()))
+            .select(nodes::id);
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert_eq!(
+            sql,
+            "SELECT \"nodes\".\"id\" FROM \"nodes\" WHERE (\"nodes\".\"parent_id\" IN (WITH RECURSIVE \"subtree\" (\"n\") AS (SELECT 2 UNION ALL SELECT id FROM nodes JOIN subtree ON parent_id = n) SELECT n FROM subtree))"
+        );
+    }
+
+    #[test]
+    fn ne_all_renders_not_in_subquery() {
+        let query = nodes::table
+            .filter(nodes::id.ne_all(subtree()))
+            .select(nodes::id);
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert!(sql.contains("WHERE (\"nodes\".\"id\" NOT IN (WITH RECURSIVE \"subtree\""));
+    }
+
+    #[test]
+    fn exists_and_single_value_render_subqueries() {
+        let query = nodes::table
+            .filter(exists(subtree()).and(nodes::id.nullable().eq(subtree().single_value())))
+            .select(nodes::id);
+        let sql = normalise_debug_sql(&debug_query::<Sqlite, _>(&query).to_string());
+        assert!(sql.contains("WHERE (EXISTS (WITH RECURSIVE \"subtree\""));
+        assert!(sql.contains("AND (\"nodes\".\"id\" = (WITH RECURSIVE \"subtree\""));
+    }
+}
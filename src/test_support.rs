@@ -16,3 +16,17 @@ pub(crate) fn normalise_debug_sql(sql: &str) -> String {
         .trim_end();
     without_binds.replace('`', "\"")
 }
+
+/// Assert that rendering failed with a query builder error mentioning
+/// `needle`.
+///
+/// Backends without an emulation for a feature report it this way instead of
+/// emitting SQL the database would reject.
+#[cfg(feature = "mysql")]
+#[track_caller]
+pub(crate) fn assert_query_builder_error(err: &diesel::result::Error, needle: &str) {
+    assert!(
+        matches!(err, diesel::result::Error::QueryBuilderError(msg) if msg.to_string().contains(needle)),
+        "expected a query builder error mentioning {needle:?}, got {err:?}"
+    );
+}
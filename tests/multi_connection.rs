@@ -0,0 +1,72 @@
+//! Behavioural tests for `RecursiveCTEExt` on a `#[derive(MultiConnection)]`
+//! connection enum.
+#![cfg(all(feature = "sqlite", feature = "postgres"))]
+
+use diesel::{Connection, RunQueryDsl, dsl::sql, sql_types::Integer};
+use diesel_cte_ext::{Hierarchy, RecursiveCTEExt, RecursiveParts};
+
+use any::AnyConnection;
+
+mod any {
+    use diesel_cte_ext::{BreadcrumbBackend, RecursiveBackend};
+
+    #[derive(diesel::MultiConnection)]
+    pub enum AnyConnection {
+        Postgresql(diesel::PgConnection),
+        Sqlite(diesel::SqliteConnection),
+    }
+
+    // The backend is only known at runtime, so neither clause is native,
+    // which is what the defaults declare.
+    impl RecursiveBackend for MultiBackend {}
+
+    // Breadcrumb keys have no representation shared by both backends.
+    impl BreadcrumbBackend for MultiBackend {
+        type Keys<ST: 'static> = diesel::sql_types::Text;
+    }
+}
+
+diesel::table! {
+    /// Categories labelled by name.
+    categories (id) {
+        /// Category identifier.
+        id -> Integer,
+        /// Parent category, `NULL` for roots.
+        parent_id -> Nullable<Integer>,
+        /// Label shown in breadcrumbs.
+        name -> Text,
+    }
+}
+
+#[test]
+fn multi_connection_runs_recursive_ctes() {
+    let mut conn = AnyConnection::establish(":memory:").expect("in-memory sqlite");
+    let rows: Vec<i32> = conn
+        .with_recursive(
+            "nums",
+            &["n"],
+            RecursiveParts::new(
+                sql::<Integer>("SELECT 1"),
+                sql::<Integer>("SELECT n + 1 FROM nums WHERE n < 3"),
+                sql::<Integer>("SELECT n FROM nums"),
+            ),
+        )
+        .load(&mut conn)
+        .expect("load rows");
+    assert_eq!(rows, vec![1, 2, 3]);
+}
+
+#[test]
+fn multi_connection_rejects_breadcrumbs() {
+    let mut conn = AnyConnection::establish(":memory:").expect("in-memory sqlite");
+    let crumbs =
+        Hierarchy::new(categories::id, categories::parent_id).breadcrumbs(categories::name);
+    let err = crumbs
+        .all()
+        .load::<(i32, String, String)>(&mut conn)
+        .expect_err("breadcrumb keys need a backend-specific representation");
+    assert!(
+        err.to_string()
+            .contains("breadcrumb keys are not supported")
+    );
+}
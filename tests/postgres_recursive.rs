@@ -1,5 +1,5 @@
-#![cfg(feature = "postgres")]
 //! Behavioural tests for recursive CTE helpers on `PostgreSQL`.
+#![cfg(feature = "postgres")]
 
 #[path = "test_helpers.rs"]
 mod test_helpers;
@@ -8,7 +8,12 @@ use diesel::RunQueryDsl as DieselRunQueryDsl;
 use diesel::{dsl::sql, sql_types::Integer};
 #[cfg(feature = "async")]
 use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl as AsyncRunQueryDsl};
-use diesel_cte_ext::{CteParts, RecursiveCTEExt, RecursiveParts};
+#[cfg(feature = "async")]
+use diesel_cte_ext::AsyncRecursiveCTEExt;
+use diesel_cte_ext::{
+    CteParts, CteTable, CycleCheck, DepthLimit, DepthTerm, Graph, Hierarchy, Materialization,
+    Orientation, RecursiveCTEExt, RecursiveParts, SearchOrder, UnionKind, seed_query, step_query,
+};
 use pg_embedded_setup_unpriv::{BootstrapResult, TestCluster};
 use rstest::{fixture, rstest};
 
@@ -98,6 +103,502 @@ fn recursive_sequence_via_async_conn(embedded_cluster: GuardedCluster) -> TestRe
     Ok(())
 }
 
+#[cfg(feature = "r2d2")]
+#[rstest]
+fn recursive_sequence_via_r2d2_pool(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel::{
+        PgConnection,
+        r2d2::{ConnectionManager, Pool},
+    };
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let db_url = cluster.connection().database_url("postgres");
+    let pool = Pool::builder()
+        .max_size(1)
+        .build(ConnectionManager::<PgConnection>::new(db_url))?;
+    let mut conn = pool.get()?;
+
+    let rows: Vec<i32> = DieselRunQueryDsl::load(
+        conn.with_recursive(
+            "t",
+            &["n"],
+            RecursiveParts::new(
+                sql::<Integer>("SELECT 1"),
+                sql::<Integer>("SELECT n + 1 FROM t WHERE n < 3"),
+                sql::<Integer>("SELECT n FROM t ORDER BY n"),
+            ),
+        ),
+        &mut conn,
+    )?;
+
+    if rows != [1, 2, 3] {
+        return Err(format!("expected [1, 2, 3] but saw {rows:?}").into());
+    }
+    Ok(())
+}
+
+#[cfg(any(feature = "deadpool", feature = "bb8"))]
+async fn load_pooled_sequence<C>(conn: &mut C) -> TestResult<Vec<i32>>
+where
+    C: AsyncRecursiveCTEExt<Backend = diesel::pg::Pg>
+        + diesel_async::AsyncConnectionCore<Backend = diesel::pg::Pg>,
+{
+    let rows = AsyncRunQueryDsl::load(
+        conn.with_recursive(
+            "t",
+            &["n"],
+            RecursiveParts::new(
+                sql::<Integer>("SELECT 1"),
+                sql::<Integer>("SELECT n + 1 FROM t WHERE n < 3"),
+                sql::<Integer>("SELECT n FROM t ORDER BY n"),
+            ),
+        ),
+        conn,
+    )
+    .await?;
+    Ok(rows)
+}
+
+#[cfg(any(feature = "deadpool", feature = "bb8"))]
+#[rstest]
+fn recursive_sequence_via_async_pools(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel_async::pooled_connection::AsyncDieselConnectionManager;
+    use tokio::runtime::Builder;
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let rt = Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()
+        .expect("tokio runtime");
+    let db_url = cluster.connection().database_url("postgres");
+
+    rt.block_on(async move {
+        #[cfg(feature = "deadpool")]
+        {
+            use diesel_async::pooled_connection::deadpool::Pool;
+
+            let manager = AsyncDieselConnectionManager::<AsyncPgConnection>::new(&db_url);
+            let pool = Pool::builder(manager).max_size(1).build()?;
+            let rows = load_pooled_sequence(&mut pool.get().await?).await?;
+            if rows != [1, 2, 3] {
+                return Err(format!("deadpool saw {rows:?}").into());
+            }
+        }
+
+        #[cfg(feature = "bb8")]
+        {
+            use diesel_async::pooled_connection::bb8::Pool;
+
+            let manager = AsyncDieselConnectionManager::<AsyncPgConnection>::new(&db_url);
+            let pool = Pool::builder().max_size(1).build(manager).await?;
+            let rows = load_pooled_sequence(&mut pool.get().await?).await?;
+            if rows != [1, 2, 3] {
+                return Err(format!("bb8 saw {rows:?}").into());
+            }
+        }
+
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+    })?;
+    Ok(())
+}
+
+diesel::table! {
+    /// Tree rows removed by the subtree deletion tests.
+    nodes (id) {
+        /// Node identifier.
+        id -> Integer,
+        /// Identifier of the parent node, `NULL` for roots.
+        parent_id -> Nullable<Integer>,
+    }
+}
+
+const NODES_SQL: &str = "CREATE TEMPORARY TABLE nodes (id INTEGER PRIMARY KEY, parent_id INTEGER);
+     INSERT INTO nodes VALUES (1, NULL), (2, 1), (3, 2), (4, 1), (5, NULL);";
+
+#[rstest]
+fn with_prefix_deletes_subtree_via_sync_conn(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel::{QueryDsl, connection::SimpleConnection, sql_types::Bool};
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(NODES_SQL)?;
+
+    let deleted = DieselRunQueryDsl::execute(
+        conn.with_recursive(
+            "subtree",
+            &["n"],
+            RecursiveParts::new(
+                sql::<Integer>("SELECT 2"),
+                sql::<Integer>("SELECT id FROM nodes JOIN subtree ON parent_id = n"),
+                diesel::delete(nodes::table.filter(sql::<Bool>("id IN (SELECT n FROM subtree)"))),
+            ),
+        ),
+        &mut conn,
+    )?;
+    let remaining: Vec<i32> =
+        DieselRunQueryDsl::load(nodes::table.select(nodes::id).order(nodes::id), &mut conn)?;
+
+    if deleted != 2 || remaining != [1, 4, 5] {
+        return Err(format!("deleted {deleted}, left {remaining:?}").into());
+    }
+    Ok(())
+}
+
+/// Row of `nodes` loaded through its `Selectable` implementation.
+#[derive(Debug, PartialEq, Eq, diesel::Queryable, diesel::Selectable)]
+#[diesel(table_name = nodes)]
+struct Node {
+    id: i32,
+    parent_id: Option<i32>,
+}
+
+#[rstest]
+fn hierarchy_walks_descendants_and_ancestors(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel::{QueryDsl, SelectableHelper, connection::SimpleConnection};
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(NODES_SQL)?;
+    let tree = Hierarchy::new(nodes::id, nodes::parent_id);
+
+    let below: Vec<(Node, i32)> =
+        DieselRunQueryDsl::load(tree.descendants_of(2).select(Node::as_select()), &mut conn)?;
+    let above: Vec<((i32, Option<i32>), i32)> =
+        DieselRunQueryDsl::load(tree.ancestors_of(3), &mut conn)?;
+
+    let expected_below = [
+        (
+            Node {
+                id: 2,
+                parent_id: Some(1),
+            },
+            0,
+        ),
+        (
+            Node {
+                id: 3,
+                parent_id: Some(2),
+            },
+            1,
+        ),
+    ];
+    let expected_above = [((3, Some(2)), 0), ((2, Some(1)), 1), ((1, None), 2)];
+    if below != expected_below || above != expected_above {
+        return Err(format!("unexpected descendants {below:?} or ancestors {above:?}").into());
+    }
+    Ok(())
+}
+
+#[rstest]
+fn hierarchy_max_depth_stops_parent_loops(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel::connection::SimpleConnection;
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(
+        "CREATE TEMPORARY TABLE nodes (id INTEGER PRIMARY KEY, parent_id INTEGER);
+         INSERT INTO nodes VALUES (1, 3), (2, 1), (3, 2);",
+    )?;
+    let tree = Hierarchy::new(nodes::id, nodes::parent_id).with_max_depth(4);
+
+    let below: Vec<((i32, Option<i32>), i32)> =
+        DieselRunQueryDsl::load(tree.descendants_of(1), &mut conn)?;
+
+    let expected = [
+        ((1, Some(3)), 0),
+        ((2, Some(1)), 1),
+        ((3, Some(2)), 2),
+        ((1, Some(3)), 3),
+        ((2, Some(1)), 4),
+    ];
+    if below != expected {
+        return Err(format!("unexpected descendants {below:?}").into());
+    }
+    Ok(())
+}
+
+#[rstest]
+fn cte_queries_filter_as_subqueries(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel::{
+        BoolExpressionMethods, ExpressionMethods, NullableExpressionMethods, QueryDsl,
+        connection::SimpleConnection,
+    };
+    use diesel_cte_ext::{builders, subquery::exists};
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(NODES_SQL)?;
+    let subtree = |root: i32| {
+        builders::with_recursive::<diesel::pg::Pg, _, _, _, _, _>(
+            "subtree",
+            &["n"],
+            RecursiveParts::new(
+                sql::<Integer>("SELECT ").bind::<Integer, _>(root),
+                sql::<Integer>("SELECT id FROM nodes JOIN subtree ON parent_id = n"),
+                sql::<Integer>("SELECT n FROM subtree ORDER BY n"),
+            ),
+        )
+    };
+
+    let children: Vec<i32> = DieselRunQueryDsl::load(
+        nodes::table
+            .filter(nodes::parent_id.eq_any(subtree(2)))
+            .select(nodes::id),
+        &mut conn,
+    )?;
+    let outside: Vec<i32> = DieselRunQueryDsl::load(
+        nodes::table
+            .filter(nodes::id.ne_all(subtree(1)))
+            .select(nodes::id),
+        &mut conn,
+    )?;
+    let leaf: Vec<i32> = DieselRunQueryDsl::load(
+        nodes::table
+            .filter(exists(subtree(2)).and(nodes::id.nullable().eq(subtree(3).single_value())))
+            .select(nodes::id),
+        &mut conn,
+    )?;
+
+    if children != [3] || outside != [5] || leaf != [3] {
+        return Err(format!("got {children:?}, {outside:?}, {leaf:?}").into());
+    }
+    Ok(())
+}
+
+diesel_cte_ext::cte_table! {
+    /// Recursive CTE walking `nodes` from the roots.
+    tree (id) {
+        /// Node identifier.
+        id -> Integer,
+        /// Distance from the root.
+        depth -> Integer,
+    }
+}
+
+diesel::allow_tables_to_appear_in_same_query!(nodes, tree);
+
+#[rstest]
+fn cte_table_drives_dsl_queries(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel::{
+        ExpressionMethods, IntoSql, JoinOnDsl, NullableExpressionMethods, QueryDsl,
+        connection::SimpleConnection,
+    };
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(NODES_SQL)?;
+
+    let rows: Vec<(i32, i32)> = DieselRunQueryDsl::load(
+        conn.with_recursive(
+            tree::table::NAME,
+            tree::table::columns(),
+            RecursiveParts::new(
+                nodes::table
+                    .filter(nodes::parent_id.is_null())
+                    .select((nodes::id, 0.into_sql::<Integer>())),
+                nodes::table
+                    .inner_join(tree::table.on(nodes::parent_id.eq(tree::id.nullable())))
+                    .select((nodes::id, tree::depth + 1)),
+                tree::table
+                    .filter(tree::depth.lt(2))
+                    .order((tree::depth, tree::id))
+                    .select((tree::id, tree::depth)),
+            ),
+        ),
+        &mut conn,
+    )?;
+
+    let expected = [(1, 0), (5, 0), (2, 1), (4, 1)];
+    if rows != expected {
+        return Err(format!("expected {expected:?} but saw {rows:?}").into());
+    }
+    Ok(())
+}
+
+#[rstest]
+fn dsl_methods_refine_cte_output(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel::{
+        ExpressionMethods, IntoSql, JoinOnDsl, NullableExpressionMethods, QueryDsl,
+        connection::SimpleConnection,
+    };
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(NODES_SQL)?;
+
+    let query = conn
+        .with_recursive(
+            tree::table::NAME,
+            tree::table::columns(),
+            RecursiveParts::new(
+                nodes::table
+                    .filter(nodes::parent_id.is_null())
+                    .select((nodes::id, 0.into_sql::<Integer>())),
+                nodes::table
+                    .inner_join(tree::table.on(nodes::parent_id.eq(tree::id.nullable())))
+                    .select((nodes::id, tree::depth + 1)),
+                tree::table,
+            ),
+        )
+        .filter(tree::depth.gt(0))
+        .order_by(tree::depth.desc())
+        .then_order_by(tree::id)
+        .limit(2)
+        .offset(1)
+        .select(tree::id);
+    let rows: Vec<i32> = DieselRunQueryDsl::load(query, &mut conn)?;
+
+    let expected = [2, 4];
+    if rows != expected {
+        return Err(format!("expected {expected:?} but saw {rows:?}").into());
+    }
+    Ok(())
+}
+
+#[rstest]
+fn statement_cache_distinguishes_runtime_column_names(
+    embedded_cluster: GuardedCluster,
+) -> TestResult<()> {
+    use diesel::{ExpressionMethods, IntoSql, QueryDsl, connection::SimpleConnection};
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(NODES_SQL)?;
+
+    let mut load_ids = |columns: &'static [&'static str]| {
+        DieselRunQueryDsl::load::<i32>(
+            conn.with_cte(
+                tree::table::NAME,
+                columns,
+                CteParts::new(
+                    nodes::table
+                        .filter(nodes::id.eq(2))
+                        .select((nodes::id, 7.into_sql::<Integer>())),
+                    tree::table.select(tree::id),
+                ),
+            ),
+            &mut conn,
+        )
+    };
+    let by_id = load_ids(&["id", "depth"])?;
+    let by_depth = load_ids(&["depth", "id"])?;
+
+    if by_id != [2] || by_depth != [7] {
+        return Err(format!("saw {by_id:?} and {by_depth:?}").into());
+    }
+    Ok(())
+}
+
+#[rstest]
+fn statement_cache_skips_typed_columns_under_other_names(
+    embedded_cluster: GuardedCluster,
+) -> TestResult<()> {
+    use diesel::{ExpressionMethods, IntoSql, QueryDsl, connection::SimpleConnection};
+    use diesel_cte_ext::Columns;
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(NODES_SQL)?;
+
+    let mut load_ids = |columns: Columns<(tree::id, tree::depth)>| {
+        DieselRunQueryDsl::load::<i32>(
+            conn.with_cte(
+                tree::table::NAME,
+                columns,
+                CteParts::new(
+                    nodes::table
+                        .filter(nodes::id.eq(2))
+                        .select((nodes::id, 7.into_sql::<Integer>())),
+                    tree::table.select(tree::id),
+                ),
+            ),
+            &mut conn,
+        )
+    };
+    let by_id = load_ids(tree::table::columns())?;
+    let by_depth = load_ids(Columns::raw(&["depth", "id"]))?;
+
+    if by_id != [2] || by_depth != [7] {
+        return Err(format!("saw {by_id:?} and {by_depth:?}").into());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[rstest]
+fn spec_from_json_binds_values(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel::{pg::Pg, sql_types::BigInt};
+    use diesel_cte_ext::CteSpec;
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+
+    let spec: CteSpec = serde_json::from_str(
+        r#"{
+            "name": "t",
+            "columns": ["n"],
+            "limit": 3,
+            "seed": { "sql": "SELECT ?", "binds": [2] },
+            "step": { "sql": "SELECT n * ? FROM t WHERE n < ?", "binds": [2, 100] },
+            "body": { "sql": "SELECT n FROM t ORDER BY n" }
+        }"#,
+    )?;
+    let rows: Vec<i64> = DieselRunQueryDsl::load(spec.build::<Pg, BigInt>()?, &mut conn)?;
+
+    let expected = [2, 4, 8];
+    if rows != expected {
+        return Err(format!("expected {expected:?} but saw {rows:?}").into());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[rstest]
+fn with_prefix_deletes_subtree_via_async_conn(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel::{QueryDsl, sql_types::Bool};
+    use diesel_async::SimpleAsyncConnection;
+    use tokio::runtime::Builder;
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let rt = Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()
+        .expect("tokio runtime");
+
+    let db_url = cluster.connection().database_url("postgres");
+
+    rt.block_on(async move {
+        let mut conn = AsyncPgConnection::establish(&db_url).await?;
+        conn.batch_execute(NODES_SQL).await?;
+
+        let deleted = AsyncRunQueryDsl::execute(
+            conn.with_recursive(
+                "subtree",
+                &["n"],
+                RecursiveParts::new(
+                    sql::<Integer>("SELECT 1"),
+                    sql::<Integer>("SELECT id FROM nodes JOIN subtree ON parent_id = n"),
+                    diesel::delete(
+                        nodes::table.filter(sql::<Bool>("id IN (SELECT n FROM subtree)")),
+                    ),
+                ),
+            ),
+            &mut conn,
+        )
+        .await?;
+
+        if deleted != 4 {
+            return Err(format!("expected 4 deleted rows but saw {deleted}").into());
+        }
+
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+    })?;
+
+    Ok(())
+}
+
 #[rstest]
 fn non_recursive_cte_returns_seed(embedded_cluster: GuardedCluster) -> TestResult<()> {
     let (_env_guard, cluster) = embedded_cluster?;
@@ -120,3 +621,458 @@ fn non_recursive_cte_returns_seed(embedded_cluster: GuardedCluster) -> TestResul
     }
     Ok(())
 }
+
+#[rstest]
+#[case::materialized(Materialization::Materialized)]
+#[case::not_materialized(Materialization::NotMaterialized)]
+fn materialization_hint_is_accepted(
+    embedded_cluster: GuardedCluster,
+    #[case] hint: Materialization,
+) -> TestResult<()> {
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+
+    let rows: Vec<i32> = DieselRunQueryDsl::load(
+        conn.with_cte(
+            "seed",
+            &["n"],
+            CteParts::new(
+                sql::<Integer>("SELECT 1 UNION ALL SELECT 2"),
+                sql::<Integer>("SELECT n FROM seed ORDER BY n"),
+            )
+            .with_materialization(hint),
+        ),
+        &mut conn,
+    )?;
+
+    let expected = [1, 2];
+    if rows != expected {
+        return Err(format!("expected {expected:?} but saw {rows:?}").into());
+    }
+    Ok(())
+}
+
+#[rstest]
+fn data_modifying_cte_moves_rows(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel::{ExpressionMethods, QueryDsl, connection::SimpleConnection};
+
+    diesel::table! {
+        tasks (id) {
+            id -> Integer,
+            done -> Bool,
+        }
+    }
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(
+        "CREATE TEMPORARY TABLE tasks (id INTEGER PRIMARY KEY, done BOOLEAN NOT NULL);
+         CREATE TEMPORARY TABLE archive (id INTEGER PRIMARY KEY);
+         INSERT INTO tasks VALUES (1, TRUE), (2, FALSE), (3, TRUE);",
+    )?;
+
+    let archived: Vec<i32> = DieselRunQueryDsl::load(
+        conn.with_cte(
+            "moved",
+            &["id"],
+            CteParts::new(
+                diesel::delete(tasks::table.filter(tasks::done.eq(true))).returning(tasks::id),
+                sql::<Integer>("INSERT INTO archive SELECT id FROM moved RETURNING id"),
+            ),
+        ),
+        &mut conn,
+    )?;
+    let remaining: Vec<i32> =
+        DieselRunQueryDsl::load(tasks::table.select(tasks::id).order(tasks::id), &mut conn)?;
+
+    let mut sorted = archived;
+    sorted.sort_unstable();
+    if sorted != [1, 3] || remaining != [2] {
+        return Err(format!("archived {sorted:?}, left {remaining:?}").into());
+    }
+    Ok(())
+}
+
+#[rstest]
+fn distinct_union_terminates_on_cycles(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel::connection::SimpleConnection;
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(
+        "CREATE TEMPORARY TABLE edges (src INTEGER NOT NULL, dst INTEGER NOT NULL);
+         INSERT INTO edges VALUES (1, 2), (2, 3), (3, 1);",
+    )?;
+
+    let rows: Vec<i32> = DieselRunQueryDsl::load(
+        conn.with_recursive(
+            "reach",
+            &["node"],
+            RecursiveParts::new(
+                sql::<Integer>("SELECT 1"),
+                sql::<Integer>("SELECT dst FROM edges JOIN reach ON src = node"),
+                sql::<Integer>("SELECT node FROM reach ORDER BY node"),
+            )
+            .with_union(UnionKind::Distinct),
+        ),
+        &mut conn,
+    )?;
+
+    let expected = [1, 2, 3];
+    if rows != expected {
+        return Err(format!("expected {expected:?} but saw {rows:?}").into());
+    }
+    Ok(())
+}
+
+#[rstest]
+#[case::depth_first(SearchOrder::depth_first("id", "ord"), [1, 2, 4, 6, 3, 5])]
+#[case::breadth_first(SearchOrder::breadth_first("id", "ord"), [1, 2, 3, 4, 5, 6])]
+fn search_orders_tree_rows(
+    embedded_cluster: GuardedCluster,
+    #[case] search: SearchOrder,
+    #[case] expected: [i32; 6],
+) -> TestResult<()> {
+    use diesel::connection::SimpleConnection;
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(
+        "CREATE TEMPORARY TABLE nodes (id INTEGER PRIMARY KEY, parent_id INTEGER);
+         INSERT INTO nodes VALUES (1, NULL), (2, 1), (3, 1), (4, 2), (5, 3), (6, 2);",
+    )?;
+
+    let rows: Vec<i32> = DieselRunQueryDsl::load(
+        conn.with_recursive(
+            "tree",
+            &["id"],
+            RecursiveParts::new(
+                seed_query!(
+                    sql::<Integer>("SELECT n.id"),
+                    search.seed_key("n", "id"),
+                    sql::<Integer>(" FROM nodes n WHERE n.parent_id IS NULL"),
+                ),
+                step_query!(
+                    sql::<Integer>("SELECT n.id"),
+                    search.step_key("n", "id", "tree"),
+                    sql::<Integer>(" FROM nodes n JOIN tree ON n.parent_id = tree.id"),
+                ),
+                sql::<Integer>("SELECT id FROM tree ORDER BY ord"),
+            )
+            .with_search(search),
+        ),
+        &mut conn,
+    )?;
+
+    if rows != expected {
+        return Err(format!("expected {expected:?} but saw {rows:?}").into());
+    }
+    Ok(())
+}
+
+#[rstest]
+fn cycle_check_marks_and_stops_cycles(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel::{connection::SimpleConnection, sql_types::Bool};
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(
+        "CREATE TEMPORARY TABLE edges (src INTEGER NOT NULL, dst INTEGER NOT NULL);
+         INSERT INTO edges VALUES (1, 2), (2, 3), (3, 1), (3, 4);",
+    )?;
+
+    let check = CycleCheck::new("id", "is_cycle", "path");
+    let rows: Vec<(i32, bool)> = DieselRunQueryDsl::load(
+        conn.with_recursive(
+            "walk",
+            &["id"],
+            RecursiveParts::new(
+                seed_query!(
+                    sql::<Integer>("SELECT s.id"),
+                    check.seed_key("s", "id"),
+                    sql::<Integer>(" FROM (SELECT 1 AS id) s"),
+                ),
+                step_query!(
+                    sql::<Integer>("SELECT e.dst"),
+                    check.step_key("e", "dst", "walk"),
+                    sql::<Integer>(" FROM edges e JOIN walk ON e.src = walk.id WHERE "),
+                    check.step_guard("walk"),
+                ),
+                sql::<(Integer, Bool)>("SELECT id, is_cycle FROM walk ORDER BY id, is_cycle"),
+            )
+            .with_cycle(check),
+        ),
+        &mut conn,
+    )?;
+
+    let expected = [(1, false), (1, true), (2, false), (3, false), (4, false)];
+    if rows != expected {
+        return Err(format!("expected {expected:?} but saw {rows:?}").into());
+    }
+    Ok(())
+}
+
+#[rstest]
+fn depth_limit_counts_levels_and_stops(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel::connection::SimpleConnection;
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(
+        "CREATE TEMPORARY TABLE nodes (id INTEGER PRIMARY KEY, parent_id INTEGER);
+         INSERT INTO nodes VALUES (1, NULL), (2, 1), (3, 1), (4, 2), (5, 4);",
+    )?;
+
+    let depth = DepthLimit::new("depth", 2);
+    let rows: Vec<(i32, i32)> = DieselRunQueryDsl::load(
+        conn.with_recursive(
+            "tree",
+            &["id"],
+            RecursiveParts::new(
+                sql::<Integer>("SELECT id FROM nodes WHERE parent_id IS NULL"),
+                DepthTerm::new(
+                    sql::<Integer>("n.id"),
+                    sql::<Integer>("nodes n JOIN tree ON n.parent_id = tree.id"),
+                ),
+                sql::<(Integer, Integer)>("SELECT id, depth FROM tree ORDER BY id"),
+            ),
+        )
+        .with_depth(depth),
+        &mut conn,
+    )?;
+
+    let expected = [(1, 0), (2, 1), (3, 1), (4, 2)];
+    if rows != expected {
+        return Err(format!("expected {expected:?} but saw {rows:?}").into());
+    }
+    Ok(())
+}
+
+#[rstest]
+fn depth_limit_extends_dsl_steps(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel::{
+        ExpressionMethods, JoinOnDsl, NullableExpressionMethods, QueryDsl,
+        connection::SimpleConnection,
+    };
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(NODES_SQL)?;
+
+    let rows: Vec<(i32, i32)> = DieselRunQueryDsl::load(
+        conn.with_recursive(
+            tree::table::NAME,
+            &["id"],
+            RecursiveParts::new(
+                sql::<Integer>("SELECT id FROM nodes WHERE parent_id IS NULL"),
+                nodes::table
+                    .inner_join(tree::table.on(nodes::parent_id.eq(tree::id.nullable())))
+                    .select(nodes::id),
+                tree::table.order(tree::id).select((tree::id, tree::depth)),
+            ),
+        )
+        .with_depth(DepthLimit::new("depth", 1)),
+        &mut conn,
+    )?;
+
+    let expected = [(1, 0), (2, 1), (4, 1), (5, 0)];
+    if rows != expected {
+        return Err(format!("expected {expected:?} but saw {rows:?}").into());
+    }
+    Ok(())
+}
+
+diesel::table! {
+    /// Directed edges walked by the graph traversal tests.
+    edges (src, dst) {
+        /// Node the edge leaves.
+        src -> Integer,
+        /// Node the edge enters.
+        dst -> Integer,
+    }
+}
+
+#[rstest]
+fn graph_reaches_nodes_through_cycles(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel::connection::SimpleConnection;
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(
+        "CREATE TEMPORARY TABLE edges (src INTEGER NOT NULL, dst INTEGER NOT NULL);
+         INSERT INTO edges VALUES (1, 2), (2, 3), (3, 1), (3, 4), (1, 4), (5, 1);",
+    )?;
+
+    let rows: Vec<(i32, i32)> = DieselRunQueryDsl::load(
+        Graph::new(edges::src, edges::dst).reachable_from(1),
+        &mut conn,
+    )?;
+
+    let expected = [(1, 0), (2, 1), (4, 1), (3, 2)];
+    if rows != expected {
+        return Err(format!("expected {expected:?} but saw {rows:?}").into());
+    }
+    Ok(())
+}
+
+#[rstest]
+fn graph_bounds_dense_traversals(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel::connection::SimpleConnection;
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(
+        "CREATE TEMPORARY TABLE edges (src INTEGER NOT NULL, dst INTEGER NOT NULL);
+         WITH RECURSIVE n (i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 10)
+         INSERT INTO edges SELECT a.i, b.i FROM n a, n b WHERE a.i <> b.i;",
+    )?;
+    let graph = Graph::new(edges::src, edges::dst);
+
+    let nodes: Vec<i32> = DieselRunQueryDsl::load(graph.nodes_reachable_from(1), &mut conn)?;
+    let near: Vec<(i32, i32)> =
+        DieselRunQueryDsl::load(graph.with_max_hops(1).reachable_from(1), &mut conn)?;
+
+    let expected_near: Vec<(i32, i32)> = std::iter::once((1, 0))
+        .chain((2..=10).map(|node| (node, 1)))
+        .collect();
+    if nodes != (1..=10).collect::<Vec<_>>() || near != expected_near {
+        return Err(format!("unexpected nodes {nodes:?} or nearby nodes {near:?}").into());
+    }
+    Ok(())
+}
+
+#[rstest]
+fn undirected_graph_follows_edges_both_ways(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel::connection::SimpleConnection;
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(
+        "CREATE TEMPORARY TABLE edges (src INTEGER NOT NULL, dst INTEGER NOT NULL);
+         INSERT INTO edges VALUES (1, 2), (2, 3), (3, 1), (4, 3), (5, 6);",
+    )?;
+
+    let rows: Vec<(i32, i32)> = DieselRunQueryDsl::load(
+        Graph::new(edges::src, edges::dst)
+            .with_orientation(Orientation::Undirected)
+            .reachable_from(1),
+        &mut conn,
+    )?;
+
+    let expected = [(1, 0), (2, 1), (3, 1), (4, 2)];
+    if rows != expected {
+        return Err(format!("expected {expected:?} but saw {rows:?}").into());
+    }
+    Ok(())
+}
+
+diesel::table! {
+    /// Labelled tree rows walked by the breadcrumb tests.
+    categories (id) {
+        /// Category identifier.
+        id -> Integer,
+        /// Identifier of the parent category, `NULL` for roots.
+        parent_id -> Nullable<Integer>,
+        /// Label shown in breadcrumbs.
+        name -> Varchar,
+    }
+}
+
+#[rstest]
+fn breadcrumbs_join_labels_and_keys_from_the_root(
+    embedded_cluster: GuardedCluster,
+) -> TestResult<()> {
+    use diesel::connection::SimpleConnection;
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(
+        "CREATE TEMPORARY TABLE categories (
+             id INTEGER PRIMARY KEY,
+             parent_id INTEGER,
+             name VARCHAR(40) NOT NULL
+         );
+         INSERT INTO categories VALUES
+             (1, NULL, 'Root'), (2, 1, 'Electronics'), (3, 2, 'Phones'),
+             (4, 1, 'Books'), (5, NULL, 'Archive');",
+    )?;
+    let crumbs =
+        Hierarchy::new(categories::id, categories::parent_id).breadcrumbs(categories::name);
+
+    let every: Vec<(i32, String, Vec<i32>)> = DieselRunQueryDsl::load(crumbs.all(), &mut conn)?;
+    let phones: Vec<(i32, String, Vec<i32>)> =
+        DieselRunQueryDsl::load(crumbs.with_separator(" / ").of(3), &mut conn)?;
+
+    let expected_every = [
+        (1, "Root".to_owned(), vec![1]),
+        (2, "Root > Electronics".to_owned(), vec![1, 2]),
+        (3, "Root > Electronics > Phones".to_owned(), vec![1, 2, 3]),
+        (4, "Root > Books".to_owned(), vec![1, 4]),
+        (5, "Archive".to_owned(), vec![5]),
+    ];
+    let expected_phones = [(3, "Root / Electronics / Phones".to_owned(), vec![1, 2, 3])];
+    if every != expected_every || phones != expected_phones {
+        return Err(format!("unexpected breadcrumbs {every:?} or {phones:?}").into());
+    }
+    Ok(())
+}
+
+#[rstest]
+fn breadcrumbs_stop_at_the_maximum_depth(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel::connection::SimpleConnection;
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(
+        "CREATE TEMPORARY TABLE categories (
+             id INTEGER PRIMARY KEY,
+             parent_id INTEGER,
+             name VARCHAR(40) NOT NULL
+         );
+         INSERT INTO categories VALUES
+             (1, NULL, 'Root'), (2, 1, 'Electronics'), (3, 2, 'Phones'), (4, 1, 'Books');",
+    )?;
+    let crumbs = Hierarchy::new(categories::id, categories::parent_id)
+        .with_max_depth(1)
+        .breadcrumbs(categories::name);
+
+    let every: Vec<(i32, String, Vec<i32>)> = DieselRunQueryDsl::load(crumbs.all(), &mut conn)?;
+    let phones: Vec<(i32, String, Vec<i32>)> = DieselRunQueryDsl::load(crumbs.of(3), &mut conn)?;
+
+    let expected_every = [
+        (1, "Root".to_owned(), vec![1]),
+        (2, "Root > Electronics".to_owned(), vec![1, 2]),
+        (4, "Root > Books".to_owned(), vec![1, 4]),
+    ];
+    if every != expected_every || !phones.is_empty() {
+        return Err(format!("unexpected breadcrumbs {every:?} or {phones:?}").into());
+    }
+    Ok(())
+}
+
+#[rstest]
+fn breadcrumbs_stop_at_parent_loops(embedded_cluster: GuardedCluster) -> TestResult<()> {
+    use diesel::connection::SimpleConnection;
+
+    let (_env_guard, cluster) = embedded_cluster?;
+    let mut conn = cluster.connection().diesel_connection("postgres")?;
+    conn.batch_execute(
+        "CREATE TEMPORARY TABLE categories (
+             id INTEGER PRIMARY KEY,
+             parent_id INTEGER,
+             name VARCHAR(40) NOT NULL
+         );
+         INSERT INTO categories VALUES
+             (1, NULL, 'Root'), (2, 1, 'Phones'), (3, 4, 'Loop'), (4, 3, 'Pool'), (5, 3, 'Stray');",
+    )?;
+    let crumbs =
+        Hierarchy::new(categories::id, categories::parent_id).breadcrumbs(categories::name);
+
+    let stray: Vec<(i32, String, Vec<i32>)> = DieselRunQueryDsl::load(crumbs.of(5), &mut conn)?;
+    let phones: Vec<(i32, String, Vec<i32>)> = DieselRunQueryDsl::load(crumbs.of(2), &mut conn)?;
+
+    if !stray.is_empty() || phones != [(2, "Root > Phones".to_owned(), vec![1, 2])] {
+        return Err(format!("unexpected breadcrumbs {stray:?} or {phones:?}").into());
+    }
+    Ok(())
+}
@@ -1,9 +1,18 @@
-#![cfg(feature = "sqlite")]
 //! Behavioural tests ensuring the `SQLite` implementations of `RecursiveCTEExt`
 //! function across sync and async entry points.
+#![cfg(feature = "sqlite")]
 
-use diesel::{Connection, dsl::sql, sql_types::Integer, sqlite::SqliteConnection};
-use diesel_cte_ext::{RecursiveCTEExt, RecursiveParts};
+use diesel::{
+    Connection, QueryDsl,
+    dsl::sql,
+    sql_types::{Bool, Integer},
+    sqlite::SqliteConnection,
+};
+use diesel_cte_ext::{
+    BoxedCteQuery, CteColumns, CteParts, CteTable, CycleCheck, DepthLimit, DepthTerm, Graph,
+    Hierarchy, Materialization, Orientation, RecursiveCTEExt, RecursiveParts, RecursiveTerms,
+    SearchOrder, UnionKind, seed_query, step_query,
+};
 
 #[test]
 fn sqlite_sync_recursive_sequence() {
@@ -24,6 +33,939 @@ fn sqlite_sync_recursive_sequence() {
     assert_eq!(rows, vec![1, 2, 3, 4]);
 }
 
+#[cfg(feature = "r2d2")]
+#[test]
+fn sqlite_r2d2_pooled_connection_runs_recursive_ctes() {
+    use diesel::{
+        RunQueryDsl,
+        r2d2::{ConnectionManager, Pool},
+    };
+    let pool = Pool::builder()
+        .max_size(1)
+        .build(ConnectionManager::<SqliteConnection>::new(":memory:"))
+        .expect("sqlite pool");
+    let mut conn = pool.get().expect("pooled connection");
+    let rows: Vec<i32> = conn
+        .with_recursive(
+            "nums",
+            &["n"],
+            RecursiveParts::new(
+                sql::<Integer>("SELECT 1"),
+                sql::<Integer>("SELECT n + 1 FROM nums WHERE n < 4"),
+                sql::<Integer>("SELECT n FROM nums"),
+            ),
+        )
+        .load(&mut conn)
+        .expect("load rows");
+    assert_eq!(rows, vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn sqlite_sync_chained_ctes() {
+    use diesel::RunQueryDsl;
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    let rows: Vec<i32> = conn
+        .with_cte(
+            "base",
+            &["n"],
+            CteParts::new(
+                sql::<Integer>("SELECT 1 UNION ALL SELECT 2"),
+                sql::<Integer>("SELECT m FROM doubled ORDER BY m"),
+            ),
+        )
+        .with("doubled", &["m"], sql::<Integer>("SELECT n * 2 FROM base"))
+        .load(&mut conn)
+        .expect("load rows");
+    assert_eq!(rows, vec![2, 4]);
+}
+
+#[test]
+fn sqlite_sync_plain_cte_feeds_recursive_cte() {
+    use diesel::RunQueryDsl;
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    let rows: Vec<i32> = conn
+        .with_cte(
+            "params",
+            &["lim"],
+            CteParts::new(
+                sql::<Integer>("SELECT 3"),
+                sql::<Integer>("SELECT n FROM nums ORDER BY n"),
+            ),
+        )
+        .with_recursive(
+            "nums",
+            &["n"],
+            RecursiveTerms::new(
+                sql::<Integer>("SELECT 1"),
+                sql::<Integer>("SELECT n + 1 FROM nums, params WHERE n < lim"),
+            ),
+        )
+        .load(&mut conn)
+        .expect("load rows");
+    assert_eq!(rows, vec![1, 2, 3]);
+}
+
+#[test]
+fn sqlite_materialization_hints_are_accepted() {
+    use diesel::RunQueryDsl;
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    let rows: Vec<i32> = conn
+        .with_cte(
+            "base",
+            &["n"],
+            CteParts::new(
+                sql::<Integer>("SELECT 1 UNION ALL SELECT 2"),
+                sql::<Integer>("SELECT n FROM nums ORDER BY n"),
+            )
+            .with_materialization(Materialization::Materialized),
+        )
+        .with_recursive(
+            "nums",
+            &["n"],
+            RecursiveTerms::new(
+                sql::<Integer>("SELECT n FROM base"),
+                sql::<Integer>("SELECT n + 2 FROM nums WHERE n < 3"),
+            )
+            .with_materialization(Materialization::NotMaterialized),
+        )
+        .load(&mut conn)
+        .expect("load rows");
+    assert_eq!(rows, vec![1, 2, 3, 4]);
+}
+
+diesel::table! {
+    /// Tree rows removed by the subtree deletion tests.
+    nodes (id) {
+        /// Node identifier.
+        id -> Integer,
+        /// Identifier of the parent node, `NULL` for roots.
+        parent_id -> Nullable<Integer>,
+    }
+}
+
+const NODES_SQL: &str = "CREATE TABLE nodes (id INTEGER PRIMARY KEY, parent_id INTEGER);
+     INSERT INTO nodes VALUES (1, NULL), (2, 1), (3, 2), (4, 1), (5, NULL);";
+
+#[test]
+fn sqlite_with_prefix_deletes_subtree() {
+    use diesel::{RunQueryDsl, connection::SimpleConnection};
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(NODES_SQL).expect("create nodes");
+    let deleted = conn
+        .with_recursive(
+            "subtree",
+            &["n"],
+            RecursiveParts::new(
+                sql::<Integer>("SELECT 2"),
+                sql::<Integer>("SELECT id FROM nodes JOIN subtree ON parent_id = n"),
+                diesel::delete(nodes::table.filter(sql::<Bool>("id IN (SELECT n FROM subtree)"))),
+            ),
+        )
+        .execute(&mut conn)
+        .expect("delete subtree");
+    let remaining: Vec<i32> = nodes::table
+        .select(nodes::id)
+        .order(nodes::id)
+        .load(&mut conn)
+        .expect("load remaining");
+    assert_eq!(deleted, 2);
+    assert_eq!(remaining, vec![1, 4, 5]);
+}
+
+/// Row of `nodes` loaded through its `Selectable` implementation.
+#[derive(Debug, PartialEq, Eq, diesel::Queryable, diesel::Selectable)]
+#[diesel(table_name = nodes)]
+struct Node {
+    id: i32,
+    parent_id: Option<i32>,
+}
+
+#[test]
+fn sqlite_hierarchy_walks_descendants_and_ancestors() {
+    use diesel::{RunQueryDsl, SelectableHelper, connection::SimpleConnection};
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(NODES_SQL).expect("create nodes");
+    let tree = Hierarchy::new(nodes::id, nodes::parent_id);
+    let below: Vec<((i32, Option<i32>), i32)> = tree
+        .descendants_of(1)
+        .load(&mut conn)
+        .expect("load descendants");
+    let above: Vec<(Node, i32)> = tree
+        .ancestors_of(3)
+        .select(Node::as_select())
+        .load(&mut conn)
+        .expect("load ancestors");
+    assert_eq!(
+        below,
+        vec![
+            ((1, None), 0),
+            ((2, Some(1)), 1),
+            ((4, Some(1)), 1),
+            ((3, Some(2)), 2)
+        ]
+    );
+    assert_eq!(
+        above,
+        vec![
+            (
+                Node {
+                    id: 3,
+                    parent_id: Some(2)
+                },
+                0
+            ),
+            (
+                Node {
+                    id: 2,
+                    parent_id: Some(1)
+                },
+                1
+            ),
+            (
+                Node {
+                    id: 1,
+                    parent_id: None
+                },
+                2
+            ),
+        ]
+    );
+}
+
+#[test]
+fn sqlite_hierarchy_max_depth_stops_parent_loops() {
+    use diesel::{RunQueryDsl, connection::SimpleConnection};
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(
+        "CREATE TABLE nodes (id INTEGER PRIMARY KEY, parent_id INTEGER);
+         INSERT INTO nodes VALUES (1, 3), (2, 1), (3, 2);",
+    )
+    .expect("create looping nodes");
+    let tree = Hierarchy::new(nodes::id, nodes::parent_id).with_max_depth(4);
+    let below: Vec<((i32, Option<i32>), i32)> = tree
+        .descendants_of(1)
+        .load(&mut conn)
+        .expect("load descendants");
+    let above: Vec<((i32, Option<i32>), i32)> = tree
+        .with_max_depth(2)
+        .ancestors_of(1)
+        .load(&mut conn)
+        .expect("load ancestors");
+    assert_eq!(
+        below,
+        vec![
+            ((1, Some(3)), 0),
+            ((2, Some(1)), 1),
+            ((3, Some(2)), 2),
+            ((1, Some(3)), 3),
+            ((2, Some(1)), 4)
+        ]
+    );
+    assert_eq!(
+        above,
+        vec![((1, Some(3)), 0), ((3, Some(2)), 1), ((2, Some(1)), 2)]
+    );
+}
+
+diesel_cte_ext::cte_table! {
+    /// Recursive CTE walking `nodes` from the roots.
+    tree (id) {
+        /// Node identifier.
+        id -> Integer,
+        /// Distance from the root.
+        depth -> Integer,
+    }
+}
+
+diesel::allow_tables_to_appear_in_same_query!(nodes, tree);
+
+#[test]
+fn sqlite_cte_table_drives_dsl_queries() {
+    use diesel::{
+        ExpressionMethods, IntoSql, JoinOnDsl, NullableExpressionMethods, RunQueryDsl,
+        connection::SimpleConnection,
+    };
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(NODES_SQL).expect("create nodes");
+    let rows: Vec<(i32, i32)> = conn
+        .with_recursive(
+            tree::table::NAME,
+            tree::table::columns(),
+            RecursiveParts::new(
+                nodes::table
+                    .filter(nodes::parent_id.is_null())
+                    .select((nodes::id, 0.into_sql::<Integer>())),
+                nodes::table
+                    .inner_join(tree::table.on(nodes::parent_id.eq(tree::id.nullable())))
+                    .select((nodes::id, tree::depth + 1)),
+                tree::table
+                    .filter(tree::depth.lt(2))
+                    .order((tree::depth, tree::id))
+                    .select((tree::id, tree::depth)),
+            ),
+        )
+        .load(&mut conn)
+        .expect("load rows");
+    assert_eq!(rows, vec![(1, 0), (5, 0), (2, 1), (4, 1)]);
+}
+
+#[test]
+fn sqlite_dsl_methods_refine_cte_output() {
+    use diesel::{
+        ExpressionMethods, IntoSql, JoinOnDsl, NullableExpressionMethods, RunQueryDsl,
+        connection::SimpleConnection,
+    };
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(NODES_SQL).expect("create nodes");
+    let rows: Vec<i32> = conn
+        .with_recursive(
+            tree::table::NAME,
+            tree::table::columns(),
+            RecursiveParts::new(
+                nodes::table
+                    .filter(nodes::parent_id.is_null())
+                    .select((nodes::id, 0.into_sql::<Integer>())),
+                nodes::table
+                    .inner_join(tree::table.on(nodes::parent_id.eq(tree::id.nullable())))
+                    .select((nodes::id, tree::depth + 1)),
+                tree::table,
+            ),
+        )
+        .filter(tree::depth.gt(0))
+        .order_by(tree::depth.desc())
+        .then_order_by(tree::id)
+        .limit(2)
+        .offset(1)
+        .select(tree::id)
+        .load(&mut conn)
+        .expect("load rows");
+    assert_eq!(rows, vec![2, 4]);
+}
+
+#[test]
+fn sqlite_statement_cache_distinguishes_runtime_column_names() {
+    use diesel::{ExpressionMethods, IntoSql, RunQueryDsl, connection::SimpleConnection};
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(NODES_SQL).expect("create nodes");
+    let mut load_ids = |columns: &'static [&'static str]| -> Vec<i32> {
+        conn.with_cte(
+            tree::table::NAME,
+            columns,
+            CteParts::new(
+                nodes::table
+                    .filter(nodes::id.eq(2))
+                    .select((nodes::id, 7.into_sql::<Integer>())),
+                tree::table.select(tree::id),
+            ),
+        )
+        .load(&mut conn)
+        .expect("load rows")
+    };
+    assert_eq!(load_ids(&["id", "depth"]), vec![2]);
+    assert_eq!(load_ids(&["depth", "id"]), vec![7]);
+}
+
+#[test]
+fn sqlite_statement_cache_skips_typed_columns_under_other_names() {
+    use diesel::{ExpressionMethods, IntoSql, RunQueryDsl, connection::SimpleConnection};
+    use diesel_cte_ext::Columns;
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(NODES_SQL).expect("create nodes");
+    let mut load_ids = |columns: Columns<(tree::id, tree::depth)>| -> Vec<i32> {
+        conn.with_cte(
+            tree::table::NAME,
+            columns,
+            CteParts::new(
+                nodes::table
+                    .filter(nodes::id.eq(2))
+                    .select((nodes::id, 7.into_sql::<Integer>())),
+                tree::table.select(tree::id),
+            ),
+        )
+        .load(&mut conn)
+        .expect("load rows")
+    };
+    assert_eq!(load_ids(tree::table::columns()), vec![2]);
+    assert_eq!(load_ids(Columns::raw(&["depth", "id"])), vec![7]);
+}
+
+#[test]
+fn sqlite_checked_builder_runs_typed_dsl_queries() {
+    use diesel::{
+        ExpressionMethods, IntoSql, JoinOnDsl, NullableExpressionMethods, RunQueryDsl,
+        connection::SimpleConnection,
+    };
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(NODES_SQL).expect("create nodes");
+    let rows: Vec<i32> = conn
+        .with_recursive_checked(
+            tree::table::NAME,
+            tree::table::columns(),
+            RecursiveParts::new(
+                nodes::table
+                    .filter(nodes::id.eq(2))
+                    .select((nodes::id, 0.into_sql::<Integer>())),
+                nodes::table
+                    .inner_join(tree::table.on(nodes::parent_id.eq(tree::id.nullable())))
+                    .select((nodes::id, tree::depth + 1)),
+                tree::table.order(tree::id).select(tree::id),
+            ),
+        )
+        .load(&mut conn)
+        .expect("load rows");
+    assert_eq!(rows, vec![2, 3]);
+}
+
+#[test]
+fn sqlite_runtime_names_from_configuration() {
+    use diesel::RunQueryDsl;
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    let cte_name = String::from("report_nums");
+    let column = String::from("value");
+    let body = format!("SELECT {column} FROM {cte_name}");
+    let rows: Vec<i32> = conn
+        .with_recursive(
+            cte_name.clone(),
+            CteColumns::owned([column.clone()]),
+            RecursiveParts::new(
+                sql::<Integer>("SELECT 1"),
+                sql::<Integer>(&format!(
+                    "SELECT {column} + 1 FROM {cte_name} WHERE {column} < 3"
+                )),
+                sql::<Integer>(&body),
+            ),
+        )
+        .load(&mut conn)
+        .expect("load rows");
+    assert_eq!(rows, vec![1, 2, 3]);
+}
+
+#[cfg(feature = "macros")]
+#[test]
+fn sqlite_cte_macro_builds_runnable_queries() {
+    use diesel::{RunQueryDsl, connection::SimpleConnection, sql_types::Integer};
+    use diesel_cte_ext::cte;
+
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(NODES_SQL).expect("create nodes");
+    let limit = 3;
+    let depths: Vec<(i32, i32)> = cte!(recursive tree(id: Integer, depth: Integer)
+        = { sql::<(Integer, Integer)>("SELECT id, 0 FROM nodes WHERE parent_id IS NULL") }
+        union all {
+            sql::<(Integer, Integer)>("SELECT nodes.id, depth + 1 FROM nodes JOIN tree ON parent_id = tree.id WHERE depth < ")
+                .bind::<Integer, _>(limit)
+        };
+        select { sql::<(Integer, Integer)>("SELECT id, depth FROM tree ORDER BY id") })
+    .load(&mut conn)
+    .expect("load recursive rows");
+    assert_eq!(depths, vec![(1, 0), (2, 1), (3, 2), (4, 1), (5, 0)]);
+
+    let roots: Vec<i32> = cte!(roots(id) materialized
+        = { sql::<Integer>("SELECT id FROM nodes"), sql::<Integer>(" WHERE parent_id IS NULL") };
+        select { sql::<Integer>("SELECT id FROM roots ORDER BY id") })
+    .load(&mut conn)
+    .expect("load plain rows");
+    assert_eq!(roots, vec![1, 5]);
+}
+
+#[cfg(feature = "macros")]
+#[test]
+fn sqlite_cte_macro_resolves_a_reexported_crate() {
+    use diesel::{RunQueryDsl, sql_types::Integer};
+    use diesel_cte_ext::cte;
+
+    mod db {
+        pub use diesel_cte_ext as cte_ext;
+    }
+
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    let rows: Vec<i32> = cte!(crate = db::cte_ext;
+        recursive nums(n: Integer)
+        = { sql::<Integer>("SELECT 1") }
+        union { sql::<Integer>("SELECT n + 1 FROM nums WHERE n < 3") };
+        select { sql::<Integer>("SELECT n FROM nums ORDER BY n") })
+    .load(&mut conn)
+    .expect("load rows");
+    assert_eq!(rows, vec![1, 2, 3]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn sqlite_spec_from_toml_runs_with_bound_values() {
+    use diesel::{RunQueryDsl, connection::SimpleConnection, sql_types::BigInt};
+    use diesel_cte_ext::CteSpec;
+
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(NODES_SQL).expect("create nodes");
+    let spec: CteSpec = toml::from_str(
+        r#"
+        name = "subtree"
+        columns = ["n"]
+        limit = 2
+
+        [seed]
+        sql = "SELECT id FROM nodes WHERE id = ?"
+        binds = [1]
+
+        [step]
+        sql = "SELECT id FROM nodes JOIN subtree ON parent_id = n WHERE id <> ?"
+        binds = [2]
+
+        [body]
+        sql = "SELECT n FROM subtree ORDER BY n DESC"
+        "#,
+    )
+    .expect("parse spec");
+    let rows: Vec<i64> = spec
+        .build::<diesel::sqlite::Sqlite, BigInt>()
+        .expect("valid spec")
+        .load(&mut conn)
+        .expect("load rows");
+    assert_eq!(rows, vec![4, 1]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn sqlite_spec_limit_wraps_bodies_with_their_own_limit() {
+    use diesel::{RunQueryDsl, connection::SimpleConnection, sql_types::BigInt};
+    use diesel_cte_ext::CteSpec;
+
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(NODES_SQL).expect("create nodes");
+    let spec: CteSpec = toml::from_str(
+        r#"
+        name = "subtree"
+        columns = ["n"]
+        limit = 2
+
+        [seed]
+        sql = "SELECT 1"
+
+        [step]
+        sql = "SELECT id FROM nodes JOIN subtree ON parent_id = n"
+
+        [body]
+        sql = "SELECT n FROM subtree ORDER BY n DESC LIMIT 3 OFFSET 1 -- skip the largest"
+        "#,
+    )
+    .expect("parse spec");
+    let rows: Vec<i64> = spec
+        .build::<diesel::sqlite::Sqlite, BigInt>()
+        .expect("valid spec")
+        .load(&mut conn)
+        .expect("load rows");
+    assert_eq!(rows, vec![3, 2]);
+}
+
+fn subtree_ids(root: Option<i32>) -> BoxedCteQuery<'static, diesel::sqlite::Sqlite, Integer> {
+    let step = sql::<Integer>("SELECT id FROM nodes JOIN subtree ON parent_id = n");
+    let body = sql::<Integer>("SELECT n FROM subtree ORDER BY n");
+    let parts = match root {
+        Some(id) => {
+            RecursiveParts::new(sql::<Integer>("SELECT ").bind::<Integer, _>(id), step, body)
+                .into_boxed()
+        }
+        None => RecursiveParts::new(
+            sql::<Integer>("SELECT id FROM nodes WHERE parent_id IS NULL"),
+            step,
+            body,
+        )
+        .into_boxed(),
+    };
+    diesel_cte_ext::builders::with_recursive("subtree", &["n"], parts).into_boxed()
+}
+
+#[test]
+fn sqlite_boxed_queries_load_from_any_branch() {
+    use diesel::{RunQueryDsl, connection::SimpleConnection};
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(NODES_SQL).expect("create nodes");
+    let everything: Vec<i32> = subtree_ids(None).load(&mut conn).expect("load all");
+    let below_two: Vec<i32> = subtree_ids(Some(2)).load(&mut conn).expect("load subtree");
+    assert_eq!(everything, vec![1, 2, 3, 4, 5]);
+    assert_eq!(below_two, vec![2, 3]);
+}
+
+#[test]
+fn sqlite_cte_queries_filter_as_subqueries() {
+    use diesel::{
+        BoolExpressionMethods, ExpressionMethods, NullableExpressionMethods, RunQueryDsl,
+        connection::SimpleConnection,
+    };
+    use diesel_cte_ext::subquery::exists;
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(NODES_SQL).expect("create nodes");
+    let children: Vec<i32> = nodes::table
+        .filter(nodes::parent_id.eq_any(subtree_ids(Some(2))))
+        .select(nodes::id)
+        .load(&mut conn)
+        .expect("load children");
+    let outside: Vec<i32> = nodes::table
+        .filter(nodes::id.ne_all(subtree_ids(Some(1))))
+        .select(nodes::id)
+        .load(&mut conn)
+        .expect("load outside");
+    let first: Vec<i32> = nodes::table
+        .filter(
+            exists(subtree_ids(Some(2)))
+                .and(nodes::id.nullable().eq(subtree_ids(None).single_value())),
+        )
+        .select(nodes::id)
+        .load(&mut conn)
+        .expect("load first");
+    assert_eq!(children, vec![3]);
+    assert_eq!(outside, vec![5]);
+    assert_eq!(first, vec![1]);
+}
+
+#[test]
+fn sqlite_distinct_union_terminates_on_cycles() {
+    use diesel::{RunQueryDsl, connection::SimpleConnection};
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(
+        "CREATE TABLE edges (src INTEGER NOT NULL, dst INTEGER NOT NULL);
+         INSERT INTO edges VALUES (1, 2), (2, 3), (3, 1);",
+    )
+    .expect("create cyclic edges");
+    let rows: Vec<i32> = conn
+        .with_recursive(
+            "reach",
+            &["node"],
+            RecursiveParts::new(
+                sql::<Integer>("SELECT 1"),
+                sql::<Integer>("SELECT dst FROM edges JOIN reach ON src = node"),
+                sql::<Integer>("SELECT node FROM reach ORDER BY node"),
+            )
+            .with_union(UnionKind::Distinct),
+        )
+        .load(&mut conn)
+        .expect("load rows");
+    assert_eq!(rows, vec![1, 2, 3]);
+}
+
+fn traverse_tree(search: SearchOrder) -> diesel::QueryResult<Vec<i32>> {
+    use diesel::{RunQueryDsl, connection::SimpleConnection};
+    let mut conn = SqliteConnection::establish(":memory:")
+        .map_err(|err| diesel::result::Error::QueryBuilderError(err.into()))?;
+    conn.batch_execute(
+        "CREATE TABLE nodes (id INTEGER PRIMARY KEY, parent_id INTEGER);
+         INSERT INTO nodes VALUES (1, NULL), (2, 1), (3, 1), (4, 2), (5, 3), (6, 2);",
+    )?;
+    conn.with_recursive(
+        "tree",
+        &["id"],
+        RecursiveParts::new(
+            seed_query!(
+                sql::<Integer>("SELECT n.id"),
+                search.seed_key("n", "id"),
+                sql::<Integer>(" FROM nodes n WHERE n.parent_id IS NULL"),
+            ),
+            step_query!(
+                sql::<Integer>("SELECT n.id"),
+                search.step_key("n", "id", "tree"),
+                sql::<Integer>(" FROM nodes n JOIN tree ON n.parent_id = tree.id"),
+            ),
+            sql::<Integer>("SELECT id FROM tree ORDER BY ord"),
+        )
+        .with_search(search),
+    )
+    .load(&mut conn)
+}
+
+#[test]
+fn sqlite_search_depth_first_orders_rows() {
+    let rows = traverse_tree(SearchOrder::depth_first("id", "ord")).expect("load rows");
+    assert_eq!(rows, vec![1, 2, 4, 6, 3, 5]);
+}
+
+#[test]
+fn sqlite_search_breadth_first_orders_rows() {
+    let rows = traverse_tree(SearchOrder::breadth_first("id", "ord")).expect("load rows");
+    assert_eq!(rows, vec![1, 2, 3, 4, 5, 6]);
+}
+
+#[test]
+fn sqlite_cycle_check_marks_and_stops_cycles() {
+    use diesel::{
+        RunQueryDsl,
+        connection::SimpleConnection,
+        sql_types::{Bool, Integer as Int},
+    };
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(
+        "CREATE TABLE edges (src INTEGER NOT NULL, dst INTEGER NOT NULL);
+         INSERT INTO edges VALUES (1, 2), (2, 3), (3, 1), (3, 4);",
+    )
+    .expect("create cyclic edges");
+    let check = CycleCheck::new("id", "is_cycle", "path");
+    let rows: Vec<(i32, bool)> = conn
+        .with_recursive(
+            "walk",
+            &["id"],
+            RecursiveParts::new(
+                seed_query!(
+                    sql::<Int>("SELECT s.id"),
+                    check.seed_key("s", "id"),
+                    sql::<Int>(" FROM (SELECT 1 AS id) s"),
+                ),
+                step_query!(
+                    sql::<Int>("SELECT e.dst"),
+                    check.step_key("e", "dst", "walk"),
+                    sql::<Int>(" FROM edges e JOIN walk ON e.src = walk.id WHERE "),
+                    check.step_guard("walk"),
+                ),
+                sql::<(Int, Bool)>("SELECT id, is_cycle FROM walk ORDER BY id, is_cycle"),
+            )
+            .with_cycle(check),
+        )
+        .load(&mut conn)
+        .expect("load rows");
+    assert_eq!(
+        rows,
+        vec![(1, false), (1, true), (2, false), (3, false), (4, false)]
+    );
+}
+
+#[test]
+fn sqlite_depth_limit_counts_levels_and_stops() {
+    use diesel::{RunQueryDsl, connection::SimpleConnection};
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(
+        "CREATE TABLE nodes (id INTEGER PRIMARY KEY, parent_id INTEGER);
+         INSERT INTO nodes VALUES (1, NULL), (2, 1), (3, 1), (4, 2), (5, 4);",
+    )
+    .expect("create nodes");
+    let depth = DepthLimit::new("depth", 2);
+    let rows: Vec<(i32, i32)> = conn
+        .with_recursive(
+            "tree",
+            &["id"],
+            RecursiveParts::new(
+                sql::<Integer>("SELECT id FROM nodes WHERE parent_id IS NULL"),
+                DepthTerm::new(
+                    sql::<Integer>("n.id"),
+                    sql::<Integer>("nodes n JOIN tree ON n.parent_id = tree.id"),
+                ),
+                sql::<(Integer, Integer)>("SELECT id, depth FROM tree ORDER BY id"),
+            ),
+        )
+        .with_depth(depth)
+        .load(&mut conn)
+        .expect("load rows");
+    assert_eq!(rows, vec![(1, 0), (2, 1), (3, 1), (4, 2)]);
+}
+
+#[test]
+fn sqlite_depth_limit_extends_dsl_steps() {
+    use diesel::{
+        ExpressionMethods, JoinOnDsl, NullableExpressionMethods, RunQueryDsl,
+        connection::SimpleConnection,
+    };
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(NODES_SQL).expect("create nodes");
+    let rows: Vec<(i32, i32)> = conn
+        .with_recursive(
+            tree::table::NAME,
+            &["id"],
+            RecursiveParts::new(
+                sql::<Integer>("SELECT id FROM nodes WHERE parent_id IS NULL"),
+                nodes::table
+                    .inner_join(tree::table.on(nodes::parent_id.eq(tree::id.nullable())))
+                    .select(nodes::id),
+                tree::table.order(tree::id).select((tree::id, tree::depth)),
+            ),
+        )
+        .with_depth(DepthLimit::new("depth", 1))
+        .load(&mut conn)
+        .expect("load rows");
+    assert_eq!(rows, vec![(1, 0), (2, 1), (4, 1), (5, 0)]);
+}
+
+diesel::table! {
+    /// Directed edges walked by the graph traversal tests.
+    edges (src, dst) {
+        /// Node the edge leaves.
+        src -> Integer,
+        /// Node the edge enters.
+        dst -> Integer,
+    }
+}
+
+#[test]
+fn sqlite_graph_reaches_nodes_through_cycles() {
+    use diesel::{RunQueryDsl, connection::SimpleConnection};
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(
+        "CREATE TABLE edges (src INTEGER NOT NULL, dst INTEGER NOT NULL);
+         INSERT INTO edges VALUES (1, 2), (2, 3), (3, 1), (3, 4), (1, 4), (5, 1);",
+    )
+    .expect("create cyclic edges");
+    let rows: Vec<(i32, i32)> = Graph::new(edges::src, edges::dst)
+        .reachable_from(1)
+        .load(&mut conn)
+        .expect("load reachable nodes");
+    assert_eq!(rows, vec![(1, 0), (2, 1), (4, 1), (3, 2)]);
+}
+
+/// Every ordered pair of ten distinct nodes, a graph with millions of simple
+/// paths.
+const COMPLETE_GRAPH_SQL: &str = "CREATE TABLE edges (src INTEGER NOT NULL, dst INTEGER NOT NULL);
+     WITH RECURSIVE n (i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 10)
+     INSERT INTO edges SELECT a.i, b.i FROM n a, n b WHERE a.i <> b.i;";
+
+#[test]
+fn sqlite_graph_bounds_dense_traversals() {
+    use diesel::{RunQueryDsl, connection::SimpleConnection};
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(COMPLETE_GRAPH_SQL)
+        .expect("create complete graph");
+    let graph = Graph::new(edges::src, edges::dst);
+    let nodes: Vec<i32> = graph
+        .nodes_reachable_from(1)
+        .load(&mut conn)
+        .expect("load reachable nodes");
+    let near: Vec<(i32, i32)> = graph
+        .with_max_hops(1)
+        .reachable_from(1)
+        .load(&mut conn)
+        .expect("load nodes one hop away");
+    assert_eq!(nodes, (1..=10).collect::<Vec<_>>());
+    assert_eq!(
+        near,
+        std::iter::once((1, 0))
+            .chain((2..=10).map(|node| (node, 1)))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn sqlite_undirected_graph_follows_edges_both_ways() {
+    use diesel::{RunQueryDsl, connection::SimpleConnection};
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(
+        "CREATE TABLE edges (src INTEGER NOT NULL, dst INTEGER NOT NULL);
+         INSERT INTO edges VALUES (1, 2), (2, 3), (3, 1), (4, 3), (5, 6);",
+    )
+    .expect("create related items");
+    let rows: Vec<(i32, i32)> = Graph::new(edges::src, edges::dst)
+        .with_orientation(Orientation::Undirected)
+        .reachable_from(1)
+        .load(&mut conn)
+        .expect("load related items");
+    assert_eq!(rows, vec![(1, 0), (2, 1), (3, 1), (4, 2)]);
+}
+
+diesel::table! {
+    /// Labelled tree rows walked by the breadcrumb tests.
+    categories (id) {
+        /// Category identifier.
+        id -> Integer,
+        /// Identifier of the parent category, `NULL` for roots.
+        parent_id -> Nullable<Integer>,
+        /// Label shown in breadcrumbs.
+        name -> Text,
+    }
+}
+
+#[test]
+fn sqlite_breadcrumbs_join_labels_and_keys_from_the_root() {
+    use diesel::{RunQueryDsl, connection::SimpleConnection};
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(
+        "CREATE TABLE categories (id INTEGER PRIMARY KEY, parent_id INTEGER, name TEXT NOT NULL);
+         INSERT INTO categories VALUES
+             (1, NULL, 'Root'), (2, 1, 'Electronics'), (3, 2, 'Phones'),
+             (4, 1, 'Books'), (5, NULL, 'Archive');",
+    )
+    .expect("create categories");
+    let crumbs =
+        Hierarchy::new(categories::id, categories::parent_id).breadcrumbs(categories::name);
+    let every: Vec<(i32, String, String)> = crumbs.all().load(&mut conn).expect("load breadcrumbs");
+    let phones: Vec<(i32, String, String)> = crumbs
+        .with_separator(" / ")
+        .of(3)
+        .load(&mut conn)
+        .expect("load breadcrumb");
+    assert_eq!(
+        every,
+        vec![
+            (1, "Root".to_owned(), "1".to_owned()),
+            (2, "Root > Electronics".to_owned(), "1/2".to_owned()),
+            (
+                3,
+                "Root > Electronics > Phones".to_owned(),
+                "1/2/3".to_owned()
+            ),
+            (4, "Root > Books".to_owned(), "1/4".to_owned()),
+            (5, "Archive".to_owned(), "5".to_owned()),
+        ]
+    );
+    assert_eq!(
+        phones,
+        vec![(
+            3,
+            "Root / Electronics / Phones".to_owned(),
+            "1/2/3".to_owned()
+        )]
+    );
+}
+
+#[test]
+fn sqlite_breadcrumbs_stop_at_the_maximum_depth() {
+    use diesel::{RunQueryDsl, connection::SimpleConnection};
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(
+        "CREATE TABLE categories (id INTEGER PRIMARY KEY, parent_id INTEGER, name TEXT NOT NULL);
+         INSERT INTO categories VALUES
+             (1, NULL, 'Root'), (2, 1, 'Electronics'), (3, 2, 'Phones'), (4, 1, 'Books');",
+    )
+    .expect("create categories");
+    let crumbs = Hierarchy::new(categories::id, categories::parent_id)
+        .with_max_depth(1)
+        .breadcrumbs(categories::name);
+    let every: Vec<(i32, String, String)> = crumbs.all().load(&mut conn).expect("load breadcrumbs");
+    let books: Vec<(i32, String, String)> = crumbs.of(4).load(&mut conn).expect("load books");
+    let phones: Vec<(i32, String, String)> = crumbs.of(3).load(&mut conn).expect("load phones");
+    assert_eq!(
+        every,
+        vec![
+            (1, "Root".to_owned(), "1".to_owned()),
+            (2, "Root > Electronics".to_owned(), "1/2".to_owned()),
+            (4, "Root > Books".to_owned(), "1/4".to_owned()),
+        ]
+    );
+    assert_eq!(
+        books,
+        vec![(4, "Root > Books".to_owned(), "1/4".to_owned())]
+    );
+    assert!(phones.is_empty(), "unexpected breadcrumb {phones:?}");
+}
+
+#[test]
+fn sqlite_breadcrumbs_stop_at_parent_loops() {
+    use diesel::{RunQueryDsl, connection::SimpleConnection};
+    let mut conn = SqliteConnection::establish(":memory:").expect("in-memory sqlite");
+    conn.batch_execute(
+        "CREATE TABLE categories (id INTEGER PRIMARY KEY, parent_id INTEGER, name TEXT NOT NULL);
+         INSERT INTO categories VALUES
+             (1, NULL, 'Root'), (2, 1, 'Phones'), (3, 4, 'Loop'), (4, 3, 'Pool'), (5, 3, 'Stray');",
+    )
+    .expect("create looping categories");
+    let crumbs =
+        Hierarchy::new(categories::id, categories::parent_id).breadcrumbs(categories::name);
+    let stray: Vec<(i32, String, String)> =
+        crumbs.of(5).load(&mut conn).expect("load stray breadcrumb");
+    let phones: Vec<(i32, String, String)> = crumbs
+        .of(2)
+        .load(&mut conn)
+        .expect("load rooted breadcrumb");
+    assert!(stray.is_empty(), "unexpected breadcrumb {stray:?}");
+    assert_eq!(
+        phones,
+        vec![(2, "Root > Phones".to_owned(), "1/2".to_owned())]
+    );
+}
+
 #[cfg(feature = "async")]
 mod async_sqlite {
     use super::*;
@@ -31,7 +973,7 @@ mod async_sqlite {
         AsyncConnection, RunQueryDsl as AsyncRunQueryDsl,
         sync_connection_wrapper::SyncConnectionWrapper,
     };
-    use diesel_cte_ext::RecursiveCTEExt;
+    use diesel_cte_ext::AsyncRecursiveCTEExt;
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn sqlite_async_recursive_sequence() {
@@ -53,4 +995,91 @@ mod async_sqlite {
             .expect("load rows");
         assert_eq!(rows, vec![1, 2, 3, 4]);
     }
+
+    #[cfg(feature = "deadpool")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn sqlite_deadpool_object_runs_recursive_ctes() {
+        use diesel_async::pooled_connection::{AsyncDieselConnectionManager, deadpool::Pool};
+
+        let manager = AsyncDieselConnectionManager::<SyncConnectionWrapper<SqliteConnection>>::new(
+            ":memory:",
+        );
+        let pool = Pool::builder(manager)
+            .max_size(1)
+            .build()
+            .expect("sqlite pool");
+        let mut conn = pool.get().await.expect("pooled connection");
+        let rows: Vec<i32> = conn
+            .with_recursive(
+                "nums",
+                &["n"],
+                RecursiveParts::new(
+                    sql::<Integer>("SELECT 1"),
+                    sql::<Integer>("SELECT n + 1 FROM nums WHERE n < 4"),
+                    sql::<Integer>("SELECT n FROM nums"),
+                ),
+            )
+            .load(&mut conn)
+            .await
+            .expect("load rows");
+        assert_eq!(rows, vec![1, 2, 3, 4]);
+    }
+
+    #[cfg(feature = "bb8")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn sqlite_bb8_pooled_connection_runs_recursive_ctes() {
+        use diesel_async::pooled_connection::{AsyncDieselConnectionManager, bb8::Pool};
+
+        let manager = AsyncDieselConnectionManager::<SyncConnectionWrapper<SqliteConnection>>::new(
+            ":memory:",
+        );
+        let pool = Pool::builder()
+            .max_size(1)
+            .build(manager)
+            .await
+            .expect("sqlite pool");
+        let mut conn = pool.get().await.expect("pooled connection");
+        let rows: Vec<i32> = conn
+            .with_recursive(
+                "nums",
+                &["n"],
+                RecursiveParts::new(
+                    sql::<Integer>("SELECT 1"),
+                    sql::<Integer>("SELECT n + 1 FROM nums WHERE n < 4"),
+                    sql::<Integer>("SELECT n FROM nums"),
+                ),
+            )
+            .load(&mut conn)
+            .await
+            .expect("load rows");
+        assert_eq!(rows, vec![1, 2, 3, 4]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn sqlite_async_with_prefix_updates_subtree() {
+        use diesel::ExpressionMethods;
+        use diesel_async::SimpleAsyncConnection;
+
+        let mut conn = SyncConnectionWrapper::<SqliteConnection>::establish(":memory:")
+            .await
+            .expect("async sqlite wrapper");
+        conn.batch_execute(NODES_SQL).await.expect("create nodes");
+        let updated = conn
+            .with_recursive(
+                "subtree",
+                &["n"],
+                RecursiveParts::new(
+                    sql::<Integer>("SELECT 1"),
+                    sql::<Integer>("SELECT id FROM nodes JOIN subtree ON parent_id = n"),
+                    diesel::update(
+                        nodes::table.filter(sql::<Bool>("id IN (SELECT n FROM subtree)")),
+                    )
+                    .set(nodes::parent_id.eq(None::<i32>)),
+                ),
+            )
+            .execute(&mut conn)
+            .await
+            .expect("update subtree");
+        assert_eq!(updated, 4);
+    }
 }